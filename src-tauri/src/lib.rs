@@ -3,15 +3,33 @@ use tauri_plugin_dialog::DialogExt;
 use tauri::Emitter;
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use serde::{Deserialize, Serialize};
 use base64::{Engine as _, engine::general_purpose};
 use std::path::PathBuf;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
 
 // Global state to track recording processes
 lazy_static::lazy_static! {
     static ref RECORDING_PROCESS: Arc<Mutex<Option<std::process::Child>>> = Arc::new(Mutex::new(None));
+    // Temp/final paths of the screen recording in progress
+    static ref RECORDING_OUTPUT: Arc<Mutex<Option<PendingRecording>>> = Arc::new(Mutex::new(None));
     static ref CAMERA_RECORDING_PROCESS: Arc<Mutex<Option<std::process::Child>>> = Arc::new(Mutex::new(None));
+    static ref CAMERA_RECORDING_OUTPUT: Arc<Mutex<Option<PendingRecording>>> = Arc::new(Mutex::new(None));
     static ref SCREEN_PREVIEW_PROCESS: Arc<Mutex<Option<std::process::Child>>> = Arc::new(Mutex::new(None));
+    static ref MIC_MONITOR_PROCESS: Arc<Mutex<Option<std::process::Child>>> = Arc::new(Mutex::new(None));
+    // Stop flag for the device hotplug poller (None when not monitoring)
+    static ref DEVICE_MONITOR: Arc<Mutex<Option<Arc<AtomicBool>>>> = Arc::new(Mutex::new(None));
+    // Exports currently in progress, keyed by job id
+    static ref EXPORT_JOBS: Arc<Mutex<HashMap<String, ExportJobStatus>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Whether ffprobe can be run at all (minimal FFmpeg installs ship only the ffmpeg binary);
+    // None until first checked
+    static ref FFPROBE_AVAILABLE: Mutex<Option<bool>> = Mutex::new(None);
+    // Hardware decoders this FFmpeg build supports (from `ffmpeg -hwaccels`); None until first checked
+    static ref HWACCELS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+    // Where auto-named recordings go (set_default_output_dir); None uses the user's videos folder
+    static ref DEFAULT_OUTPUT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
 }
 
 // Helper function to get the FFmpeg binary path
@@ -45,6 +63,107 @@ fn get_ffmpeg_path() -> PathBuf {
     }
 }
 
+#[derive(Debug, Serialize)]
+struct FfmpegProcessInfo {
+    pid: u32,
+    parent_pid: u32,
+    command: String,
+    orphaned: bool, // Its ClipForge parent is gone (e.g. left behind by a crash)
+}
+
+// Helper function to list FFmpeg processes started by ClipForge
+// A process counts as ours when it runs our bundled/downloaded FFmpeg binary, so a system
+// `ffmpeg` the user runs by hand is never listed. It's orphaned once its parent has exited
+// (or it was re-parented to init/launchd); FFmpeg run by any live ClipForge instance is left alone.
+fn list_own_ffmpeg_processes() -> Result<Vec<FfmpegProcessInfo>, String> {
+    // Every process is listed so parents can be looked up
+    let output = if cfg!(target_os = "windows") {
+        output_with_timeout(Command::new("powershell").args([
+            "-NoProfile",
+            "-Command",
+            "Get-CimInstance Win32_Process | ForEach-Object { \"{0} {1} {2}\" -f $_.ProcessId, $_.ParentProcessId, $_.CommandLine }",
+        ]))
+    } else {
+        output_with_timeout(Command::new("ps").args(["-axww", "-o", "pid=,ppid=,args="]))
+    }
+    .map_err(|e| format!("Failed to list processes: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Failed to list processes: {}", stderr.trim()));
+    }
+
+    let ffmpeg_path = get_ffmpeg_path();
+    // A bare "ffmpeg" (dev mode / PATH fallback) could be anyone's, so it doesn't prove ownership
+    let own_binary = ffmpeg_path.is_absolute().then(|| ffmpeg_path.to_string_lossy().to_string());
+    let runs_own_binary = |command: &str| {
+        own_binary.as_deref().is_some_and(|binary| command.trim_start_matches('"').starts_with(binary))
+    };
+
+    let all_processes: Vec<(u32, u32, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse::<u32>().ok()?;
+            let parent_pid = fields.next()?.parse::<u32>().ok()?;
+            Some((pid, parent_pid, fields.collect::<Vec<_>>().join(" ")))
+        })
+        .collect();
+    let running: std::collections::HashSet<u32> = all_processes.iter().map(|(pid, _, _)| *pid).collect();
+
+    let processes = all_processes
+        .into_iter()
+        .filter(|(_, _, command)| runs_own_binary(command))
+        .map(|(pid, parent_pid, command)| FfmpegProcessInfo {
+            pid,
+            parent_pid,
+            command,
+            orphaned: parent_pid <= 1 || !running.contains(&parent_pid),
+        })
+        .collect();
+
+    Ok(processes)
+}
+
+#[tauri::command]
+fn list_clipforge_ffmpeg_processes() -> Result<Vec<FfmpegProcessInfo>, String> {
+    let processes = list_own_ffmpeg_processes()?;
+    println!(
+        "[list_clipforge_ffmpeg_processes] {} FFmpeg processes ({} orphaned)",
+        processes.len(),
+        processes.iter().filter(|p| p.orphaned).count()
+    );
+    Ok(processes)
+}
+
+#[tauri::command]
+fn kill_orphaned_ffmpeg() -> Result<Vec<u32>, String> {
+    // Only orphans: FFmpeg with a live parent belongs to a running recording/export
+    let orphans: Vec<u32> = list_own_ffmpeg_processes()?
+        .into_iter()
+        .filter(|p| p.orphaned)
+        .map(|p| p.pid)
+        .collect();
+
+    let mut killed = Vec::new();
+    for pid in orphans {
+        let pid_str = pid.to_string();
+        let status = if cfg!(target_os = "windows") {
+            Command::new("taskkill").args(["/PID", &pid_str, "/F"]).stdout(Stdio::null()).stderr(Stdio::null()).status()
+        } else {
+            Command::new("kill").args(["-KILL", &pid_str]).stdout(Stdio::null()).stderr(Stdio::null()).status()
+        };
+        match status {
+            Ok(status) if status.success() => killed.push(pid),
+            Ok(status) => println!("[kill_orphaned_ffmpeg] Warning: failed to kill {} ({})", pid, status),
+            Err(e) => println!("[kill_orphaned_ffmpeg] Warning: failed to kill {}: {}", pid, e),
+        }
+    }
+
+    println!("[kill_orphaned_ffmpeg] Killed {} orphaned FFmpeg processes", killed.len());
+    Ok(killed)
+}
+
 // Helper function to get the FFprobe binary path
 fn get_ffprobe_path() -> PathBuf {
     if std::env::var("TAURI_DEV").is_ok() {
@@ -69,1154 +188,8338 @@ fn get_ffprobe_path() -> PathBuf {
     }
 }
 
-// Recording options structure
-#[derive(Debug, Deserialize)]
-struct RecordingOptions {
-    resolution: String, // "720p", "1080p", or "source"
-    #[serde(default)]
-    source_width: Option<i32>,
-    #[serde(default)]
-    source_height: Option<i32>,
-    #[serde(default)]
-    audio_device: Option<String>, // Optional audio device name (Windows only)
+// Helper function to list the hardware decode methods FFmpeg was built with
+// Output is a "Hardware acceleration methods:" header followed by one name per line
+fn detect_hwaccels() -> Vec<String> {
+    let ffmpeg_path = get_ffmpeg_path();
+    match output_with_timeout(Command::new(&ffmpeg_path).args(["-hide_banner", "-hwaccels"])) {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.ends_with(':'))
+            .map(|line| line.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct ScreenResolution {
-    width: i32,
-    height: i32,
-}
+// Helper function to turn an `hwaccel` export option into input arguments
+// "auto" picks the best available method for the platform; an unavailable method
+// falls back to software decode with a warning. Decoded frames are copied back to
+// system memory (no -hwaccel_output_format), so software filters keep working.
+fn resolve_hwaccel_args(requested: Option<&str>) -> Vec<String> {
+    let available = available_hwaccels();
+    let method = match requested {
+        None | Some("none") | Some("") => return Vec::new(),
+        Some("auto") => {
+            let preferred = ["cuda", "videotoolbox", "d3d11va", "qsv", "dxva2", "vaapi"];
+            match preferred.iter().find(|m| available.iter().any(|a| a == *m)) {
+                Some(method) => method.to_string(),
+                None => {
+                    println!("[hwaccel] No hardware decoder available, using software decode");
+                    return Vec::new();
+                }
+            }
+        }
+        Some(method) => {
+            if !available.iter().any(|a| a == method) {
+                println!("[hwaccel] Warning: {} is not available, using software decode", method);
+                return Vec::new();
+            }
+            method.to_string()
+        }
+    };
 
-#[derive(Debug, Serialize)]
-struct CameraCapabilities {
-    native_width: i32,
-    native_height: i32,
-    supported_resolutions: Vec<String>,
+    println!("[hwaccel] Using {} for decode", method);
+    vec!["-hwaccel".to_string(), method]
 }
 
-#[derive(Debug, Serialize)]
-struct AudioVideoDevices {
-    video_devices: Vec<String>,
-    audio_devices: Vec<String>,
+// Helper function to get the hardware decode methods (detected once per FFmpeg install)
+fn available_hwaccels() -> Vec<String> {
+    HWACCELS.lock().unwrap().get_or_insert_with(detect_hwaccels).clone()
 }
 
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+fn list_hwaccels() -> Vec<String> {
+    available_hwaccels()
 }
 
-#[derive(Debug, Deserialize)]
-struct ExportOptions {
-    resolution: Option<String>, // "720p", "1080p", or "source"
-    #[serde(default)]
-    source_width: Option<i32>,
-    #[serde(default)]
-    source_height: Option<i32>,
+// Helper function to check whether ffprobe is usable (checked once per FFmpeg install)
+fn ffprobe_available() -> bool {
+    *FFPROBE_AVAILABLE.lock().unwrap().get_or_insert_with(|| {
+        Command::new(get_ffprobe_path())
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    })
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct TrackExportData {
-    path: String,
-    position_x: i32,
-    position_y: i32,
-    volume: f64,      // 0.0 to 1.0
-    opacity: f64,     // 0.0 to 1.0
-    width: i32,
-    height: i32,
-    z_index: i32,
+// Stream info recovered from `ffmpeg -i` output when ffprobe is missing
+#[derive(Debug, Default)]
+struct FfmpegStreamInfo {
+    codec_type: String, // "video", "audio", "subtitle", ...
+    codec_name: String,
+    width: Option<i32>,
+    height: Option<i32>,
+    frame_rate: Option<f64>,
+    sample_rate: Option<i32>,
+    pix_fmt: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct CompositeExportOptions {
-    resolution: Option<String>,
-    #[serde(default)]
-    source_width: Option<i32>,
-    #[serde(default)]
-    source_height: Option<i32>,
+#[derive(Debug, Default)]
+struct FfmpegProbeInfo {
+    duration: Option<f64>,
+    streams: Vec<FfmpegStreamInfo>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ClipSegment {
-    path: String,
-    #[serde(rename = "clipStart")]
-    clip_start: f64,
-    #[serde(rename = "clipEnd")]
-    clip_end: f64,
-}
+// Helper function to parse the input summary FFmpeg prints to stderr, e.g.
+//   Duration: 00:01:02.50, start: 0.000000, bitrate: 5216 kb/s
+//   Stream #0:0(und): Video: h264 (High) (avc1 / 0x31637661), yuv420p, 1920x1080 [SAR 1:1 DAR 16:9], 30 fps, ...
+//   Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 192 kb/s
+// HDR sources carry color info next to the pixel format, e.g. "yuv420p10le(tv, bt2020nc/bt2020/smpte2084)"
+fn parse_ffmpeg_input_info(stderr: &str) -> FfmpegProbeInfo {
+    let mut info = FfmpegProbeInfo::default();
+
+    for line in stderr.lines() {
+        let line = line.trim();
+
+        if let Some(rest) = line.strip_prefix("Duration:") {
+            let timestamp = rest.split(',').next().unwrap_or("").trim();
+            let parts: Vec<f64> = timestamp.split(':').filter_map(|p| p.parse::<f64>().ok()).collect();
+            if parts.len() == 3 {
+                info.duration = Some(parts[0] * 3600.0 + parts[1] * 60.0 + parts[2]);
+            }
+            continue;
+        }
 
-#[derive(Debug, Deserialize, Clone)]
-struct PipTrackData {
-    path: String,
-    offset: f64,        // Start time in seconds
-    duration: f64,      // Duration in seconds
-    volume: f64,        // 0.0 to 1.0
-    position: String,   // "top-left", "top-right", "bottom-left", "bottom-right"
-    size_percent: f64,  // 25, 33, or 50
-}
+        if !line.starts_with("Stream #") {
+            continue;
+        }
 
-#[tauri::command]
-async fn trim_video(
-    input_path: String,
-    output_path: String,
-    start_time: f64,
-    end_time: f64,
-    export_options: Option<ExportOptions>,
-    _window: tauri::Window
-) -> Result<String, String> {
-    println!("[trim_video] Starting trim operation");
-    println!("[trim_video] Input: {}", input_path);
-    println!("[trim_video] Output: {}", output_path);
-    println!("[trim_video] Start time: {}", start_time);
-    println!("[trim_video] End time: {}", end_time);
+        // "Stream #0:0(und): Video: h264 ..." -> kind "Video", details "h264 ..."
+        let Some((_, description)) = line.split_once(": ") else { continue };
+        let Some((kind, details)) = description.split_once(": ") else { continue };
+
+        let mut stream = FfmpegStreamInfo {
+            codec_type: kind.trim().to_lowercase(),
+            codec_name: details
+                .split([' ', ','])
+                .next()
+                .unwrap_or("")
+                .to_string(),
+            ..Default::default()
+        };
+
+        let parts = split_top_level_commas(details);
+        if stream.codec_type == "video" {
+            if let Some(pixel_format) = parts.get(1) {
+                parse_ffmpeg_pixel_format(pixel_format, &mut stream);
+            }
+        }
 
-    // Format time as HH:MM:SS.mmm
-    fn format_time(seconds: f64) -> String {
-        let hours = (seconds / 3600.0).floor() as u32;
-        let minutes = ((seconds % 3600.0) / 60.0).floor() as u32;
-        let secs = seconds % 60.0;
-        format!("{:02}:{:02}:{:06.3}", hours, minutes, secs)
-    }
+        for part in parts {
+            let token = part.split_whitespace().next().unwrap_or("");
+            if let Some((w, h)) = token.split_once('x') {
+                if let (Ok(w), Ok(h)) = (w.parse::<i32>(), h.parse::<i32>()) {
+                    stream.width = Some(w);
+                    stream.height = Some(h);
+                }
+            } else if let Some(fps) = part.strip_suffix(" fps") {
+                stream.frame_rate = fps.trim().parse::<f64>().ok();
+            } else if let Some(rate) = part.strip_suffix(" Hz") {
+                stream.sample_rate = rate.trim().parse::<i32>().ok();
+            }
+        }
 
-    let start_str = format_time(start_time);
-    let duration = end_time - start_time;
-    println!("[trim_video] Start string: {}", start_str);
-    println!("[trim_video] Duration: {}", duration);
+        info.streams.push(stream);
+    }
 
-    // Parse export options
-    let opts = export_options.unwrap_or_else(|| ExportOptions {
-        resolution: Some("source".to_string()),
-        source_width: None,
-        source_height: None,
-    });
+    info
+}
 
-    // Determine resolution and bitrate for export
-    let (should_scale, scale_filter, bitrate) = match opts.resolution.as_deref() {
-        Some("720p") => {
-            println!("[trim_video] Exporting at 720p resolution");
-            (true, "scale=1280:720", "2500k")
-        }
-        Some("1080p") => {
-            println!("[trim_video] Exporting at 1080p resolution");
-            (true, "scale=1920:1080", "5000k")
+// Helper function to split on commas that aren't inside parentheses
+// "yuv420p(tv, bt709), 1920x1080" -> ["yuv420p(tv, bt709)", "1920x1080"]
+fn split_top_level_commas(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
         }
-        Some("source") | None => {
-            println!("[trim_video] Exporting at source resolution (no scaling)");
-            (false, "", "8000k")
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+// Helper function to read the pixel format and color tags from e.g. "yuv420p10le(tv, bt2020nc/bt2020/smpte2084, progressive)"
+// FFmpeg prints "space/primaries/transfer", or a single name when all three match
+fn parse_ffmpeg_pixel_format(part: &str, stream: &mut FfmpegStreamInfo) {
+    let (name, tags) = match part.split_once('(') {
+        Some((name, tags)) => (name, tags.trim_end_matches(')')),
+        None => (part, ""),
+    };
+    if name.is_empty() || name.contains(' ') {
+        return;
+    }
+    stream.pix_fmt = Some(name.to_string());
+
+    for tag in tags.split(',').map(|t| t.trim()) {
+        let values: Vec<&str> = tag.split('/').collect();
+        if values.len() == 3 {
+            stream.color_space = Some(values[0].to_string());
+            stream.color_primaries = Some(values[1].to_string());
+            stream.color_transfer = Some(values[2].to_string());
+        } else if !tag.is_empty()
+            && !tag.contains(' ')
+            && !matches!(tag, "tv" | "pc" | "progressive" | "unknown")
+            && stream.color_space.is_none()
+        {
+            stream.color_space = Some(tag.to_string());
+            stream.color_primaries = Some(tag.to_string());
+            stream.color_transfer = Some(tag.to_string());
         }
-        Some(res) => {
-            return Err(format!("Invalid resolution: {}", res));
+    }
+}
+
+#[tauri::command]
+fn set_default_output_dir(path: String) -> Result<(), String> {
+    let dir = PathBuf::from(&path);
+    if !dir.is_dir() {
+        return Err(format!("Output directory not found: {}", path));
+    }
+    println!("[set_default_output_dir] Auto-named files now go to {}", path);
+    *DEFAULT_OUTPUT_DIR.lock().unwrap() = Some(dir);
+    Ok(())
+}
+
+// Helper function to build a timestamped output path, e.g. "clipforge_screen_2024-06-01_14-30-00.mp4",
+// in the configured default directory (or ~/Movies on macOS, ~/Videos elsewhere, or the temp dir)
+// Timestamps are UTC since std has no local time zone support.
+fn default_output_path(kind: &str, extension: &str) -> Result<String, String> {
+    let dir = match DEFAULT_OUTPUT_DIR.lock().unwrap().clone() {
+        Some(dir) => dir,
+        None => {
+            let home = std::env::var_os(if cfg!(target_os = "windows") { "USERPROFILE" } else { "HOME" });
+            let videos = home.map(|home| PathBuf::from(home).join(if cfg!(target_os = "macos") { "Movies" } else { "Videos" }));
+            videos.filter(|dir| dir.is_dir()).unwrap_or_else(std::env::temp_dir)
         }
     };
 
-    // Build FFmpeg arguments
-    let mut args = vec![
-        "-y".to_string(),                        // Overwrite output file
-        "-ss".to_string(), start_str,            // Start time
-        "-i".to_string(), input_path,            // Input file
-        "-t".to_string(), duration.to_string(),  // Duration
-    ];
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("System clock error: {}", e))?
+        .as_secs() as i64;
+
+    // Days since 1970-01-01 -> civil date (Howard Hinnant's days_from_civil, inverted)
+    let days = secs.div_euclid(86400);
+    let seconds_of_day = secs.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let stamp = format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year, month, day,
+        seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60
+    );
 
-    // Add video filter if scaling is needed
-    if should_scale {
-        args.push("-vf".to_string());
-        args.push(scale_filter.to_string());
+    // Two captures in the same second get a numeric suffix instead of overwriting each other
+    let mut path = dir.join(format!("clipforge_{}_{}.{}", kind, stamp, extension));
+    let mut counter = 1;
+    while path.exists() {
+        path = dir.join(format!("clipforge_{}_{}_{}.{}", kind, stamp, counter, extension));
+        counter += 1;
     }
+    Ok(path.to_string_lossy().to_string())
+}
 
-    // Add encoding options
-    if should_scale {
-        // Re-encode when scaling
-        args.push("-c:v".to_string());
-        args.push("libx264".to_string());
-        args.push("-preset".to_string());
-        args.push("fast".to_string());
-        args.push("-b:v".to_string());
-        args.push(bitrate.to_string());
-    } else {
-        // Copy codec for source resolution (fast)
-        args.push("-c".to_string());
-        args.push("copy".to_string());
+// How long quick probe/listing commands may run before they're killed (seconds)
+static COMMAND_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(30);
+
+#[tauri::command]
+fn set_command_timeout(seconds: u64) -> Result<(), String> {
+    if seconds == 0 {
+        return Err("Command timeout must be greater than zero".to_string());
     }
+    COMMAND_TIMEOUT_SECS.store(seconds, Ordering::SeqCst);
+    println!("[set_command_timeout] Probe and device commands now time out after {}s", seconds);
+    Ok(())
+}
 
-    args.push("-avoid_negative_ts".to_string());
-    args.push("make_zero".to_string());
-    args.push(output_path.clone());
+// Helper function to run a command like `Command::output`, but kill it after COMMAND_TIMEOUT_SECS
+// A stuck device or network input can otherwise block the calling command forever.
+// stdout/stderr are drained on their own threads so a chatty process can't fill the pipe and stall.
+fn output_with_timeout(command: &mut Command) -> std::io::Result<std::process::Output> {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
 
-    println!("[trim_video] FFmpeg args: {:?}", args);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
 
-    // FFmpeg command - don't capture stderr to avoid blocking
-    println!("[trim_video] Spawning FFmpeg process (without stderr capture)...");
-    let ffmpeg_path = get_ffmpeg_path();
-    let mut child = Command::new(&ffmpeg_path)
-        .args(&args)
-        .spawn()
-        .map_err(|e| {
-            let err_msg = format!("Failed to start FFmpeg: {}", e);
-            println!("[trim_video] ERROR: {}", err_msg);
-            err_msg
-        })?;
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let timeout = Duration::from_secs(COMMAND_TIMEOUT_SECS.load(Ordering::SeqCst));
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("timed out after {}s", timeout.as_secs()),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
 
-    // Wait for FFmpeg to finish without blocking the main thread
-    println!("[trim_video] Waiting for FFmpeg to complete...");
-    let status = tokio::task::spawn_blocking(move || child.wait())
-        .await
-        .map_err(|e| {
-            let err_msg = format!("Task join error: {}", e);
-            println!("[trim_video] ERROR: {}", err_msg);
-            err_msg
-        })?
-        .map_err(|e| {
-            let err_msg = format!("Failed to wait for FFmpeg: {}", e);
-            println!("[trim_video] ERROR: {}", err_msg);
-            err_msg
-        })?;
+    Ok(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
 
-    if status.success() {
-        println!("[trim_video] FFmpeg completed successfully!");
-        println!("[trim_video] Output file: {}", output_path);
-        Ok(output_path)
-    } else {
-        let err_msg = format!("FFmpeg exited with status: {}", status);
-        println!("[trim_video] ERROR: {}", err_msg);
-        Err(err_msg)
+// Helper function to read input info via `ffmpeg -i` (lower fidelity than ffprobe)
+fn ffmpeg_probe(video_path: &str) -> Result<FfmpegProbeInfo, String> {
+    let ffmpeg_path = get_ffmpeg_path();
+    // FFmpeg exits with an error because no output is given; the input summary is still printed
+    let output = output_with_timeout(Command::new(&ffmpeg_path).args(["-hide_banner", "-i", video_path]))
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let info = parse_ffmpeg_input_info(&stderr);
+    if info.streams.is_empty() {
+        return Err(format!("FFmpeg could not read {}", video_path));
     }
+    Ok(info)
 }
 
-#[tauri::command]
-async fn concatenate_clips(
-    clips: Vec<ClipSegment>,
-    output_path: String,
-    export_options: Option<ExportOptions>,
-    pip_track: Option<PipTrackData>,
-    _window: tauri::Window
-) -> Result<String, String> {
-    println!("[concatenate_clips] Starting concatenation of {} clips", clips.len());
-    println!("[concatenate_clips] Output: {}", output_path);
-    if let Some(ref pip) = pip_track {
-        println!("[concatenate_clips] PiP track: {} (offset: {}s, duration: {}s, position: {})",
-            pip.path, pip.offset, pip.duration, pip.position);
-    }
+// Helper function to check whether an output path is an MP4/MOV-family container
+// These containers support -movflags (e.g. +faststart to move the moov atom to the front)
+fn is_mp4_container(path: &str) -> bool {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
 
-    if clips.is_empty() {
-        return Err("No clips provided for concatenation".to_string());
-    }
+    matches!(extension.as_deref(), Some("mp4") | Some("m4v") | Some("m4a") | Some("mov"))
+}
 
-    // Parse export options
-    let opts = export_options.unwrap_or_else(|| ExportOptions {
-        resolution: Some("source".to_string()),
-        source_width: None,
-        source_height: None,
-    });
+// Encoder and container settings for an export's intended use (the `optimize_for` option)
+#[derive(Debug, Clone)]
+struct OutputProfile {
+    preset: &'static str,
+    crf: Option<&'static str>,      // Constant quality, overriding the export's usual rate control
+    audio_bitrate: &'static str,
+    keyframe_interval: Option<u32>, // Force a keyframe every N seconds
+    faststart: bool,
+    x264_params: Option<String>,    // Validated -x264-params passthrough
+    scenecut_keyframes: bool,       // Keyframes at scene cuts, at most SCENECUT_MAX_GOP frames apart
+}
 
-    // Format time as HH:MM:SS.mmm
-    fn format_time(seconds: f64) -> String {
-        let hours = (seconds / 3600.0).floor() as u32;
-        let minutes = ((seconds % 3600.0) / 60.0).floor() as u32;
-        let secs = seconds % 60.0;
-        format!("{:02}:{:02}:{:06.3}", hours, minutes, secs)
+// GOP bounds for scene-cut keyframes: at most ~2s apart at 30 fps, and never closer than 0.5s
+const SCENECUT_MAX_GOP: u32 = 60;
+const SCENECUT_MIN_GOP: u32 = 15;
+
+// Helper function to validate an `x264_params` export option
+// Only "key=value" pairs joined by ':' are accepted, so the string can't smuggle in
+// other FFmpeg options (leading dashes, whitespace, quotes or separators are rejected).
+fn validate_x264_params(params: &str) -> Result<(), String> {
+    let is_key_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let is_value_char = |c: char| c.is_ascii_alphanumeric() || "-_.,+/".contains(c);
+
+    for pair in params.split(':') {
+        let valid = match pair.split_once('=') {
+            Some((key, value)) => {
+                !key.is_empty()
+                    && !key.starts_with('-')
+                    && key.chars().all(is_key_char)
+                    && !value.is_empty()
+                    && value.chars().all(is_value_char)
+            }
+            None => false,
+        };
+        if !valid {
+            return Err(format!("Invalid x264_params entry '{}': expected key=value pairs separated by ':'", pair));
+        }
     }
+    Ok(())
+}
 
-    // Create a temporary directory for intermediate files
-    let temp_dir = std::env::temp_dir().join(format!("clipforge_{}", std::process::id()));
-    std::fs::create_dir_all(&temp_dir)
-        .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+// Helper function to map `optimize_for` to an output profile
+// "streaming" (the default) matches the historical export settings.
+fn resolve_output_profile(optimize_for: Option<&str>, x264_params: Option<&str>) -> Result<OutputProfile, CommandError> {
+    let x264_params = match x264_params {
+        Some(params) if !params.is_empty() => {
+            validate_x264_params(params).map_err(CommandError::invalid_input)?;
+            Some(params.to_string())
+        }
+        _ => None,
+    };
+    let profile = match optimize_for {
+        // Sharing/web: moov atom up front so playback starts before the download finishes
+        Some("streaming") | None => OutputProfile {
+            preset: "fast",
+            crf: None,
+            audio_bitrate: "192k",
+            keyframe_interval: None,
+            faststart: true,
+            x264_params: None,
+            scenecut_keyframes: false,
+        },
+        // Re-editing: a keyframe every second for responsive scrubbing and cutting,
+        // and no faststart rewrite pass since the file stays local
+        Some("editing") => OutputProfile {
+            preset: "fast",
+            crf: None,
+            audio_bitrate: "192k",
+            keyframe_interval: Some(1),
+            faststart: false,
+            x264_params: None,
+            scenecut_keyframes: false,
+        },
+        // Long-term storage: slower preset and near-transparent constant quality
+        Some("archival") => OutputProfile {
+            preset: "slow",
+            crf: Some("16"),
+            audio_bitrate: "320k",
+            keyframe_interval: None,
+            faststart: true,
+            x264_params: None,
+            scenecut_keyframes: false,
+        },
+        Some(other) => return Err(CommandError::invalid_input(format!("Invalid optimize_for value: {}", other))),
+    };
+    Ok(OutputProfile { x264_params, ..profile })
+}
 
-    // Process each clip segment
-    let mut segment_paths = Vec::new();
-    for (i, clip) in clips.iter().enumerate() {
-        println!("[concatenate_clips] Processing clip {}: {} ({}s to {}s)",
-            i, clip.path, clip.clip_start, clip.clip_end);
+impl OutputProfile {
+    // Turn on the `scenecut_keyframes` export option
+    fn with_scenecut_keyframes(self, enabled: bool) -> Self {
+        OutputProfile { scenecut_keyframes: enabled, ..self }
+    }
 
-        let segment_path = temp_dir.join(format!("segment_{}.mp4", i));
-        let start_str = format_time(clip.clip_start);
-        let duration = clip.clip_end - clip.clip_start;
+    // libx264 options; `rate_control` is the export's usual "-b:v"/"-crf" pair
+    fn video_args(&self, rate_control: [&str; 2]) -> Vec<String> {
+        let mut args = vec!["-preset".to_string(), self.preset.to_string()];
+        match self.crf {
+            Some(crf) => args.extend(["-crf".to_string(), crf.to_string()]),
+            None => args.extend(rate_control.iter().map(|arg| arg.to_string())),
+        }
+        if let Some(interval) = self.keyframe_interval {
+            args.push("-force_key_frames".to_string());
+            args.push(format!("expr:gte(t,n_forced*{})", interval));
+        }
+        if self.scenecut_keyframes {
+            // x264 places keyframes where the scene changes; -g caps the gap on static footage
+            args.extend([
+                "-sc_threshold".to_string(), "40".to_string(),
+                "-g".to_string(), SCENECUT_MAX_GOP.to_string(),
+                "-keyint_min".to_string(), SCENECUT_MIN_GOP.to_string(),
+            ]);
+        }
+        if let Some(ref params) = self.x264_params {
+            args.push("-x264-params".to_string());
+            args.push(params.clone());
+        }
+        args
+    }
 
-        // Build ffmpeg command to extract this segment
-        let mut ffmpeg_args = vec![
-            "-y".to_string(),
-            "-ss".to_string(), start_str,
-            "-i".to_string(), clip.path.clone(),
-            "-t".to_string(), format_time(duration),
-            "-c:v".to_string(), "libx264".to_string(),
-            "-preset".to_string(), "fast".to_string(),
-            "-crf".to_string(), "18".to_string(),
+    fn audio_args(&self) -> Vec<String> {
+        vec![
             "-c:a".to_string(), "aac".to_string(),
-            "-b:a".to_string(), "192k".to_string(),
-        ];
+            "-b:a".to_string(), self.audio_bitrate.to_string(),
+        ]
+    }
 
-        // Handle resolution settings
-        match opts.resolution.as_deref() {
-            Some("source") => {},
-            Some("720p") => {
-                ffmpeg_args.extend(vec!["-vf".to_string(), "scale=-2:720".to_string()]);
-            },
-            Some("1080p") => {
-                ffmpeg_args.extend(vec!["-vf".to_string(), "scale=-2:1080".to_string()]);
-            },
-            _ => {}
+    // Container layout flags (only MP4-family outputs take -movflags)
+    fn movflags_args(&self, output_path: &str) -> Vec<String> {
+        if self.faststart && is_mp4_container(output_path) {
+            vec!["-movflags".to_string(), "+faststart".to_string()]
+        } else {
+            Vec::new()
         }
+    }
+}
 
-        ffmpeg_args.push(segment_path.to_str().unwrap().to_string());
-
-        println!("[concatenate_clips] FFmpeg args for segment {}: {:?}", i, ffmpeg_args);
+// Kind of failure reported by export and recording commands
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    FfmpegMissing,    // FFmpeg binary couldn't be found
+    SpawnFailed,      // FFmpeg exists but couldn't be started
+    NonZeroExit,      // FFmpeg ran and failed; detail holds the tail of its log
+    InvalidInput,     // Bad arguments or options, or a missing input file
+    Cancelled,        // The operation was cancelled before it finished
+    OutputEmpty,      // FFmpeg claimed success but wrote nothing usable
+    DeviceBusy,       // A capture device is held by another app; `device` names it
+    PermissionDenied, // The OS refused access to the screen, camera or microphone
+    Failed,           // Anything else
+}
 
-        let ffmpeg_path = get_ffmpeg_path();
-        let status = Command::new(&ffmpeg_path)
-            .args(&ffmpeg_args)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+// Structured command error, serialized as { code, message, detail, device }
+#[derive(Debug, Serialize)]
+struct CommandError {
+    code: ErrorCode,
+    message: String,
+    detail: Option<String>,
+    device: Option<String>, // Capture device involved in a DeviceBusy/PermissionDenied error
+}
 
-        if !status.success() {
-            // Clean up temp directory
-            let _ = std::fs::remove_dir_all(&temp_dir);
-            return Err(format!("FFmpeg failed to process segment {}", i));
+impl CommandError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CommandError {
+            code,
+            message: message.into(),
+            detail: None,
+            device: None,
         }
-
-        segment_paths.push(segment_path);
     }
 
-    // Create concat list file
-    let concat_list_path = temp_dir.join("concat_list.txt");
-    let concat_content = segment_paths
-        .iter()
-        .map(|p| format!("file '{}'", p.to_str().unwrap()))
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    std::fs::write(&concat_list_path, concat_content)
-        .map_err(|e| format!("Failed to write concat list: {}", e))?;
+    fn invalid_input(message: impl Into<String>) -> Self {
+        CommandError::new(ErrorCode::InvalidInput, message)
+    }
 
-    println!("[concatenate_clips] Concatenating segments into final output");
+    fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
 
-    // If no PiP track, use simple concat
-    if pip_track.is_none() {
-        let concat_args = vec![
-            "-y".to_string(),
-            "-f".to_string(), "concat".to_string(),
-            "-safe".to_string(), "0".to_string(),
-            "-i".to_string(), concat_list_path.to_str().unwrap().to_string(),
-            "-c".to_string(), "copy".to_string(),
-            output_path.clone(),
-        ];
+    // A spawn failure because the binary doesn't exist is reported as FfmpegMissing
+    fn spawn_failed(error: std::io::Error, message: impl Into<String>) -> Self {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            CommandError::new(ErrorCode::FfmpegMissing, format!("FFmpeg not found: {}", error))
+        } else {
+            CommandError::new(ErrorCode::SpawnFailed, message).with_detail(error.to_string())
+        }
+    }
+}
 
-        println!("[concatenate_clips] Final concat args (no PiP): {:?}", concat_args);
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.message, detail),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
 
-        let ffmpeg_path = get_ffmpeg_path();
-        let status = Command::new(&ffmpeg_path)
-            .args(&concat_args)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map_err(|e| format!("Failed to execute FFmpeg for concatenation: {}", e))?;
+// Helpers that still report plain strings surface as ErrorCode::Failed
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::new(ErrorCode::Failed, message)
+    }
+}
 
-        // Clean up temp directory
-        let _ = std::fs::remove_dir_all(&temp_dir);
+// Commands that haven't moved to CommandError keep receiving the message text
+impl From<CommandError> for String {
+    fn from(error: CommandError) -> Self {
+        error.to_string()
+    }
+}
 
-        if status.success() {
-            println!("[concatenate_clips] Concatenation completed successfully");
-            return Ok(output_path);
+impl From<tokio::task::JoinError> for CommandError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        if error.is_cancelled() {
+            CommandError::new(ErrorCode::Cancelled, "Operation was cancelled")
         } else {
-            return Err("FFmpeg concatenation failed".to_string());
+            CommandError::new(ErrorCode::Failed, format!("Task join error: {}", error))
         }
     }
+}
 
-    // With PiP track, we need to apply overlay filter
-    let pip = pip_track.unwrap();
+// How long a new recording is watched for FFmpeg failing to open its devices
+const RECORDING_STARTUP_TIMEOUT_MS: u64 = 3000;
+
+// Helper function to classify why a capture FFmpeg exited right after starting
+// Matches the messages the capture backends print when a device is taken or access is refused:
+//   dshow:       "Could not run graph (sometimes caused by a device already in use by other application)"
+//   v4l2/ALSA:   "Device or resource busy"
+//   avfoundation "... is in use by another application" / "not authorized"
+// Lines about `output_path` are skipped: an unwritable output folder also says "Permission denied".
+fn classify_capture_error(log: &str, output_path: &str) -> Option<ErrorCode> {
+    let log = log
+        .lines()
+        .filter(|line| !line.contains(output_path) && !line.contains("output file"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase();
+    const BUSY_PATTERNS: [&str; 4] = [
+        "device or resource busy",
+        "already in use",
+        "in use by another application",
+        "could not run graph",
+    ];
+    const PERMISSION_PATTERNS: [&str; 5] = [
+        "permission denied",
+        "not authorized",
+        "operation not permitted",
+        "access is denied",
+        "access denied",
+    ];
 
-    // Calculate position based on corner and size
-    let overlay_position = match pip.position.as_str() {
-        "top-left" => "20:20",
-        "top-right" => "main_w-overlay_w-20:20",
-        "bottom-left" => "20:main_h-overlay_h-20",
-        "bottom-right" => "main_w-overlay_w-20:main_h-overlay_h-20",
-        _ => "main_w-overlay_w-20:main_h-overlay_h-20", // Default to bottom-right
-    };
+    if BUSY_PATTERNS.iter().any(|pattern| log.contains(pattern)) {
+        Some(ErrorCode::DeviceBusy)
+    } else if PERMISSION_PATTERNS.iter().any(|pattern| log.contains(pattern)) {
+        Some(ErrorCode::PermissionDenied)
+    } else {
+        None
+    }
+}
 
-    // Calculate PiP size (as a fraction of main video width)
-    let pip_scale = format!("iw*{}:ih*{}", pip.size_percent / 100.0, pip.size_percent / 100.0);
+// Helper function to wait until a capture FFmpeg is actually recording
+// Device errors only show up after the process starts, so the first few seconds are watched
+// on a blocking thread: an early exit becomes a DeviceBusy/PermissionDenied/NonZeroExit error
+// for `device`. Hands the child back once it's running.
+async fn check_recording_startup(
+    mut child: std::process::Child,
+    device: &str,
+    output_path: &str,
+) -> Result<std::process::Child, CommandError> {
+    let device = device.to_string();
+    let output_path = output_path.to_string();
+    tokio::task::spawn_blocking(move || {
+        watch_recording_startup(&mut child, &device, &output_path).map(|()| child)
+    })
+    .await
+    .map_err(|e| CommandError::new(ErrorCode::Failed, format!("Recording startup check panicked: {}", e)))?
+}
 
-    // Build complex filter for PiP overlay
-    let filter_complex = format!(
-        "[1:v]scale={}[pip];[0:v][pip]overlay={}:enable='between(t,{},{})'[v];[0:a][1:a]amix=inputs=2:duration=first:weights={} {}[a]",
-        pip_scale,
-        overlay_position,
-        pip.offset,
-        pip.offset + pip.duration,
-        1.0, // Main audio at full volume
-        pip.volume // PiP audio at specified volume
-    );
+// stderr stays drained for the rest of the recording so FFmpeg never blocks on a full pipe.
+fn watch_recording_startup(child: &mut std::process::Child, device: &str, output_path: &str) -> Result<(), CommandError> {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    let tail: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let started = Arc::new(AtomicBool::new(false));
+
+    let reader = child.stderr.take().map(|mut stderr| {
+        let tail = tail.clone();
+        let started = started.clone();
+        std::thread::spawn(move || {
+            // Progress lines end in '\r', so split on both line endings
+            let mut buffer = [0u8; 4096];
+            let mut line = Vec::new();
+            while let Ok(read) = stderr.read(&mut buffer) {
+                if read == 0 {
+                    break;
+                }
+                for &byte in &buffer[..read] {
+                    if byte != b'\n' && byte != b'\r' {
+                        if line.len() < 1024 {
+                            line.push(byte);
+                        }
+                        continue;
+                    }
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let text = String::from_utf8_lossy(&line).to_string();
+                    line.clear();
+                    // FFmpeg prints this once every input is open and encoding has begun
+                    if text.contains("Press [q]") || text.starts_with("frame=") {
+                        started.store(true, Ordering::SeqCst);
+                    }
+                    let mut tail = tail.lock().unwrap();
+                    if tail.len() == 40 {
+                        tail.remove(0);
+                    }
+                    tail.push(text);
+                }
+            }
+        })
+    });
 
-    println!("[concatenate_clips] PiP overlay filter: {}", filter_complex);
+    let deadline = Instant::now() + Duration::from_millis(RECORDING_STARTUP_TIMEOUT_MS);
+    while Instant::now() < deadline && !started.load(Ordering::SeqCst) {
+        let status = child
+            .try_wait()
+            .map_err(|e| CommandError::new(ErrorCode::Failed, format!("Failed to check FFmpeg: {}", e)))?;
+        let Some(status) = status else {
+            std::thread::sleep(Duration::from_millis(50));
+            continue;
+        };
+
+        if let Some(reader) = reader {
+            let _ = reader.join();
+        }
+        let log = tail.lock().unwrap().join("\n");
+        println!("[recording_startup] FFmpeg exited during startup ({}): {}", status, log);
+
+        let error = match classify_capture_error(&log, output_path) {
+            Some(ErrorCode::DeviceBusy) => CommandError::new(
+                ErrorCode::DeviceBusy,
+                format!("The {} is in use by another app. Close it and try again.", device),
+            ),
+            Some(ErrorCode::PermissionDenied) => CommandError::new(
+                ErrorCode::PermissionDenied,
+                format!("Access to the {} was denied. Grant permission in system settings and try again.", device),
+            ),
+            _ => CommandError::new(ErrorCode::NonZeroExit, format!("Recording failed to start ({})", status)),
+        };
+        return Err(CommandError {
+            device: Some(device.to_string()),
+            ..error.with_detail(log)
+        });
+    }
 
-    // First, concatenate the main clips without PiP
-    let temp_concat_path = temp_dir.join("temp_concat.mp4");
-    let concat_args = vec![
-        "-y".to_string(),
-        "-f".to_string(), "concat".to_string(),
-        "-safe".to_string(), "0".to_string(),
-        "-i".to_string(), concat_list_path.to_str().unwrap().to_string(),
-        "-c".to_string(), "copy".to_string(),
-        temp_concat_path.to_str().unwrap().to_string(),
-    ];
+    Ok(())
+}
 
-    println!("[concatenate_clips] Creating temp concat (before PiP): {:?}", concat_args);
+// Helper function to run FFmpeg to completion off the async runtime
+// stderr is captured so failures can be reported back to the frontend
+async fn run_ffmpeg(args: Vec<String>, context: &str) -> Result<(), CommandError> {
+    println!("[{}] FFmpeg args: {:?}", context, args);
 
     let ffmpeg_path = get_ffmpeg_path();
-    let status = Command::new(&ffmpeg_path)
-        .args(&concat_args)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map_err(|e| format!("Failed to execute FFmpeg for temp concatenation: {}", e))?;
-
-    if !status.success() {
-        let _ = std::fs::remove_dir_all(&temp_dir);
-        return Err("FFmpeg temp concatenation failed".to_string());
-    }
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(&ffmpeg_path)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+    })
+    .await?
+    .map_err(|e| CommandError::spawn_failed(e, "Failed to start FFmpeg"))?;
 
-    // Now apply PiP overlay
-    let pip_args = vec![
-        "-y".to_string(),
-        "-i".to_string(), temp_concat_path.to_str().unwrap().to_string(),
-        "-i".to_string(), pip.path.clone(),
-        "-filter_complex".to_string(), filter_complex,
-        "-map".to_string(), "[v]".to_string(),
-        "-map".to_string(), "[a]".to_string(),
-        "-c:v".to_string(), "libx264".to_string(),
-        "-preset".to_string(), "fast".to_string(),
-        "-crf".to_string(), "18".to_string(),
-        "-c:a".to_string(), "aac".to_string(),
-        "-b:a".to_string(), "192k".to_string(),
-        output_path.clone(),
-    ];
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr);
+        println!("[{}] ERROR: {}", context, error);
 
-    println!("[concatenate_clips] Applying PiP overlay: {:?}", pip_args);
+        // The last few lines of FFmpeg's log carry the actual failure reason
+        let lines: Vec<&str> = error.lines().filter(|l| !l.trim().is_empty()).collect();
+        let summary = lines[lines.len().saturating_sub(5)..].join("\n");
+        Err(CommandError::new(ErrorCode::NonZeroExit, format!("FFmpeg exited with status {}", output.status)).with_detail(summary))
+    }
+}
 
-    let ffmpeg_path = get_ffmpeg_path();
-    let status = Command::new(&ffmpeg_path)
-        .args(&pip_args)
+// Helper function to run FFmpeg like run_ffmpeg, reporting its progress through an output of
+// `duration` seconds to `on_progress` (percent 0-100) while it runs
+async fn run_ffmpeg_with_progress(
+    args: Vec<String>,
+    context: &str,
+    duration: f64,
+    on_progress: impl Fn(f64) + Send + 'static,
+) -> Result<(), CommandError> {
+    println!("[{}] FFmpeg args: {:?}", context, args);
+
+    let mut child = Command::new(get_ffmpeg_path())
+        .args(&args)
+        .stdin(Stdio::null())
         .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map_err(|e| format!("Failed to execute FFmpeg for PiP overlay: {}", e))?;
-
-    // Clean up temp directory
-    let _ = std::fs::remove_dir_all(&temp_dir);
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CommandError::spawn_failed(e, "Failed to start FFmpeg"))?;
+    let progress_reader = child
+        .stderr
+        .take()
+        .map(|stderr| spawn_ffmpeg_progress_reader(stderr, duration, move |percent, _| on_progress(percent)));
+
+    let (status, stderr_tail) = tokio::task::spawn_blocking(move || {
+        let status = child.wait();
+        let tail = progress_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        status.map(|status| (status, tail))
+    })
+    .await?
+    .map_err(|e| CommandError::new(ErrorCode::Failed, format!("Failed to wait for FFmpeg: {}", e)))?;
 
     if status.success() {
-        println!("[concatenate_clips] PiP overlay completed successfully");
-        Ok(output_path)
+        Ok(())
     } else {
-        Err("FFmpeg PiP overlay failed".to_string())
+        println!("[{}] ERROR: {}", context, stderr_tail);
+        Err(CommandError::new(ErrorCode::NonZeroExit, format!("FFmpeg exited with status {}", status)).with_detail(stderr_tail))
     }
 }
 
-#[tauri::command]
-async fn save_file_dialog(default_filename: String, app: tauri::AppHandle) -> Result<String, String> {
-    println!("[save_file_dialog] Opening save dialog");
-    println!("[save_file_dialog] Default filename: {}", default_filename);
+// Outputs smaller than this are treated as failed writes (e.g. header-only files)
+const MIN_OUTPUT_BYTES: u64 = 2048;
 
-    // Use spawn_blocking to avoid blocking the main thread
-    let file_path = tokio::task::spawn_blocking(move || {
-        app
-            .dialog()
-            .file()
-            .set_file_name(&default_filename)
-            .add_filter("Video Files", &["mp4"])
-            .blocking_save_file()
-    })
-    .await
-    .map_err(|e| format!("Task join error: {}", e))?;
+// Helper function to verify FFmpeg actually produced a usable file
+// FFmpeg can exit 0 after writing nothing (e.g. a trim range past the end of the input)
+fn verify_output_file(output_path: &str) -> Result<(), CommandError> {
+    let metadata = std::fs::metadata(output_path).map_err(|_| {
+        CommandError::new(
+            ErrorCode::OutputEmpty,
+            format!("FFmpeg reported success but no output was written: {}", output_path),
+        )
+    })?;
 
-    match file_path {
-        Some(path) => {
-            let path_str = path.to_string();
-            println!("[save_file_dialog] File selected: {}", path_str);
-            Ok(path_str)
-        },
-        None => {
-            println!("[save_file_dialog] No file selected (user cancelled)");
-            Err("No file selected".to_string())
-        },
+    if metadata.len() < MIN_OUTPUT_BYTES {
+        return Err(CommandError::new(
+            ErrorCode::OutputEmpty,
+            format!("FFmpeg produced an empty or truncated output ({} bytes): {}", metadata.len(), output_path),
+        ));
     }
+
+    Ok(())
 }
 
-#[tauri::command]
-async fn open_file_dialog(app: tauri::AppHandle) -> Result<String, String> {
-    let file_path = app
-        .dialog()
-        .file()
-        .add_filter("Video Files", &["mp4", "mov"])
-        .blocking_pick_file();
+// Recording options structure
+#[derive(Debug, Deserialize, Default)]
+struct RecordingOptions {
+    resolution: String, // "720p", "1080p", or "source"
+    #[serde(default)]
+    source_width: Option<i32>,
+    #[serde(default)]
+    source_height: Option<i32>,
+    #[serde(default)]
+    audio_device: Option<String>, // Optional audio device name ("default" for the system input)
+    #[serde(default)]
+    video_device_index: Option<i32>, // Index from list_audio_video_devices (screen or camera)
+    #[serde(default)]
+    video_device: Option<String>,    // Windows camera recording: dshow camera name (alternative to the index)
+    #[serde(default)]
+    audio_device_index: Option<i32>, // Index from list_audio_video_devices
+    #[serde(default)]
+    burn_timestamp: Option<bool>, // Burn a wall-clock timestamp into the bottom-right corner
+    #[serde(default)]
+    max_duration: Option<u32>, // Safety cap in seconds; recording stops itself when reached
+    #[serde(default)]
+    window_title: Option<String>, // Screen recording: capture a single window (title from list_windows)
+    #[serde(default)]
+    color_range: Option<String>, // "tv" (limited) or "pc" (full); unset keeps FFmpeg's default conversion
+    #[serde(default)]
+    record_audio: Option<bool>, // Explicit audio on/off; overrides the configured device when set
+    #[serde(default)]
+    denoise_audio: Option<bool>, // Run the mic through FFT noise reduction while recording
+    #[serde(default)]
+    preset: Option<String>, // x264 preset (default "ultrafast")
+    #[serde(default)]
+    tune: Option<String>,   // x264 tune, e.g. "stillimage" for mostly static screens
+    #[serde(default)]
+    retain_last_seconds: Option<u32>, // Screen recording: keep only roughly the last N seconds (ring buffer)
+    #[serde(default)]
+    max_total_size_mb: Option<u64>,   // Screen recording: drop the oldest footage once segments exceed this size
+    #[serde(default)]
+    audio_gain_db: Option<f64>, // Microphone gain in dB (-20 to +30)
+    #[serde(default)]
+    framerate: Option<u32>,     // Screen recording: capture rate; defaults to the display refresh rate
+    #[serde(default)]
+    max_framerate: Option<u32>, // Screen recording: cap for the detected refresh rate (default 60)
+    #[serde(default)]
+    trim_start_seconds: Option<f64>, // Discard this much encoder warm-up from the start of the file
+}
 
-    match file_path {
-        Some(path) => Ok(path.to_string()),
-        None => Err("No file selected".to_string()),
+// Helper function to validate `trim_start_seconds`
+// It's applied as an output -ss, so FFmpeg drops the warm-up frames as they arrive
+// and the file never contains them (no post-stop trim pass needed).
+fn recording_trim_start(opts: &RecordingOptions) -> Result<Option<f64>, String> {
+    match opts.trim_start_seconds {
+        Some(seconds) if !(0.0..=30.0).contains(&seconds) => {
+            Err(format!("trim_start_seconds must be between 0 and 30: {}", seconds))
+        }
+        Some(seconds) if seconds > 0.0 => Ok(Some(seconds)),
+        _ => Ok(None),
     }
 }
 
-#[tauri::command]
-fn get_video_metadata(video_path: String) -> Result<String, String> {
-    use std::process::Command;
+// FFT denoiser settings shared by live recording and the denoise command
+// nf is the noise floor in dB; -25 removes steady hum/hiss without making speech sound underwater
+const DENOISE_FILTER: &str = "afftdn=nf=-25";
 
-    // Run ffprobe to get video metadata as JSON
-    let ffprobe_path = get_ffprobe_path();
-    let output = Command::new(&ffprobe_path)
-        .args(&[
-            "-v", "quiet",
-            "-print_format", "json",
-            "-show_format",
-            "-show_streams",
-            &video_path
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+// Helper function to build the recording audio filter (None when nothing is requested)
+// Gain is applied before denoising; a missing afftdn filter only logs a warning so the recording still starts.
+fn recording_audio_filter(opts: &RecordingOptions) -> Result<Option<String>, String> {
+    let mut filters = Vec::new();
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("FFprobe error: {}", error));
+    if let Some(gain) = opts.audio_gain_db {
+        if !(-20.0..=30.0).contains(&gain) {
+            return Err(format!("audio_gain_db must be between -20 and 30 dB: {}", gain));
+        }
+        if gain != 0.0 {
+            filters.push(format!("volume={}dB", gain));
+        }
     }
 
-    let json = String::from_utf8_lossy(&output.stdout).to_string();
-    Ok(json)
-}
-
-#[tauri::command]
-fn get_video_file(video_path: &str) -> Result<Vec<u8>, String> {
-    use std::fs;
-    fs::read(video_path)
-        .map_err(|e| format!("Failed to read video file: {}", e))
-}
-
-#[tauri::command]
-fn get_video_file_path(video_path: String) -> Result<String, String> {
-    // Validate path exists
-    let path = std::path::Path::new(&video_path);
-    if !path.exists() {
-        return Err(format!("Video file not found: {}", video_path));
+    if opts.denoise_audio.unwrap_or(false) {
+        if ffmpeg_has_filter("afftdn") {
+            filters.push(DENOISE_FILTER.to_string());
+        } else {
+            println!("[recording] Warning: afftdn filter not available, recording without noise suppression");
+        }
     }
 
-    // Return absolute path
-    match path.canonicalize() {
-        Ok(canonical_path) => Ok(canonical_path.to_string_lossy().to_string()),
-        Err(e) => Err(format!("Failed to resolve path: {}", e))
-    }
+    Ok(if filters.is_empty() { None } else { Some(filters.join(",")) })
 }
 
-#[tauri::command]
-fn start_screen_recording(
-    output_path: String,
-    options: Option<RecordingOptions>,
-    _window: tauri::Window
-) -> Result<String, String> {
-    println!("[start_screen_recording] Starting screen recording");
-    println!("[start_screen_recording] Output path: {}", output_path);
+#[derive(Debug, Serialize, Clone)]
+struct RecordingAutoStopped {
+    source: String, // "screen" or "camera"
+    max_duration: u32,
+}
 
-    // Parse resolution options
-    let opts = options.unwrap_or_else(|| RecordingOptions {
-        resolution: "720p".to_string(),
-        source_width: None,
-        source_height: None,
-        audio_device: None,
-    });
+#[derive(Debug, Serialize, Clone)]
+struct RecordingFailed {
+    source: String,  // "screen" or "camera"
+    message: String, // Why FFmpeg stopped, and what happened to the recorded file
+}
 
-    // Determine resolution and bitrate
-    let (width, height, bitrate) = match opts.resolution.as_str() {
-        "720p" => (1280, 720, "2500k"),
-        "1080p" => (1920, 1080, "5000k"),
-        "source" => {
-            if let (Some(w), Some(h)) = (opts.source_width, opts.source_height) {
-                (w, h, "8000k")
+// Helper function to watch a recording that was started with a max duration
+// FFmpeg stops itself via -t; once it exits, clear the global slot (so is_recording()
+// reports false) and notify the frontend. A clean exit at the cap is reported as
+// "recording-auto-stopped"; a crash or error exit before it as "recording-failed".
+// The pid check ensures we only clear the process we started, not a newer recording that replaced it.
+fn spawn_auto_stop_watcher(
+    slot: Arc<Mutex<Option<std::process::Child>>>,
+    pending: Option<Arc<Mutex<Option<PendingRecording>>>>,
+    pid: u32,
+    source: &str,
+    max_duration: u32,
+    window: tauri::Window,
+) {
+    use std::thread;
+    use std::time::Duration;
+
+    let source = source.to_string();
+    let started = std::time::Instant::now();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(500));
+
+        let mut process = slot.lock().unwrap();
+        let exit_status = match process.as_mut() {
+            Some(child) if child.id() == pid => child.try_wait().ok().flatten(),
+            // Stopped manually or replaced by another recording
+            _ => break,
+        };
+
+        if let Some(status) = exit_status {
+            *process = None;
+            drop(process);
+
+            // Allow for the startup check and FFmpeg's own shutdown around the cap
+            let reached_cap = status.success() && started.elapsed().as_secs_f64() + 2.0 >= max_duration as f64;
+            let finalized = pending
+                .as_ref()
+                .and_then(|p| p.lock().unwrap().take())
+                .map(|recording| finalize_recording(recording, status.success()));
+
+            if reached_cap {
+                println!("[auto_stop] {} recording reached max duration of {}s", source, max_duration);
+                if let Some(Err(e)) = finalized {
+                    println!("[auto_stop] {}", e);
+                }
+                let _ = window.emit(
+                    "recording-auto-stopped",
+                    RecordingAutoStopped {
+                        source: source.clone(),
+                        max_duration,
+                    },
+                );
             } else {
-                return Err("Source resolution not available".to_string());
+                let message = match finalized {
+                    Some(Ok(path)) => format!("FFmpeg stopped unexpectedly ({}); recording saved to {}", status, path),
+                    Some(Err(e)) => format!("FFmpeg stopped unexpectedly ({}). {}", status, e),
+                    None => format!("FFmpeg stopped unexpectedly ({})", status),
+                };
+                println!("[auto_stop] {} recording failed: {}", source, message);
+                let _ = window.emit("recording-failed", RecordingFailed { source: source.clone(), message });
             }
+            break;
         }
-        _ => return Err(format!("Invalid resolution: {}", opts.resolution)),
-    };
-
-    println!("[start_screen_recording] Resolution: {}x{} @ {} bitrate", width, height, bitrate);
-    if opts.audio_device.is_some() {
-        println!("[start_screen_recording] Audio device: {:?}", opts.audio_device);
-    }
+    });
+}
 
-    // Platform-specific FFmpeg arguments
-    let scale_filter = format!("scale={}:{}", width, height);
+// A recording being written to a temp file next to its final path
+#[derive(Debug, Clone)]
+struct PendingRecording {
+    temp_path: String,
+    output_path: String,
+    remux: bool,                        // Temp file is MPEG-TS and gets re-muxed into the MP4 output
+    segments: Option<SegmentRetention>, // Ring-buffer recording: segments are joined on stop
+}
 
-    // Prepare Windows input string (if needed) before args to ensure proper lifetime
-    let windows_audio_input = if cfg!(target_os = "windows") {
-        if let Some(audio_dev) = &opts.audio_device {
-            format!("audio={}", audio_dev)
-        } else {
-            String::new()
-        }
-    } else {
-        String::new()
-    };
+// Length of each ring-buffer segment; retention is enforced in whole segments
+const RECORDING_SEGMENT_SECONDS: u32 = 10;
 
-    let args = if cfg!(target_os = "macos") {
-        // macOS: avfoundation supports audio input
-        // Format: "1:0" means screen device 1, audio device 0 (default microphone)
-        let mut args = vec![
-            "-f".to_string(), "avfoundation".to_string(),
-            "-framerate".to_string(), "30".to_string(),
-        ];
+// Retention limits for a segmented (ring-buffer) recording
+#[derive(Debug, Clone)]
+struct SegmentRetention {
+    dir: String,
+    keep_segments: Option<usize>, // Includes the segment currently being written
+    max_bytes: Option<u64>,
+}
 
-        if opts.audio_device.is_some() {
-            // Include audio: screen (1) and microphone (0)
-            args.push("-i".to_string());
-            args.push("1:0".to_string());
-        } else {
-            // Video only
-            args.push("-i".to_string());
-            args.push("1".to_string());
+impl SegmentRetention {
+    fn from_options(recording: &PendingRecording, opts: &RecordingOptions) -> Result<Option<Self>, String> {
+        if opts.retain_last_seconds.is_none() && opts.max_total_size_mb.is_none() {
+            return Ok(None);
+        }
+        if opts.retain_last_seconds == Some(0) || opts.max_total_size_mb == Some(0) {
+            return Err("Recording retention limits must be greater than zero".to_string());
         }
 
-        args.push("-vf".to_string());
-        args.push(scale_filter.clone());
-        args.push("-pix_fmt".to_string());
-        args.push("yuv420p".to_string());
-        args.push("-c:v".to_string());
-        args.push("libx264".to_string());
-        args.push("-preset".to_string());
-        args.push("ultrafast".to_string());
-        args.push("-b:v".to_string());
-        args.push(bitrate.to_string());
+        Ok(Some(SegmentRetention {
+            dir: format!("{}.segments", recording.temp_path),
+            // One extra segment so the retained footage always covers the full window
+            keep_segments: opts
+                .retain_last_seconds
+                .map(|seconds| seconds.div_ceil(RECORDING_SEGMENT_SECONDS) as usize + 1),
+            max_bytes: opts.max_total_size_mb.map(|mb| mb * 1024 * 1024),
+        }))
+    }
 
-        // Add audio encoding if audio device is specified
-        if opts.audio_device.is_some() {
-            args.push("-c:a".to_string());
-            args.push("aac".to_string());
-            args.push("-b:a".to_string());
-            args.push("192k".to_string());
-        }
+    // Segment files in recording order (names are zero-padded, so they sort chronologically)
+    fn segment_files(&self) -> Vec<std::path::PathBuf> {
+        let mut files: Vec<std::path::PathBuf> = std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("segment_")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
 
-        args.push(output_path.clone());
-        args
-    } else if cfg!(target_os = "windows") {
-        // Windows: Use gdigrab for screen + dshow for audio (if specified)
-        let mut args = vec![
-            "-f".to_string(), "gdigrab".to_string(),
-            "-framerate".to_string(), "30".to_string(),
-            "-i".to_string(), "desktop".to_string(),
-        ];
+    // Delete the oldest segments beyond the retention window; the newest is never removed
+    fn prune(&self) {
+        let mut files = self.segment_files();
+        let sizes: Vec<u64> = files.iter().map(|f| std::fs::metadata(f).map(|m| m.len()).unwrap_or(0)).collect();
+        let mut total: u64 = sizes.iter().sum();
+        let mut removed = 0;
+
+        while files.len() - removed > 1 {
+            let over_count = self.keep_segments.is_some_and(|keep| files.len() - removed > keep);
+            let over_size = self.max_bytes.is_some_and(|max| total > max);
+            if !over_count && !over_size {
+                break;
+            }
+            if let Err(e) = std::fs::remove_file(&files[removed]) {
+                println!("[recording_segments] Warning: failed to delete {}: {}", files[removed].display(), e);
+                break;
+            }
+            total -= sizes[removed];
+            removed += 1;
+        }
+        files.drain(..removed);
 
-        // Add audio input if specified
-        if let Some(_) = &opts.audio_device {
-            args.push("-f".to_string());
-            args.push("dshow".to_string());
-            args.push("-i".to_string());
-            args.push(windows_audio_input.clone());
+        if removed > 0 {
+            println!("[recording_segments] Dropped {} old segments, {} retained", removed, files.len());
         }
+    }
+}
 
-        args.push("-vf".to_string());
-        args.push(scale_filter.clone());
-        args.push("-pix_fmt".to_string());
-        args.push("yuv420p".to_string());
-        args.push("-c:v".to_string());
-        args.push("libx264".to_string());
-        args.push("-preset".to_string());
-        args.push("ultrafast".to_string());
-        args.push("-b:v".to_string());
-        args.push(bitrate.to_string());
+// Helper function to delete old ring-buffer segments while a recording runs
+// Stops once the recording with this pid is no longer in the slot.
+fn spawn_segment_cleanup(slot: Arc<Mutex<Option<std::process::Child>>>, pid: u32, retention: SegmentRetention) {
+    use std::thread;
+    use std::time::Duration;
 
-        // Add audio encoding if audio device is specified
-        if opts.audio_device.is_some() {
-            args.push("-c:a".to_string());
-            args.push("aac".to_string());
-            args.push("-b:a".to_string());
-            args.push("192k".to_string());
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(RECORDING_SEGMENT_SECONDS as u64 / 2));
+        let running = matches!(slot.lock().unwrap().as_ref(), Some(child) if child.id() == pid);
+        if !running {
+            break;
         }
+        retention.prune();
+    });
+}
 
-        args.push(output_path.clone());
-        args
-    } else {
-        return Err("Unsupported platform".to_string());
-    };
+// Helper function to join the retained ring-buffer segments into the requested output
+// Segments are MPEG-TS, which stays readable when FFmpeg is killed mid-segment, so even an
+// unclean stop produces a usable recording. A last segment that can't be read is dropped.
+fn finalize_segmented_recording(output_path: &str, retention: &SegmentRetention) -> Result<String, String> {
+    retention.prune();
+    let mut files = retention.segment_files();
+    if let Some(last) = files.last() {
+        let last_path = last.to_string_lossy().to_string();
+        if probe_duration(&last_path).map(|d| d <= 0.0).unwrap_or(true) {
+            println!("[recording_segments] Dropping unreadable last segment {}", last_path);
+            files.pop();
+        }
+    }
+    if files.is_empty() {
+        let _ = std::fs::remove_dir_all(&retention.dir);
+        return Err("Recording did not finish cleanly and produced no file".to_string());
+    }
 
-    println!("[start_screen_recording] FFmpeg args: {:?}", args);
+    println!("[recording_segments] Joining {} segments into {}", files.len(), output_path);
+    let list_path = std::path::Path::new(&retention.dir).join("segments.txt");
+    let list: String = files
+        .iter()
+        .map(|f| format!("file '{}'\n", f.to_string_lossy().replace('\'', "'\\''")))
+        .collect();
+    std::fs::write(&list_path, list).map_err(|e| format!("Failed to write segment list: {}", e))?;
 
-    // Start FFmpeg process with stdin pipe for graceful shutdown
     let ffmpeg_path = get_ffmpeg_path();
-    let child = Command::new(&ffmpeg_path)
-        .args(&args)
-        .stdin(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to start FFmpeg: {}. Make sure you have granted screen recording permissions.", e))?;
-
-    println!("[start_screen_recording] FFmpeg process started");
+    let mut args = vec![
+        "-y".to_string(),
+        "-f".to_string(), "concat".to_string(),
+        "-safe".to_string(), "0".to_string(),
+        "-i".to_string(), list_path.to_string_lossy().to_string(),
+        "-c".to_string(), "copy".to_string(),
+    ];
+    if is_mp4_container(output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.to_string());
 
-    // Store process in global state
-    let mut process = RECORDING_PROCESS.lock().unwrap();
-    *process = Some(child);
+    let output = Command::new(&ffmpeg_path)
+        .args(&args)
+        .output()
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Failed to join recording segments (kept in {}): {}",
+            retention.dir,
+            stderr.lines().last().unwrap_or("unknown error")
+        ));
+    }
+    verify_output_file(output_path)?;
 
-    Ok("Recording started".to_string())
+    let _ = std::fs::remove_dir_all(&retention.dir);
+    Ok(output_path.to_string())
 }
 
-#[tauri::command]
-fn stop_screen_recording() -> Result<String, String> {
-    use std::io::Write;
-
-    println!("[stop_screen_recording] Stopping screen recording");
+impl PendingRecording {
+    // The temp file lives in the same directory so the final rename is atomic, and keeps the
+    // extension so FFmpeg still picks the right muxer. MP4 only gets its index when FFmpeg
+    // exits cleanly, so MP4 outputs are recorded as MPEG-TS, which stays readable while it's
+    // growing and after a kill, and re-muxed into place on stop.
+    fn new(output_path: &str) -> Self {
+        let path = std::path::Path::new(output_path);
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+        let remux = is_mp4_container(output_path);
+        let file_name = match path.extension().and_then(|e| e.to_str()) {
+            Some(_) if remux => format!("{}.recording.ts", stem),
+            Some(ext) => format!("{}.recording.{}", stem, ext),
+            None => format!("{}.recording", stem),
+        };
+        PendingRecording {
+            temp_path: path.with_file_name(file_name).to_string_lossy().to_string(),
+            output_path: output_path.to_string(),
+            remux,
+            segments: None,
+        }
+    }
+}
 
-    let mut process = RECORDING_PROCESS.lock().unwrap();
+// Helper function to move a finished recording into place
+// A clean stop renames the temp file to the requested path; anything else keeps it
+// as "<output>.partial" so a truncated file never sits at the final name.
+// MPEG-TS temp files are re-muxed instead, which also recovers recordings whose FFmpeg was killed.
+fn finalize_recording(recording: PendingRecording, success: bool) -> Result<String, String> {
+    if let Some(retention) = &recording.segments {
+        return finalize_segmented_recording(&recording.output_path, retention);
+    }
 
-    if let Some(mut child) = process.take() {
-        // Send 'q' to FFmpeg stdin to gracefully stop
-        if let Some(mut stdin) = child.stdin.take() {
-            println!("[stop_screen_recording] Sending 'q' to FFmpeg to stop gracefully");
-            if let Err(e) = stdin.write_all(b"q") {
-                println!("[stop_screen_recording] Warning: Failed to send 'q' to FFmpeg: {}", e);
-                // Fall back to kill if we can't write to stdin
-                child.kill()
-                    .map_err(|e| format!("Failed to stop recording: {}", e))?;
-            } else {
-                // Flush to ensure 'q' is sent
-                let _ = stdin.flush();
-                drop(stdin); // Close stdin
-            }
-        } else {
-            println!("[stop_screen_recording] No stdin available, using kill");
-            child.kill()
-                .map_err(|e| format!("Failed to stop recording: {}", e))?;
-        }
+    if recording.remux && std::path::Path::new(&recording.temp_path).exists() {
+        return remux_pending_recording(recording);
+    }
 
-        // Wait for FFmpeg to finish encoding
-        println!("[stop_screen_recording] Waiting for FFmpeg to finish encoding...");
-        child.wait()
-            .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+    if success {
+        move_file(recording.temp_path, recording.output_path.clone())?;
+        return Ok(recording.output_path);
+    }
 
-        println!("[stop_screen_recording] Recording stopped successfully");
-        Ok("Recording stopped".to_string())
+    let partial_path = format!("{}.partial", recording.output_path);
+    if std::path::Path::new(&recording.temp_path).exists() {
+        move_file(recording.temp_path, partial_path.clone())?;
+        Err(format!("Recording did not finish cleanly; partial file kept at {}", partial_path))
     } else {
-        Err("No recording in progress".to_string())
+        Err("Recording did not finish cleanly and produced no file".to_string())
     }
 }
 
-#[tauri::command]
-fn is_recording() -> bool {
-    let process = RECORDING_PROCESS.lock().unwrap();
-    process.is_some()
-}
-
-#[tauri::command]
-fn start_screen_preview(window: tauri::Window) -> Result<String, String> {
-    use std::io::Read;
-    use std::thread;
+// Helper function to copy a recording into an MP4 without re-encoding
+// Stream copying into a new file with +faststart writes a fresh, front-loaded index.
+fn remux_recording(input_path: &str, output_path: &str) -> Result<(), String> {
+    let ffmpeg_path = get_ffmpeg_path();
+    let output = Command::new(&ffmpeg_path)
+        .args([
+            "-y",
+            "-i", input_path,
+            "-map", "0",
+            "-c", "copy",
+            "-movflags", "+faststart",
+            output_path,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
 
-    println!("[start_screen_preview] Starting screen preview");
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Failed to remux recording: {}", stderr.lines().last().unwrap_or("unknown error")));
+    }
+    Ok(verify_output_file(output_path)?)
+}
 
-    // Check if preview is already running
-    let mut process = SCREEN_PREVIEW_PROCESS.lock().unwrap();
-    if process.is_some() {
-        return Err("Preview already running".to_string());
+// Helper function to re-mux an MPEG-TS temp recording into its MP4 output
+// Works the same whether FFmpeg stopped cleanly or was killed; if the remux fails the
+// temp file is kept as "<output>.partial" like any other unclean stop.
+fn remux_pending_recording(recording: PendingRecording) -> Result<String, String> {
+    println!("[finalize_recording] Re-muxing {} into {}", recording.temp_path, recording.output_path);
+    match remux_recording(&recording.temp_path, &recording.output_path) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&recording.temp_path);
+            Ok(recording.output_path)
+        }
+        Err(e) => {
+            println!("[finalize_recording] {}", e);
+            let _ = std::fs::remove_file(&recording.output_path);
+            finalize_recording(PendingRecording { remux: false, ..recording }, false)
+        }
     }
+}
 
-    // Platform-specific FFmpeg arguments for preview
-    // Use lower quality and framerate for preview
-    let args = if cfg!(target_os = "macos") {
-        vec![
-            "-f", "avfoundation",
-            "-framerate", "15",          // Lower framerate for preview
-            "-video_size", "640x360",    // Lower resolution for preview
-            "-i", "1",                    // Screen capture (1 = main display)
-            "-f", "image2pipe",
-            "-vcodec", "mjpeg",
-            "-q:v", "10",                 // JPEG quality (2-31, lower is better)
-            "-"                           // Output to stdout
-        ]
+// Helper function to find a font file for drawtext
+// FFmpeg builds without fontconfig need an explicit fontfile, so prefer a known system font
+fn get_drawtext_font_path() -> Option<String> {
+    let candidates: &[&str] = if cfg!(target_os = "macos") {
+        &["/System/Library/Fonts/Helvetica.ttc", "/Library/Fonts/Arial.ttf"]
     } else if cfg!(target_os = "windows") {
-        vec![
-            "-f", "gdigrab",
-            "-framerate", "15",
-            "-video_size", "640x360",
-            "-i", "desktop",
-            "-f", "image2pipe",
-            "-vcodec", "mjpeg",
-            "-q:v", "10",
-            "-"
-        ]
+        &["C:/Windows/Fonts/arial.ttf", "C:/Windows/Fonts/segoeui.ttf"]
     } else {
-        return Err("Unsupported platform".to_string());
+        &[
+            "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+            "/usr/share/fonts/TTF/DejaVuSans.ttf",
+        ]
     };
 
-    println!("[start_screen_preview] FFmpeg args: {:?}", args);
+    candidates
+        .iter()
+        .find(|path| std::path::Path::new(path).exists())
+        .map(|path| path.to_string())
+}
 
-    // Start FFmpeg process with stdout piped
-    let ffmpeg_path = get_ffmpeg_path();
-    let mut child = Command::new(&ffmpeg_path)
-        .args(&args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| format!("Failed to start preview: {}", e))?;
+// Helper function to build the "fontfile=...:" prefix for drawtext options
+// Returns an empty string when no font is found so FFmpeg falls back to fontconfig
+fn drawtext_font_option() -> String {
+    match get_drawtext_font_path() {
+        // Colons (e.g. Windows drive letters) must be escaped inside filter options
+        Some(path) => format!("fontfile='{}':", path.replace(':', "\\:")),
+        None => String::new(),
+    }
+}
 
-    // Get stdout handle
-    let mut stdout = child.stdout.take()
-        .ok_or("Failed to get stdout")?;
+// Helper function to build the recording video filter chain
+// `base_filter` is the scaling step (if any); overlays are appended after it so they render at output size
+fn build_recording_video_filter(opts: &RecordingOptions, base_filter: Option<String>) -> Option<String> {
+    let mut filters: Vec<String> = base_filter.into_iter().collect();
 
-    println!("[start_screen_preview] FFmpeg process started, spawning reader thread");
+    // Convert explicitly so full-range captures aren't squeezed into limited range
+    if let Some(ref range) = opts.color_range {
+        filters.push(format!("scale=out_range={},format=yuv420p", range));
+    }
 
-    // Spawn a thread to read frames and emit events
-    thread::spawn(move || {
+    if opts.burn_timestamp.unwrap_or(false) {
+        filters.push(format!(
+            "drawtext={}text='%{{localtime\\:%Y-%m-%d %T}}':fontcolor=white:fontsize=24:box=1:boxcolor=black@0.5:boxborderw=6:x=w-tw-20:y=h-th-20",
+            drawtext_font_option()
+        ));
+    }
+
+    if filters.is_empty() {
+        None
+    } else {
+        Some(filters.join(","))
+    }
+}
+
+// Helper function to validate the requested recording color range
+fn recording_color_range(opts: &RecordingOptions) -> Result<Option<&str>, String> {
+    match opts.color_range.as_deref() {
+        None => Ok(None),
+        Some(range @ ("tv" | "pc")) => Ok(Some(range)),
+        Some(other) => Err(format!("Invalid color range: {} (expected \"tv\" or \"pc\")", other)),
+    }
+}
+
+// Helper function to validate the recording x264 preset and tune
+// Capture must keep up in realtime, so slower presets only suit small or static captures.
+// Sensible combinations: "ultrafast"/"superfast" with "zerolatency" for low-latency capture,
+// "veryfast"/"faster" with "stillimage" for text-heavy, mostly static screens (much smaller
+// files), and "animation" for flat UI content with motion.
+fn recording_x264_settings(opts: &RecordingOptions) -> Result<(&str, Option<&str>), String> {
+    const PRESETS: &[&str] = &[
+        "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow",
+    ];
+    const TUNES: &[&str] = &["film", "animation", "grain", "stillimage", "fastdecode", "zerolatency"];
+
+    let preset = opts.preset.as_deref().unwrap_or("ultrafast");
+    if !PRESETS.contains(&preset) {
+        return Err(format!("Invalid recording preset: {}", preset));
+    }
+    match opts.tune.as_deref() {
+        None => Ok((preset, None)),
+        Some(tune) if TUNES.contains(&tune) => Ok((preset, Some(tune))),
+        Some(tune) => Err(format!("Invalid recording tune: {}", tune)),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ScreenResolution {
+    width: i32,
+    height: i32,
+    refresh_rate: f64, // Main display refresh rate in Hz (30 when it can't be detected)
+}
+
+#[derive(Debug, Serialize)]
+struct CameraCapabilities {
+    native_width: i32,
+    native_height: i32,
+    supported_resolutions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct DeviceInfo {
+    index: i32,   // avfoundation index, enumeration order for dshow, PulseAudio source / ALSA card / v4l2 number on Linux
+    name: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct AudioVideoDevices {
+    video_devices: Vec<DeviceInfo>,
+    audio_devices: Vec<DeviceInfo>,
+}
+
+// Helper function to resolve a dshow device index into its name and duplicate ordinal
+// dshow selects devices by name, so identically named devices are disambiguated with
+// -video_device_number / -audio_device_number (the ordinal among devices sharing that name)
+fn resolve_dshow_device(devices: &[DeviceInfo], index: i32) -> Result<(String, usize), String> {
+    let device = devices
+        .iter()
+        .find(|d| d.index == index)
+        .ok_or_else(|| format!("Device index {} not found", index))?;
+
+    let ordinal = devices
+        .iter()
+        .filter(|d| d.index < index && d.name == device.name)
+        .count();
+
+    Ok((device.name.clone(), ordinal))
+}
+
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct ExportOptions {
+    resolution: Option<String>, // "720p", "1080p", or "source"
+    #[serde(default)]
+    source_width: Option<i32>,
+    #[serde(default)]
+    source_height: Option<i32>,
+    #[serde(default)]
+    progress_bar_overlay: Option<ProgressBarOverlay>, // Animated progress bar burned into the video
+    #[serde(default)]
+    target_width: Option<i32>,  // Exact output width (concat scales + pads every segment to this)
+    #[serde(default)]
+    target_height: Option<i32>, // Exact output height
+    #[serde(default)]
+    smart_cut: bool, // Trim: re-encode only the partial GOPs at the edges, stream-copy the middle
+    #[serde(default)]
+    ducking: Option<DuckingOptions>, // Concat with PiP: dim the main audio while the PiP track speaks
+    #[serde(default)]
+    concat_mode: Option<String>, // Concat: "demuxer" (stream copy), "filter" (single encode), or "auto" (default)
+    #[serde(default)]
+    tonemap: Option<bool>, // Convert HDR (PQ/HLG) sources to BT.709 SDR; SDR sources are left alone
+    #[serde(default)]
+    preserve_timestamps: Option<bool>, // Copy the source file's modified (and creation, where supported) time to the output
+    #[serde(default)]
+    hwaccel: Option<String>, // Trim decode: "auto", "cuda", "videotoolbox", ... or "none" (default)
+    #[serde(default)]
+    section_titles: Option<Vec<SectionTitle>>, // Title cards burned in over the export timeline
+    #[serde(default)]
+    optimize_for: Option<String>, // "streaming" (default), "editing", or "archival"
+    #[serde(default)]
+    separate_audio_tracks: Option<bool>, // Concat with PiP: keep main and PiP audio as separate streams
+    #[serde(default)]
+    resume: bool, // Concat: keep finished segments after a failure and reuse them on retry
+    #[serde(default)]
+    fill_mode: Option<String>, // Concat to a target size: "pad" (black bars, default) or "blur" (blurred fill)
+    #[serde(default)]
+    auto_orient: Option<bool>, // Trim: bake phone rotation metadata into the frames (default true)
+    #[serde(default)]
+    x264_params: Option<String>, // Passed to libx264 as -x264-params, e.g. "keyint=48:min-keyint=48:scenecut=0"
+    #[serde(default)]
+    video_only: Option<bool>, // Trim: drop the audio stream (-an)
+    #[serde(default)]
+    audio_only: Option<bool>, // Trim: drop the video stream (-vn); codec follows the output extension
+    #[serde(default)]
+    encoder: Option<String>, // Trim: "libx264" (default) or "prores_ks" (.mov intermediate for editors)
+    #[serde(default)]
+    profile: Option<String>, // ProRes profile: "proxy", "lt", "standard" (default), or "hq"
+    #[serde(default)]
+    scenecut_keyframes: Option<bool>, // Re-encodes: keyframes at scene cuts, capped interval, for faster seeking
+    #[serde(default)]
+    audio_copy: Option<bool>, // Trim: copy the source audio even when the video is re-encoded
+}
+
+// Helper function to decide whether a trim can stream-copy the source audio
+// Only codecs where every packet is independently decodable are copied, and only when the
+// first audio packet at or after the cut starts within one video frame of it, so the streams
+// stay in sync. Anything else, or a codec the output container can't hold, is re-encoded.
+fn audio_copy_is_safe(input_path: &str, output_path: &str, start_time: f64) -> bool {
+    let codec = probe_entry(input_path, "stream=codec_name", Some("a:0")).unwrap_or_default();
+    let codec = codec.lines().next().unwrap_or("").trim();
+    let codec_ok = match codec {
+        "aac" | "mp3" | "ac3" | "eac3" | "opus" | "flac" => true,
+        pcm if pcm.starts_with("pcm_") => !is_mp4_container(output_path) || output_path.to_lowercase().ends_with(".mov"),
+        _ => false,
+    };
+    if !codec_ok || !ffprobe_available() {
+        return false;
+    }
+
+    let frame_duration = probe_frame_rate(input_path)
+        .ok()
+        .filter(|fps| *fps > 0.0)
+        .map(|fps| 1.0 / fps)
+        .unwrap_or(1.0 / 30.0);
+    match probe_first_audio_packet(input_path, start_time) {
+        Ok(Some(pts)) => {
+            let offset = pts - start_time;
+            println!("[trim_video] First copied audio packet starts {:.3}s after the cut", offset);
+            offset <= frame_duration
+        }
+        Ok(None) => false,
+        Err(e) => {
+            println!("[trim_video] Could not probe audio packets: {}", e);
+            false
+        }
+    }
+}
+
+// Helper function to find the first audio packet that starts at or after a time
+// Only packets within a second of it are read, so long sources aren't scanned
+fn probe_first_audio_packet(input_path: &str, start_time: f64) -> Result<Option<f64>, String> {
+    let interval = format!("{}%{}", (start_time - 1.0).max(0.0), start_time + 1.0);
+    let ffprobe_path = get_ffprobe_path();
+    let output = output_with_timeout(Command::new(&ffprobe_path).args([
+        "-v", "error",
+        "-select_streams", "a:0",
+        "-read_intervals", &interval,
+        "-show_entries", "packet=pts_time",
+        "-of", "csv=p=0",
+        input_path,
+    ]))
+    .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("FFprobe error: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+        .filter(|pts| *pts >= start_time - 1e-6)
+        .fold(None, |first: Option<f64>, pts| Some(first.map_or(pts, |first| first.min(pts)))))
+}
+
+// Helper function to build ProRes codec args for an editing/mezzanine export
+// Returns (video codec args, audio codec args); ProRes is only written to .mov
+fn prores_codec_args(profile: Option<&str>, output_path: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let is_mov = std::path::Path::new(output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("mov"))
+        .unwrap_or(false);
+    if !is_mov {
+        return Err(format!("ProRes export requires a .mov output: {}", output_path));
+    }
+
+    // prores_ks profile numbers
+    let profile_number = match profile.unwrap_or("standard") {
+        "proxy" => "0",
+        "lt" => "1",
+        "standard" => "2",
+        "hq" => "3",
+        other => return Err(format!("Invalid ProRes profile (use proxy, lt, standard or hq): {}", other)),
+    };
+    if !ffmpeg_has_encoder("prores_ks") {
+        return Err("Encoder not available in this FFmpeg build: prores_ks".to_string());
+    }
+
+    let video = ["-c:v", "prores_ks", "-profile:v", profile_number, "-pix_fmt", "yuv422p10le"];
+    let audio = ["-c:a", "pcm_s16le"];
+    Ok((
+        video.iter().map(|arg| arg.to_string()).collect(),
+        audio.iter().map(|arg| arg.to_string()).collect(),
+    ))
+}
+
+// Helper function to fit a video stream onto a canvas of a different aspect ratio
+// "pad" letterboxes with black bars; "blur" fills the bars with a blurred, zoomed copy of
+// the same frame (the usual look for landscape clips in a vertical 9:16 export).
+fn build_canvas_fit_filter(
+    input: &str,
+    id: usize,
+    width: i32,
+    height: i32,
+    fill_mode: Option<&str>,
+) -> Result<String, CommandError> {
+    match fill_mode {
+        Some("pad") | None => Ok(format!(
+            "{input}scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+            input = input,
+            w = width,
+            h = height
+        )),
+        Some("blur") => Ok(format!(
+            "{input}split=2[fillbg{id}][fillfg{id}];\
+             [fillbg{id}]scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h},boxblur=20:5[fillblur{id}];\
+             [fillfg{id}]scale={w}:{h}:force_original_aspect_ratio=decrease[fillsrc{id}];\
+             [fillblur{id}][fillsrc{id}]overlay=(W-w)/2:(H-h)/2",
+            input = input,
+            id = id,
+            w = width,
+            h = height
+        )),
+        Some(other) => Err(CommandError::invalid_input(format!("Invalid fill_mode: {}", other))),
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct SectionTitle {
+    text: String,
+    start_time: f64, // Seconds into the exported video
+    duration: f64,
+}
+
+// Helper function to escape literal text for a drawtext `text` option
+// Escapes for the option parser first, then for the filtergraph parser
+fn escape_drawtext_text(text: &str) -> String {
+    let escape = |value: &str, special: &[char]| {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if special.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+    let option_level = escape(text, &['\\', '\'', ':']);
+    escape(&option_level, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+// Helper function to build one drawtext filter per section title
+// Titles showing at the same time are stacked downwards instead of drawn on top of each other.
+fn build_section_title_filters(titles: &[SectionTitle]) -> Result<Vec<String>, CommandError> {
+    let mut filters = Vec::new();
+    for (i, title) in titles.iter().enumerate() {
+        if title.text.trim().is_empty() {
+            return Err(CommandError::invalid_input(format!("Section title {} has no text", i + 1)));
+        }
+        if title.start_time < 0.0 || title.duration <= 0.0 {
+            return Err(CommandError::invalid_input(format!(
+                "Invalid section title timing for \"{}\": start {}s, duration {}s",
+                title.text, title.start_time, title.duration
+            )));
+        }
+
+        let end_time = title.start_time + title.duration;
+        let slot = titles[..i]
+            .iter()
+            .filter(|other| other.start_time < end_time && title.start_time < other.start_time + other.duration)
+            .count();
+
+        filters.push(format!(
+            "drawtext={}expansion=none:text={}:fontcolor=white:fontsize=h/20:box=1:boxcolor=black@0.6:boxborderw=12:x=(w-tw)/2:y=h/12+{}*(h/20+36):enable='between(t,{},{})'",
+            drawtext_font_option(),
+            escape_drawtext_text(&title.text),
+            slot,
+            title.start_time,
+            end_time
+        ));
+    }
+    Ok(filters)
+}
+
+// Helper function to copy a file's modification time (and creation time on Windows/macOS)
+// onto another file, so processed clips keep sorting by their original date
+fn copy_file_timestamps(source: &str, destination: &str) -> Result<(), String> {
+    let metadata = std::fs::metadata(source)
+        .map_err(|e| format!("Failed to read timestamps of {}: {}", source, e))?;
+    let modified = metadata
+        .modified()
+        .map_err(|e| format!("Modification time unavailable for {}: {}", source, e))?;
+
+    #[allow(unused_mut)]
+    let mut times = std::fs::FileTimes::new().set_modified(modified);
+
+    #[cfg(target_os = "windows")]
+    if let Ok(created) = metadata.created() {
+        use std::os::windows::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+
+    #[cfg(target_os = "macos")]
+    if let Ok(created) = metadata.created() {
+        use std::os::macos::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(destination)
+        .and_then(|file| file.set_times(times))
+        .map_err(|e| format!("Failed to set timestamps on {}: {}", destination, e))
+}
+
+// Helper function to apply preserve_timestamps after a successful export
+// A failure here only logs a warning since the export itself succeeded
+fn preserve_source_timestamps(opts: &ExportOptions, source: &str, destination: &str) {
+    if !opts.preserve_timestamps.unwrap_or(false) {
+        return;
+    }
+    match copy_file_timestamps(source, destination) {
+        Ok(()) => println!("[preserve_timestamps] Copied timestamps from {} to {}", source, destination),
+        Err(e) => println!("[preserve_timestamps] Warning: {}", e),
+    }
+}
+
+// Helper function to detect HDR video from the first video stream's transfer function
+// BT.2020 primaries alone aren't HDR (SDR wide-gamut files use them too); tone mapping
+// needs a PQ or HLG transfer.
+fn is_hdr_source(video_path: &str) -> bool {
+    let transfer = probe_entry(video_path, "stream=color_transfer", Some("v:0")).unwrap_or_default();
+    is_hdr_transfer(transfer.lines().next().unwrap_or(""))
+}
+
+fn is_hdr_transfer(transfer: &str) -> bool {
+    matches!(transfer.trim().trim_end_matches(','), "smpte2084" | "arib-std-b67")
+}
+
+// Helper function to read a video's rotation in clockwise degrees (0, 90, 180 or 270)
+// Older files carry a "rotate" tag; newer FFmpeg reports a display matrix, whose rotation
+// is counter-clockwise, so its sign is flipped.
+fn probe_rotation(video_path: &str) -> i32 {
+    if !ffprobe_available() {
+        return 0;
+    }
+
+    let tag = probe_entry(video_path, "stream_tags=rotate", Some("v:0"))
+        .ok()
+        .and_then(|value| value.lines().next().and_then(|line| line.trim().parse::<f64>().ok()));
+    let side_data = || {
+        probe_entry(video_path, "stream_side_data=rotation", Some("v:0"))
+            .ok()
+            .and_then(|value| value.lines().find_map(|line| line.trim().parse::<f64>().ok()))
+            .map(|rotation| -rotation)
+    };
+
+    let degrees = tag.or_else(side_data).unwrap_or(0.0).round() as i32;
+    // Snap to the nearest quarter turn
+    ((degrees.rem_euclid(360) + 45) / 90 % 4) * 90
+}
+
+// Helper function to build an HDR -> SDR (BT.709) tone mapping chain for a source
+// Returns None when tone mapping wasn't requested or the source is already SDR.
+fn hdr_tonemap_filter(video_path: &str, requested: Option<bool>) -> Result<Option<String>, CommandError> {
+    if !requested.unwrap_or(false) {
+        return Ok(None);
+    }
+    if !is_hdr_source(video_path) {
+        println!("[tonemap] {} is SDR, skipping tone mapping", video_path);
+        return Ok(None);
+    }
+    if !ffmpeg_has_filter("zscale") {
+        return Err(CommandError::new(ErrorCode::Failed, "HDR tone mapping requires an FFmpeg build with zscale (libzimg)"));
+    }
+
+    println!("[tonemap] Tone mapping HDR source {}", video_path);
+    Ok(Some(
+        "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p"
+            .to_string(),
+    ))
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct DuckingOptions {
+    #[serde(default)]
+    target_track: Option<usize>, // Composite: index of the track to duck (default: bottom-most); PiP exports always duck the main timeline
+    #[serde(default)]
+    threshold: Option<f64>, // Sidechain level that triggers ducking, 0.001-1 (default 0.05)
+    #[serde(default)]
+    ratio: Option<f64>,     // Compression ratio while ducking, 1-20 (default 8)
+}
+
+// Static weight applied to the ducked track when sidechaincompress isn't available
+const DUCKING_FALLBACK_WEIGHT: f64 = 0.5;
+
+// Helper function to check whether the bundled FFmpeg provides a filter
+fn ffmpeg_has_filter(name: &str) -> bool {
+    let ffmpeg_path = get_ffmpeg_path();
+    match output_with_timeout(Command::new(&ffmpeg_path).args(["-hide_banner", "-filters"])) {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some(name)),
+        Err(_) => false,
+    }
+}
+
+// Helper function to check whether this FFmpeg build includes an encoder
+fn ffmpeg_has_encoder(name: &str) -> bool {
+    let ffmpeg_path = get_ffmpeg_path();
+    match output_with_timeout(Command::new(&ffmpeg_path).args(["-hide_banner", "-encoders"])) {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some(name)),
+        Err(_) => false,
+    }
+}
+
+// Helper function to build a sidechain ducking chain
+// `main` is compressed whenever `key` is above the threshold; the key is padded with silence
+// so the compressor keeps running after a shorter commentary track ends.
+// Returns Ok(None) when sidechaincompress isn't available so callers can fall back to static weights.
+fn build_ducking_filter(
+    ducking: &DuckingOptions,
+    main: &str,
+    key: &str,
+    output: &str,
+) -> Result<Option<String>, String> {
+    let threshold = ducking.threshold.unwrap_or(0.05);
+    if !(0.000976563..=1.0).contains(&threshold) {
+        return Err(format!("Invalid ducking threshold: {} (expected 0.001-1)", threshold));
+    }
+    let ratio = ducking.ratio.unwrap_or(8.0);
+    if !(1.0..=20.0).contains(&ratio) {
+        return Err(format!("Invalid ducking ratio: {} (expected 1-20)", ratio));
+    }
+
+    if !ffmpeg_has_filter("sidechaincompress") {
+        println!("[ducking] sidechaincompress unavailable, using static weight {}", DUCKING_FALLBACK_WEIGHT);
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "{key}apad[duckkey];{main}[duckkey]sidechaincompress=threshold={threshold}:ratio={ratio}:attack=20:release=400{output}",
+        key = key,
+        main = main,
+        threshold = threshold,
+        ratio = ratio,
+        output = output
+    )))
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ProgressBarOverlay {
+    #[serde(default)]
+    position: Option<String>, // "top" or "bottom" (default)
+    #[serde(default)]
+    color: Option<String>,    // FFmpeg color name or hex, e.g. "white" or "#ff0000@0.8"
+    #[serde(default)]
+    height: Option<i32>,      // Bar height in pixels
+}
+
+// Helper function to validate a color before interpolating it into a filter graph
+// Accepts FFmpeg color names and hex values, optionally with an @alpha suffix
+fn is_valid_ffmpeg_color(color: &str) -> bool {
+    !color.is_empty()
+        && color
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '.' | '_'))
+}
+
+// Helper function to build a progress bar whose width grows with playback time
+// `elapsed_offset` is how far into the overall output this input starts (for concatenated segments)
+// drawbox only evaluates its size once, so the bar is a filled strip cropped from the frame that
+// slides in from the left via overlay's per-frame x expression. The result is a chain fragment.
+fn build_progress_bar_filter(
+    bar: &ProgressBarOverlay,
+    elapsed_offset: f64,
+    total_duration: f64,
+) -> Result<String, CommandError> {
+    if total_duration <= 0.0 {
+        return Err(CommandError::invalid_input("Cannot draw progress bar for an empty duration"));
+    }
+
+    let height = bar.height.unwrap_or(8);
+    if height <= 0 {
+        return Err(CommandError::invalid_input(format!("Invalid progress bar height: {}", height)));
+    }
+
+    let color = bar.color.clone().unwrap_or_else(|| "white".to_string());
+    if !is_valid_ffmpeg_color(&color) {
+        return Err(CommandError::invalid_input(format!("Invalid progress bar color: {}", color)));
+    }
+
+    let (crop_y, overlay_y) = match bar.position.as_deref() {
+        Some("top") => ("0".to_string(), "0".to_string()),
+        Some("bottom") | None => (format!("ih-{}", height), format!("H-{}", height)),
+        Some(other) => return Err(CommandError::invalid_input(format!("Invalid progress bar position: {}", other))),
+    };
+
+    Ok(format!(
+        "split[pbmain][pbsrc];\
+         [pbsrc]crop=w=iw:h={h}:x=0:y={crop_y},drawbox=x=0:y=0:w=iw:h=ih:color={color}:t=fill[pbbar];\
+         [pbmain][pbbar]overlay=x='min(0,-W+W*(t+{off})/{dur})':y={overlay_y}:eval=frame",
+        h = height,
+        crop_y = crop_y,
+        color = color,
+        off = elapsed_offset,
+        dur = total_duration,
+        overlay_y = overlay_y,
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TrackExportData {
+    path: String,
+    position_x: i32,
+    position_y: i32,
+    volume: f64,      // 0.0 to 1.0
+    opacity: f64,     // 0.0 to 1.0
+    width: i32,
+    height: i32,
+    z_index: i32,
+    #[serde(default)]
+    blend_mode: Option<String>, // "normal" (default), "screen", "multiply", "addition", ...
+    #[serde(default)]
+    fade_in: Option<f64>,  // Seconds to fade in from transparent/silent at the track's start
+    #[serde(default)]
+    fade_out: Option<f64>, // Seconds to fade out at the track's end
+    #[serde(default)]
+    language: Option<String>, // ISO 639-2 code for the track's audio stream with separate_audio_tracks
+}
+
+// Helper function to label separately mapped audio streams so players can switch between them
+// Each entry is (title, ISO 639-2 language); the first stream stays the default track.
+fn audio_stream_metadata_args(streams: &[(String, String)]) -> Vec<String> {
+    let mut args = Vec::new();
+    for (i, (title, language)) in streams.iter().enumerate() {
+        args.extend([
+            format!("-metadata:s:a:{}", i), format!("title={}", title),
+            format!("-metadata:s:a:{}", i), format!("language={}", language),
+            format!("-disposition:a:{}", i), if i == 0 { "default" } else { "0" }.to_string(),
+        ]);
+    }
+    args
+}
+
+// Helper function to count the audio streams in a file
+fn count_audio_streams(video_path: &str) -> Result<usize, String> {
+    if !ffprobe_available() {
+        return Ok(ffmpeg_probe(video_path)?
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == "audio")
+            .count());
+    }
+    Ok(probe_entry(video_path, "stream=index", Some("a"))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count())
+}
+
+// Helper function to check that an export kept every separate audio track
+fn verify_audio_stream_count(output_path: &str, expected: usize) -> Result<(), String> {
+    let actual = count_audio_streams(output_path)?;
+    if actual != expected {
+        return Err(format!("Expected {} audio streams in {}, found {}", expected, output_path, actual));
+    }
+    Ok(())
+}
+
+// Helper function to build a track's fade filters as (video, audio) chain suffixes
+// Tracks start at 0 on the composite timeline; with a render region the inputs are seeked,
+// so fade times are shifted by `render_start` and clipped to the part that is rendered.
+fn track_fade_filters(track: &TrackExportData, render_start: f64) -> Result<(String, String), String> {
+    let mut video = String::new();
+    let mut audio = String::new();
+
+    let mut add_fade = |kind: &str, start: f64, duration: f64| {
+        let end = start + duration - render_start;
+        if end <= 0.0 {
+            return; // Fade finished before the rendered region
+        }
+        let start = (start - render_start).max(0.0);
+        video.push_str(&format!(",fade=t={}:st={}:d={}:alpha=1", kind, start, end - start));
+        audio.push_str(&format!(",afade=t={}:st={}:d={}", kind, start, end - start));
+    };
+
+    if let Some(fade_in) = track.fade_in {
+        if fade_in < 0.0 {
+            return Err(format!("Invalid fade_in for {}: {}", track.path, fade_in));
+        }
+        if fade_in > 0.0 {
+            add_fade("in", 0.0, fade_in);
+        }
+    }
+    if let Some(fade_out) = track.fade_out {
+        if fade_out < 0.0 {
+            return Err(format!("Invalid fade_out for {}: {}", track.path, fade_out));
+        }
+        if fade_out > 0.0 {
+            let duration = probe_duration(&track.path)?;
+            add_fade("out", (duration - fade_out).max(0.0), fade_out.min(duration));
+        }
+    }
+
+    Ok((video, audio))
+}
+
+// FFmpeg blend modes accepted for composite tracks
+const BLEND_MODES: &[&str] = &[
+    "addition", "average", "burn", "darken", "difference", "divide", "dodge", "exclusion",
+    "glow", "grainextract", "grainmerge", "hardlight", "hardmix", "lighten", "linearlight",
+    "multiply", "negation", "overlay", "phoenix", "pinlight", "reflect", "screen",
+    "softlight", "subtract", "vividlight",
+];
+
+// Helper function to validate a track's blend mode (None means a plain alpha overlay)
+fn resolve_blend_mode(blend_mode: Option<&str>) -> Result<Option<String>, String> {
+    match blend_mode {
+        None | Some("") | Some("normal") => Ok(None),
+        Some("add") => Ok(Some("addition".to_string())),
+        Some(mode) if BLEND_MODES.contains(&mode) => Ok(Some(mode.to_string())),
+        Some(mode) => Err(format!("Unsupported blend mode: {}", mode)),
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct CompositeExportOptions {
+    resolution: Option<String>,
+    #[serde(default)]
+    source_width: Option<i32>,
+    #[serde(default)]
+    source_height: Option<i32>,
+    #[serde(default)]
+    background: Option<Background>, // Canvas background (default black)
+    #[serde(default)]
+    render_start: Option<f64>, // Only render from this time (seconds)
+    #[serde(default)]
+    render_end: Option<f64>,   // Only render up to this time (seconds)
+    #[serde(default)]
+    ducking: Option<DuckingOptions>, // Dim one track while the others are audible
+    #[serde(default)]
+    chunk_threshold: Option<usize>, // Render in chunks above this many tracks (default 8)
+    #[serde(default)]
+    tonemap: Option<bool>, // Convert HDR tracks to BT.709 SDR before compositing
+    #[serde(default)]
+    audio_sample_rate: Option<u32>, // Every track is resampled to this rate before mixing (default 48000)
+    #[serde(default)]
+    hwaccel: Option<String>, // Track decode: "auto", "cuda", "videotoolbox", ... or "none" (default)
+    #[serde(default)]
+    optimize_for: Option<String>, // "streaming" (default), "editing", or "archival"
+    #[serde(default)]
+    separate_audio_tracks: Option<bool>, // One output audio stream per track instead of a mix
+    #[serde(default)]
+    transparent_background: Option<bool>, // Alpha canvas instead of black (.webm VP9 or .mov ProRes 4444 only)
+    #[serde(default)]
+    x264_params: Option<String>, // Passed to libx264 as -x264-params (not allowed with transparent output)
+    #[serde(default)]
+    output_fps: Option<u32>, // Force the output frame rate (-r); the color background is generated at it too
+    #[serde(default)]
+    scenecut_keyframes: Option<bool>, // Keyframes at scene cuts with a capped interval, for faster seeking
+}
+
+// Above this many tracks the composite is rendered in chunks of COMPOSITE_CHUNK_SIZE
+const CHUNKED_COMPOSITE_THRESHOLD: usize = 8;
+const COMPOSITE_CHUNK_SIZE: usize = 4;
+
+// Helper function to build the filter for a background image input, cropped to fill the canvas
+fn composite_background_image_filter(input_index: usize, width: i32, height: i32) -> String {
+    format!(
+        "[{}:v]scale={}:{}:force_original_aspect_ratio=increase,crop={}:{},setsar=1,format=yuv420p",
+        input_index, width, height, width, height
+    )
+}
+
+// Helper function to pick the alpha-capable codec for a transparent composite
+// Returns (video codec args, audio codec args); only containers that can carry alpha are accepted
+fn alpha_output_codec(output_path: &str, bitrate: &str, audio_bitrate: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let extension = std::path::Path::new(output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    let (video, audio): (Vec<&str>, Vec<&str>) = match extension.as_deref() {
+        Some("webm") => (
+            vec!["-c:v", "libvpx-vp9", "-pix_fmt", "yuva420p", "-b:v", bitrate],
+            vec!["-c:a", "libopus", "-b:a", audio_bitrate],
+        ),
+        Some("mov") => (
+            vec!["-c:v", "prores_ks", "-profile:v", "4444", "-pix_fmt", "yuva444p10le"],
+            vec!["-c:a", "pcm_s16le"],
+        ),
+        _ => {
+            return Err(format!(
+                "Transparent background requires a .webm (VP9) or .mov (ProRes 4444) output: {}",
+                output_path
+            ))
+        }
+    };
+
+    let codec = &video[1];
+    if !ffmpeg_has_encoder(codec) {
+        return Err(format!("Encoder not available in this FFmpeg build: {}", codec));
+    }
+
+    Ok((
+        video.iter().map(|arg| arg.to_string()).collect(),
+        audio.iter().map(|arg| arg.to_string()).collect(),
+    ))
+}
+
+// Helper function for the encoding options shared by every final composite output
+// Transparent output must already have been validated with alpha_output_codec
+fn composite_output_args(
+    profile: &OutputProfile,
+    bitrate: &str,
+    audio_sample_rate: u32,
+    render_duration: Option<f64>,
+    output_fps: Option<u32>,
+    transparent: bool,
+    output_path: &str,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    let alpha_codec = if transparent { alpha_output_codec(output_path, bitrate, profile.audio_bitrate).ok() } else { None };
+    match alpha_codec {
+        Some((video_args, audio_args)) => {
+            args.extend(video_args);
+            args.extend(audio_args);
+            args.extend(["-ar".to_string(), audio_sample_rate.to_string()]);
+        }
+        None => {
+            args.extend(["-c:v".to_string(), "libx264".to_string()]);
+            args.extend(profile.video_args(["-b:v", bitrate]));
+            args.extend(profile.audio_args());
+            args.extend([
+                "-ar".to_string(), audio_sample_rate.to_string(),
+                "-pix_fmt".to_string(), "yuv420p".to_string(),
+            ]);
+        }
+    }
+
+    // Cap the output at the end of the requested region
+    if let Some(duration) = render_duration {
+        args.push("-t".to_string());
+        args.push(duration.to_string());
+    }
+
+    if let Some(fps) = output_fps {
+        args.push("-r".to_string());
+        args.push(fps.to_string());
+    }
+
+    // Put the moov atom at the front so MP4 output can start playing before fully downloaded
+    args.extend(profile.movflags_args(output_path));
+
+    args.push(output_path.to_string());
+    args
+}
+
+// A track prepared for compositing: its video filter chain (without pads) and overlay position
+struct CompositeLayer {
+    path: String,
+    video_filter: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    opacity: f64,
+    blend_mode: Option<String>, // Validated FFmpeg blend mode; None for a normal overlay
+}
+
+// Helper function to place one layer onto `base`, writing the result to `output`
+// Blended layers are combined with just the region of the base they cover, then overlaid back,
+// since the blend filter needs both inputs at the same size. `id` keeps pad names unique.
+fn composite_layer_stage(base: &str, layer_input: &str, layer: &CompositeLayer, id: usize, output: &str) -> String {
+    match layer.blend_mode {
+        None => format!("{}{}overlay=x={}:y={}{}", base, layer_input, layer.x, layer.y, output),
+        Some(ref mode) => format!(
+            "{base}split[bfull{id}][bcrop{id}];\
+             [bcrop{id}]format=gbrp,crop={w}:{h}:{x}:{y}[bregion{id}];\
+             {layer}format=gbrp[blayer{id}];\
+             [bregion{id}][blayer{id}]blend=all_mode={mode}:all_opacity={opacity},format=yuva420p[blended{id}];\
+             [bfull{id}][blended{id}]overlay=x={x}:y={y}{output}",
+            base = base,
+            layer = layer_input,
+            id = id,
+            w = layer.width,
+            h = layer.height,
+            x = layer.x,
+            y = layer.y,
+            mode = mode,
+            opacity = layer.opacity,
+            output = output
+        ),
+    }
+}
+
+// Helper function to check that an export reports the frame rate it was asked for
+fn verify_output_frame_rate(output_path: &str, fps: u32) -> Result<(), String> {
+    let actual_fps = probe_frame_rate(output_path)?;
+    if (actual_fps - fps as f64).abs() > 0.01 {
+        return Err(format!(
+            "Output frame rate {:.3} does not match requested {}",
+            actual_fps, fps
+        ));
+    }
+    Ok(())
+}
+
+// Audio half of a composite export's filter graph
+struct CompositeAudioGraph {
+    graph: String,          // Per-track chains ([i:a] -> [ai]) followed by the mix
+    outputs: Vec<String>,   // Labels to map: [aout], or one per track with separate tracks
+    metadata: Vec<String>,  // Stream titles/languages for separate tracks
+}
+
+// Helper function to resolve composite ducking against the tracks feeding the audio mix
+// `order` holds the original indices of those tracks in input order; the result is the
+// ducked track's position in it plus the sidechain chain (None: static weight fallback).
+fn resolve_composite_ducking(
+    ducking: Option<&DuckingOptions>,
+    tracks: &[TrackExportData],
+    order: &[usize],
+) -> Result<Option<(usize, Option<String>)>, CommandError> {
+    let Some(ducking) = ducking else { return Ok(None) };
+    if order.len() < 2 {
+        return Ok(None);
+    }
+
+    // Default: the bottom-most track that's part of the mix
+    let target = ducking
+        .target_track
+        .unwrap_or_else(|| order.iter().copied().min_by_key(|&i| tracks[i].z_index).unwrap_or(0));
+    if target >= tracks.len() {
+        return Err(CommandError::invalid_input(format!("Invalid ducking target_track: {}", target)));
+    }
+    let position = order.iter().position(|&i| i == target).ok_or_else(|| {
+        CommandError::invalid_input(format!("Ducking target_track {} has no audio", target))
+    })?;
+    let chain = build_ducking_filter(ducking, &format!("[a{}]", position), "[duckkeysrc]", "[ducked]")
+        .map_err(CommandError::invalid_input)?;
+    Ok(Some((position, chain)))
+}
+
+// Helper function to build the audio graph shared by the composite video and audio exports
+// Input i is tracks[i]; `ducking` comes from resolve_composite_ducking.
+fn build_composite_audio_graph(
+    tracks: &[&TrackExportData],
+    ducking: Option<&(usize, Option<String>)>,
+    separate_audio: bool,
+    audio_sample_rate: u32,
+    render_start: f64,
+) -> Result<CompositeAudioGraph, CommandError> {
+    let mut parts = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        // A ducked track without sidechain support is lowered statically
+        let volume = match ducking {
+            Some((target, None)) if *target == i => track.volume * DUCKING_FALLBACK_WEIGHT,
+            _ => track.volume,
+        };
+        let (_, audio_fade) = track_fade_filters(track, render_start).map_err(CommandError::invalid_input)?;
+        // Resample first so amix never has to reconcile 44.1k and 48k inputs
+        parts.push(format!("[{}:a]aresample={},volume={}{}[a{}]", i, audio_sample_rate, volume, audio_fade, i));
+    }
+
+    let audio_inputs: Vec<String> = (0..tracks.len()).map(|i| format!("[a{}]", i)).collect();
+    let mut outputs = vec!["[aout]".to_string()];
+    let mut metadata = Vec::new();
+    let mix = if separate_audio {
+        // Every track becomes its own stream; a ducked track is still keyed by a mix of the others
+        let mut mix = String::new();
+        outputs = audio_inputs.clone();
+        if let Some((target, Some(chain))) = ducking {
+            let others: Vec<usize> = (0..tracks.len()).filter(|i| i != target).collect();
+            for i in &others {
+                mix.push_str(&format!(";[a{}]asplit=2[a{}key][a{}out]", i, i, i));
+                outputs[*i] = format!("[a{}out]", i);
+            }
+            let keys: String = others.iter().map(|i| format!("[a{}key]", i)).collect();
+            let key_mix = if others.len() > 1 {
+                format!("{}amix=inputs={}:duration=longest,", keys, others.len())
+            } else {
+                keys
+            };
+            mix.push_str(&format!(";{}anull[duckkeysrc];{}", key_mix, chain));
+            outputs[*target] = "[ducked]".to_string();
+        }
+        let streams: Vec<(String, String)> = tracks
+            .iter()
+            .map(|track| {
+                let title = std::path::Path::new(&track.path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| track.path.clone());
+                (title, track.language.clone().unwrap_or_else(|| "und".to_string()))
+            })
+            .collect();
+        metadata = audio_stream_metadata_args(&streams);
+        mix
+    } else if let Some((target, Some(chain))) = ducking {
+        // Mix everything except the ducked track, use that mix as the sidechain key,
+        // then combine it with the ducked track
+        let others: Vec<String> = (0..tracks.len())
+            .filter(|i| i != target)
+            .map(|i| format!("[a{}]", i))
+            .collect();
+        let others_mix = if others.len() > 1 {
+            format!("{}amix=inputs={}:duration=longest,", others.join(""), others.len())
+        } else {
+            others[0].clone()
+        };
+        format!(
+            ";{}asplit=2[duckkeysrc][others];{};[ducked][others]amix=inputs=2:duration=longest[aout]",
+            others_mix, chain
+        )
+    } else if tracks.len() > 1 {
+        format!(";{}amix=inputs={}:duration=longest[aout]", audio_inputs.join(""), tracks.len())
+    } else {
+        ";[a0]anull[aout]".to_string()
+    };
+
+    Ok(CompositeAudioGraph {
+        graph: format!("{}{}", parts.join(";"), mix),
+        outputs,
+        metadata,
+    })
+}
+
+// Helper function to render a composite in chunks when one filter graph would be too large
+// Audio is mixed once in its own pass; video is built up bottom-to-top, each chunk overlaying
+// its tracks onto the previous (lossless) intermediate, so stacking order, positions and
+// timing match the single-pass graph.
+#[allow(clippy::too_many_arguments)]
+async fn export_composite_chunked(
+    layers: &[CompositeLayer],
+    background_filter: &str,
+    background_image: Option<&str>,
+    background_duration: f64,
+    output_size: (i32, i32),
+    audio_graph: &str,
+    audio_outputs: &[String],
+    audio_metadata: &[String],
+    render_start: f64,
+    render_duration: Option<f64>,
+    chunk_size: usize,
+    profile: &OutputProfile,
+    bitrate: &str,
+    audio_sample_rate: u32,
+    output_fps: Option<u32>,
+    output_path: &str,
+) -> Result<(), String> {
+    let temp_dir = std::env::temp_dir().join(format!(
+        "clipforge_composite_{}_{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0)
+    ));
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+
+    let result = async {
+        let push_track_input = |args: &mut Vec<String>, path: &str| {
+            if render_start > 0.0 {
+                args.push("-ss".to_string());
+                args.push(render_start.to_string());
+            }
+            args.push("-i".to_string());
+            args.push(path.to_string());
+        };
+        let push_duration = |args: &mut Vec<String>| {
+            if let Some(duration) = render_duration {
+                args.push("-t".to_string());
+                args.push(duration.to_string());
+            }
+        };
+
+        // Mix all track audio in one pass (the audio graph is small)
+        let audio_path = temp_dir.join("audio.mka").to_string_lossy().to_string();
+        let mut audio_args = vec!["-y".to_string()];
+        for layer in layers {
+            push_track_input(&mut audio_args, &layer.path);
+        }
+        audio_args.extend(["-filter_complex".to_string(), audio_graph.to_string()]);
+        for label in audio_outputs {
+            audio_args.extend(["-map".to_string(), label.clone()]);
+        }
+        audio_args.extend(["-c:a".to_string(), "pcm_s16le".to_string()]);
+        push_duration(&mut audio_args);
+        audio_args.push(audio_path.clone());
+        run_ffmpeg(audio_args, "export_composite_video").await?;
+
+        let chunk_count = layers.len().div_ceil(chunk_size);
+        let mut base_path: Option<String> = None;
+
+        for (chunk_index, chunk) in layers.chunks(chunk_size).enumerate() {
+            let is_last = chunk_index + 1 == chunk_count;
+            println!(
+                "[export_composite_video] Rendering chunk {}/{} ({} tracks)",
+                chunk_index + 1, chunk_count, chunk.len()
+            );
+
+            let mut args = vec!["-y".to_string()];
+            for layer in chunk {
+                push_track_input(&mut args, &layer.path);
+            }
+
+            // The base is the previous intermediate (already trimmed) or the canvas background
+            let base_index = chunk.len();
+            let mut parts = Vec::new();
+            match (&base_path, background_image) {
+                (Some(path), _) => {
+                    args.push("-i".to_string());
+                    args.push(path.clone());
+                    parts.push(format!("[{}:v]null[base]", base_index));
+                }
+                (None, Some(image_path)) => {
+                    args.extend([
+                        "-loop".to_string(), "1".to_string(),
+                        "-t".to_string(), background_duration.to_string(),
+                        "-i".to_string(), image_path.to_string(),
+                    ]);
+                    parts.push(format!(
+                        "{}[base]",
+                        composite_background_image_filter(base_index, output_size.0, output_size.1)
+                    ));
+                }
+                (None, None) => parts.push(format!("{}[base]", background_filter)),
+            }
+
+            // The final chunk also takes the premixed audio
+            let input_count = args.iter().filter(|arg| *arg == "-i").count();
+            if is_last {
+                args.push("-i".to_string());
+                args.push(audio_path.clone());
+            }
+
+            let mut previous = "[base]".to_string();
+            for (j, layer) in chunk.iter().enumerate() {
+                parts.push(format!("[{}:v]{}[v{}]", j, layer.video_filter, j));
+                let label = if j + 1 == chunk.len() { "[vout]".to_string() } else { format!("[tmp{}]", j) };
+                parts.push(composite_layer_stage(&previous, &format!("[v{}]", j), layer, j, &label));
+                previous = label;
+            }
+
+            args.push("-filter_complex".to_string());
+            args.push(parts.join(";"));
+            args.push("-map".to_string());
+            args.push("[vout]".to_string());
+
+            if is_last {
+                args.push("-map".to_string());
+                args.push(format!("{}:a", input_count));
+                args.extend(audio_metadata.iter().cloned());
+                args.extend(composite_output_args(profile, bitrate, audio_sample_rate, render_duration, output_fps, false, output_path));
+                run_ffmpeg(args, "export_composite_video").await?;
+            } else {
+                // Lossless intermediate so chunking doesn't add generation loss
+                let stage_path = temp_dir.join(format!("stage_{}.mkv", chunk_index)).to_string_lossy().to_string();
+                args.extend([
+                    "-an".to_string(),
+                    "-c:v".to_string(), "libx264".to_string(),
+                    "-preset".to_string(), "ultrafast".to_string(),
+                    "-qp".to_string(), "0".to_string(),
+                ]);
+                push_duration(&mut args);
+                args.push(stage_path.clone());
+                run_ffmpeg(args, "export_composite_video").await?;
+                base_path = Some(stage_path);
+            }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    result
+}
+
+// Composite canvas background, e.g. { "type": "color", "value": "#1e1e1e" }
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+enum Background {
+    Color(String), // FFmpeg color name or hex
+    Image(String), // Image file path, scaled to the canvas and looped for the whole export
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ClipSegment {
+    path: String,
+    #[serde(rename = "clipStart")]
+    clip_start: f64,
+    #[serde(rename = "clipEnd")]
+    clip_end: f64,
+}
+
+// Helper function to format a time in seconds as HH:MM:SS.mmm
+fn format_time(seconds: f64) -> String {
+    let hours = (seconds / 3600.0).floor() as u32;
+    let minutes = ((seconds % 3600.0) / 60.0).floor() as u32;
+    let secs = seconds % 60.0;
+    format!("{:02}:{:02}:{:06.3}", hours, minutes, secs)
+}
+
+// Helper function to build the FFmpeg args that stream-copy one clip's range into a concat segment
+// Audio that ends before the video (common with webcam captures) would pull every later
+// segment's sound early, so it's padded with silence and stopped at the video's end.
+fn concat_segment_args(clip: &ClipSegment, segment_path: &str, has_audio: bool, profile: &OutputProfile) -> Vec<String> {
+    let mut args = vec![
+        "-y".to_string(),
+        "-ss".to_string(), format_time(clip.clip_start),
+        "-i".to_string(), clip.path.clone(),
+        "-t".to_string(), format_time(clip.clip_end - clip.clip_start),
+        "-map".to_string(), "0:v:0".to_string(),
+        "-map".to_string(), "0:a:0?".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+    ];
+    if has_audio {
+        args.push("-af".to_string());
+        args.push("apad".to_string());
+        args.extend(profile.audio_args());
+        args.push("-shortest".to_string());
+    }
+    args.push(segment_path.to_string());
+    args
+}
+
+// Helper function to identify the source of a concat segment (file, range, size and mtime)
+// A resumed export only reuses a segment whose marker holds the same key, so edited
+// sources or changed ranges are cut again.
+fn concat_segment_key(clip: &ClipSegment) -> String {
+    let (size, modified) = std::fs::metadata(&clip.path)
+        .map(|metadata| {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis())
+                .unwrap_or(0);
+            (metadata.len(), modified)
+        })
+        .unwrap_or((0, 0));
+    format!("{}|{}|{}|{}|{}", clip.path, clip.clip_start, clip.clip_end, size, modified)
+}
+
+// Resume directories left behind by exports that were never retried are removed after this long
+const STALE_CONCAT_DIR_AGE: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+// Helper function to remove clipforge_concat_* directories that haven't been touched in a week
+fn remove_stale_concat_dirs() {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("clipforge_concat_") {
+            continue;
+        }
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map(|age| age > STALE_CONCAT_DIR_AGE)
+            .unwrap_or(false);
+        if stale {
+            println!("[concatenate_clips] Removing stale resume directory {:?}", entry.path());
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PipTrackData {
+    path: String,
+    offset: f64,        // Start time in seconds
+    duration: f64,      // Duration in seconds
+    volume: f64,        // 0.0 to 1.0
+    position: String,   // "top-left", "top-right", "bottom-left", "bottom-right"
+    size_percent: f64,  // 25, 33, or 50
+    #[serde(default)]
+    margin: Option<i32>, // Gap between the PiP and the frame edges in pixels (default 20)
+    #[serde(default)]
+    border_width: Option<i32>,     // Solid frame around the PiP in pixels, even (default none)
+    #[serde(default)]
+    border_color: Option<String>,  // FFmpeg color for the frame (default "white")
+    #[serde(default)]
+    rounded_corners: Option<i32>,  // Corner radius in pixels; corners outside it become transparent
+}
+
+// Helper function to build the overlay x/y options for a PiP corner and margin
+// Each coordinate is clamped to the frame so an oversized margin can't push the PiP off-screen
+fn pip_overlay_position(position: &str, margin: i32) -> String {
+    let near_x = format!("clip({},0,main_w-overlay_w)", margin);
+    let far_x = format!("clip(main_w-overlay_w-{},0,main_w-overlay_w)", margin);
+    let near_y = format!("clip({},0,main_h-overlay_h)", margin);
+    let far_y = format!("clip(main_h-overlay_h-{},0,main_h-overlay_h)", margin);
+    let (x, y) = match position {
+        "top-left" => (&near_x, &near_y),
+        "top-right" => (&far_x, &near_y),
+        "bottom-left" => (&near_x, &far_y),
+        _ => (&far_x, &far_y), // Default to bottom-right
+    };
+    format!("x='{}':y='{}'", x, y)
+}
+
+// Helper function to size the PiP against the main video (input 0), producing [main] and [pipscaled]
+// The width is a fraction of the main video's (the scale filter's reference input, rw), and the
+// height follows the PiP's own display aspect ratio so a 4:3 camera over 16:9 footage isn't
+// stretched. Sizes are kept even for yuv420p.
+fn build_pip_scale_filter(size_percent: f64) -> String {
+    format!(
+        "[0:v]split[main][mainref];[1:v][mainref]scale=w=trunc(rw*{}/2)*2:h=trunc(ow/dar/2)*2[pipscaled]",
+        size_percent / 100.0
+    )
+}
+
+// Helper function to build the PiP styling chain applied after scaling (border, then rounded corners)
+// Returns an empty string when no styling is requested
+fn pip_style_filter(pip: &PipTrackData) -> Result<String, String> {
+    let mut filters = Vec::new();
+
+    // pad rounds odd offsets down for yuv420p, which would leave the frame one pixel thinner on the
+    // top and left, so only even widths are accepted
+    let border_width = pip.border_width.unwrap_or(0);
+    if border_width < 0 || border_width % 2 != 0 {
+        return Err(format!("Invalid PiP border width (must be even): {}", border_width));
+    }
+    if border_width > 0 {
+        let color = pip.border_color.as_deref().unwrap_or("white");
+        if !is_valid_ffmpeg_color(color) {
+            return Err(format!("Invalid PiP border color: {}", color));
+        }
+        filters.push(format!(
+            "pad=iw+{}:ih+{}:{}:{}:color={}",
+            border_width * 2, border_width * 2, border_width, border_width, color
+        ));
+    }
+
+    let radius = pip.rounded_corners.unwrap_or(0);
+    if radius < 0 {
+        return Err(format!("Invalid PiP corner radius: {}", radius));
+    }
+    if radius > 0 {
+        // Alpha is 0 outside a circle of radius R in each corner box, 255 everywhere else;
+        // overlay then blends the transparent corners against the main video
+        filters.push("format=yuva420p".to_string());
+        filters.push(format!(
+            "geq=lum='p(X,Y)':cb='p(X,Y)':cr='p(X,Y)':a='if(gt(abs(W/2-X),W/2-{r})*gt(abs(H/2-Y),H/2-{r}),if(lte(hypot({r}-(W/2-abs(W/2-X)),{r}-(H/2-abs(H/2-Y))),{r}),255,0),255)'",
+            r = radius
+        ));
+    }
+
+    Ok(filters.join(","))
+}
+
+// Current project file schema version (bump when the layout changes incompatibly)
+const PROJECT_FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProjectFile {
+    version: u32,
+    canvas_width: i32,
+    canvas_height: i32,
+    tracks: Vec<TrackExportData>,
+    #[serde(default)]
+    clips: Vec<ClipSegment>,
+    #[serde(default)]
+    pip_track: Option<PipTrackData>,
+    #[serde(default)]
+    relative_paths: bool, // Media paths are stored relative to the project file's directory
+}
+
+#[derive(Debug, Serialize)]
+struct LoadedProject {
+    project: ProjectFile,
+    missing_media: Vec<String>, // Referenced media paths that no longer exist
+}
+
+// Helper function to list every media path a project references
+fn project_media_paths(project: &ProjectFile) -> Vec<String> {
+    let mut paths: Vec<String> = project.tracks.iter().map(|t| t.path.clone()).collect();
+    paths.extend(project.clips.iter().map(|c| c.path.clone()));
+    if let Some(ref pip) = project.pip_track {
+        paths.push(pip.path.clone());
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// Helper function to rewrite every media path in a project
+fn map_project_paths(project: &mut ProjectFile, f: impl Fn(&str) -> String) {
+    for track in project.tracks.iter_mut() {
+        track.path = f(&track.path);
+    }
+    for clip in project.clips.iter_mut() {
+        clip.path = f(&clip.path);
+    }
+    if let Some(ref mut pip) = project.pip_track {
+        pip.path = f(&pip.path);
+    }
+}
+
+// Helper function to express `path` relative to `base_dir` (using ".." where needed)
+// Returns None when no relative path exists (e.g. different Windows drives)
+fn relative_path(path: &std::path::Path, base_dir: &std::path::Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base_dir.components().collect();
+
+    // Prefixes/roots must match for a relative path to make sense
+    if path_components.first() != base_components.first() {
+        return None;
+    }
+
+    let common = path_components
+        .iter()
+        .zip(base_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component.as_os_str());
+    }
+    Some(relative)
+}
+
+// Helper function to get the directory a project file lives in (as an absolute path)
+fn project_dir(project_path: &str) -> PathBuf {
+    let path = std::path::Path::new(project_path);
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    };
+    absolute.parent().map(|p| p.to_path_buf()).unwrap_or_default()
+}
+
+// Helper function to read a project file and resolve relative media paths to absolute ones
+fn read_project_file(path: &str) -> Result<ProjectFile, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read project file: {}", e))?;
+    let mut project: ProjectFile = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid project file: {}", e))?;
+
+    if project.version > PROJECT_FILE_VERSION {
+        return Err(format!(
+            "Project file version {} is newer than supported version {}",
+            project.version, PROJECT_FILE_VERSION
+        ));
+    }
+
+    // Relative paths are resolved against the project's directory regardless of the flag,
+    // so hand-edited project files work too
+    let base_dir = project_dir(path);
+    map_project_paths(&mut project, |p| {
+        let media_path = std::path::Path::new(p);
+        if media_path.is_absolute() {
+            p.to_string()
+        } else {
+            base_dir.join(media_path).to_string_lossy().to_string()
+        }
+    });
+    project.relative_paths = false;
+
+    Ok(project)
+}
+
+// Helper function to search directories (recursively, a few levels deep) for a file name
+fn find_file_by_name(dir: &std::path::Path, file_name: &std::ffi::OsStr, depth: u32) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let mut subdirs = Vec::new();
+
+    for entry in entries.flatten() {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            subdirs.push(entry_path);
+        } else if entry_path.file_name() == Some(file_name) {
+            return Some(entry_path);
+        }
+    }
+
+    if depth == 0 {
+        return None;
+    }
+
+    subdirs
+        .iter()
+        .find_map(|subdir| find_file_by_name(subdir, file_name, depth - 1))
+}
+
+#[tauri::command]
+fn save_project(
+    path: String,
+    tracks: Vec<TrackExportData>,
+    canvas_width: i32,
+    canvas_height: i32,
+    clips: Option<Vec<ClipSegment>>,
+    pip_track: Option<PipTrackData>,
+    relative_paths: Option<bool>,
+) -> Result<String, String> {
+    println!("[save_project] Saving project with {} tracks to {}", tracks.len(), path);
+
+    let relative_paths = relative_paths.unwrap_or(false);
+    let mut project = ProjectFile {
+        version: PROJECT_FILE_VERSION,
+        canvas_width,
+        canvas_height,
+        tracks,
+        clips: clips.unwrap_or_default(),
+        pip_track,
+        relative_paths,
+    };
+
+    // Store media relative to the project folder so the folder can be moved as a unit
+    if relative_paths {
+        let base_dir = project_dir(&path);
+        map_project_paths(&mut project, |p| {
+            relative_path(std::path::Path::new(p), &base_dir)
+                .map(|rel| rel.to_string_lossy().to_string())
+                .unwrap_or_else(|| p.to_string())
+        });
+    }
+
+    let json = serde_json::to_string_pretty(&project)
+        .map_err(|e| format!("Failed to serialize project: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write project file: {}", e))?;
+
+    println!("[save_project] Project saved");
+    Ok(path)
+}
+
+#[tauri::command]
+fn load_project(path: String) -> Result<LoadedProject, String> {
+    println!("[load_project] Loading project from {}", path);
+
+    let project = read_project_file(&path)?;
+
+    let missing_media: Vec<String> = project_media_paths(&project)
+        .into_iter()
+        .filter(|p| !std::path::Path::new(p).exists())
+        .collect();
+
+    if !missing_media.is_empty() {
+        println!("[load_project] Missing media: {:?}", missing_media);
+    }
+
+    Ok(LoadedProject {
+        project,
+        missing_media,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct RelocatedMedia {
+    original: String,
+    resolved: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RelocationReport {
+    project: ProjectFile,           // Project with relocated paths applied (not saved)
+    resolved: Vec<RelocatedMedia>,
+    missing: Vec<String>,
+}
+
+#[tauri::command]
+fn relocate_media(project_path: String, search_dirs: Vec<String>) -> Result<RelocationReport, String> {
+    println!("[relocate_media] Relocating media for {} in {:?}", project_path, search_dirs);
+
+    let mut project = read_project_file(&project_path)?;
+
+    let mut resolved = Vec::new();
+    let mut missing = Vec::new();
+    for media_path in project_media_paths(&project) {
+        if std::path::Path::new(&media_path).exists() {
+            continue;
+        }
+
+        let found = std::path::Path::new(&media_path).file_name().and_then(|file_name| {
+            search_dirs
+                .iter()
+                .find_map(|dir| find_file_by_name(std::path::Path::new(dir), file_name, 3))
+        });
+
+        match found {
+            Some(new_path) => resolved.push(RelocatedMedia {
+                original: media_path,
+                resolved: new_path.to_string_lossy().to_string(),
+            }),
+            None => missing.push(media_path),
+        }
+    }
+
+    map_project_paths(&mut project, |p| {
+        resolved
+            .iter()
+            .find(|r| r.original == p)
+            .map(|r| r.resolved.clone())
+            .unwrap_or_else(|| p.to_string())
+    });
+
+    println!("[relocate_media] Resolved {}, still missing {}", resolved.len(), missing.len());
+    Ok(RelocationReport {
+        project,
+        resolved,
+        missing,
+    })
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct ExportJobStatus {
+    job_id: String,
+    kind: String, // "trim", "concat", or "composite"
+    percent: f64, // 0-100; trim and composite exports follow FFmpeg's position, concat advances per segment
+    #[serde(skip)]
+    sequence: u64, // Start order; job ids don't sort numerically ("trim-10" < "trim-9")
+}
+
+static NEXT_EXPORT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+// Registers an export in EXPORT_JOBS for as long as the guard lives,
+// so the entry is removed on every return path (including errors)
+struct ExportJobGuard {
+    job_id: String,
+}
+
+impl ExportJobGuard {
+    fn register(kind: &str) -> Self {
+        let sequence = NEXT_EXPORT_JOB_ID.fetch_add(1, Ordering::SeqCst);
+        let job_id = format!("{}-{}", kind, sequence);
+        EXPORT_JOBS.lock().unwrap().insert(
+            job_id.clone(),
+            ExportJobStatus {
+                job_id: job_id.clone(),
+                kind: kind.to_string(),
+                percent: 0.0,
+                sequence,
+            },
+        );
+        println!("[export_jobs] Started {}", job_id);
+        ExportJobGuard { job_id }
+    }
+
+    fn set_percent(&self, percent: f64) {
+        set_export_job_percent(&self.job_id, percent);
+    }
+
+    // For progress readers running on their own thread, which can't borrow the guard
+    fn percent_setter(&self) -> impl Fn(f64) + Send + 'static {
+        let job_id = self.job_id.clone();
+        move |percent| set_export_job_percent(&job_id, percent)
+    }
+}
+
+fn set_export_job_percent(job_id: &str, percent: f64) {
+    if let Some(job) = EXPORT_JOBS.lock().unwrap().get_mut(job_id) {
+        job.percent = percent.clamp(0.0, 100.0);
+    }
+}
+
+impl Drop for ExportJobGuard {
+    fn drop(&mut self) {
+        EXPORT_JOBS.lock().unwrap().remove(&self.job_id);
+        println!("[export_jobs] Finished {}", self.job_id);
+    }
+}
+
+#[tauri::command]
+fn is_export_running() -> bool {
+    !EXPORT_JOBS.lock().unwrap().is_empty()
+}
+
+#[tauri::command]
+fn list_active_exports() -> Vec<ExportJobStatus> {
+    let mut jobs: Vec<ExportJobStatus> = EXPORT_JOBS.lock().unwrap().values().cloned().collect();
+    jobs.sort_by_key(|job| job.sequence);
+    jobs
+}
+
+#[derive(Debug, Serialize)]
+struct ClipAroundResult {
+    output_path: String,
+    in_point: f64,  // Actual start used after clamping (seconds)
+    out_point: f64, // Actual end used after clamping (seconds)
+}
+
+#[tauri::command]
+async fn clip_around(
+    input_path: String,
+    output_path: String,
+    center_time: f64,
+    before: f64,
+    after: f64,
+    export_options: Option<ExportOptions>,
+    window: tauri::Window
+) -> Result<ClipAroundResult, CommandError> {
+    println!(
+        "[clip_around] {} around {}s (-{}s / +{}s)",
+        input_path, center_time, before, after
+    );
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(CommandError::invalid_input(format!("Input file not found: {}", input_path)));
+    }
+    if before < 0.0 || after < 0.0 {
+        return Err(CommandError::invalid_input(format!("Invalid clip window: -{}s / +{}s", before, after)));
+    }
+
+    // Clamp the window to the file so "last 30 seconds" near the start/end still works
+    let duration = probe_duration(&input_path)?;
+    let center_time = center_time.clamp(0.0, duration);
+    let in_point = (center_time - before).max(0.0);
+    let out_point = (center_time + after).min(duration);
+    if out_point <= in_point {
+        return Err(CommandError::invalid_input("Clip window is empty"));
+    }
+    println!("[clip_around] Clamped range: {}s to {}s", in_point, out_point);
+
+    let output_path = trim_video(input_path, output_path, in_point, out_point, export_options, window).await?;
+
+    Ok(ClipAroundResult {
+        output_path,
+        in_point,
+        out_point,
+    })
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct TrimProgress {
+    percent: f64, // 0-100
+    time: f64,    // Seconds of output encoded so far
+}
+
+// Helper function to pull the "time=HH:MM:SS.xx" position out of an FFmpeg stats line
+fn parse_ffmpeg_progress_time(line: &str) -> Option<f64> {
+    let rest = &line[line.rfind("time=")? + "time=".len()..];
+    let timestamp = rest.split_whitespace().next()?;
+    let parts: Vec<f64> = timestamp.split(':').filter_map(|p| p.parse::<f64>().ok()).collect();
+    match parts.as_slice() {
+        [hours, minutes, seconds] => Some(hours * 3600.0 + minutes * 60.0 + seconds),
+        _ => None,
+    }
+}
+
+// Helper function to turn FFmpeg's stderr stats into progress updates for an output of `duration`
+// Runs on its own thread and reads until EOF, so the pipe never fills and stalls FFmpeg.
+// Stats lines end in '\r' rather than '\n', so the stream is split on both. `on_progress`
+// gets (percent, seconds encoded) a few times per second. The thread returns the last few
+// log lines (not stats), which carry the failure reason when FFmpeg exits with an error.
+fn spawn_ffmpeg_progress_reader(
+    stderr: std::process::ChildStderr,
+    duration: f64,
+    on_progress: impl Fn(f64, f64) + Send + 'static,
+) -> std::thread::JoinHandle<String> {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    std::thread::spawn(move || {
+        let mut stderr = stderr;
+        let mut buffer = [0u8; 4096];
+        let mut line = Vec::new();
+        let mut last_emit: Option<Instant> = None;
+        let mut tail: std::collections::VecDeque<String> = std::collections::VecDeque::new();
+
+        loop {
+            let n = match stderr.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            for &byte in &buffer[..n] {
+                if byte != b'\r' && byte != b'\n' {
+                    line.push(byte);
+                    continue;
+                }
+                let text = String::from_utf8_lossy(&line).to_string();
+                line.clear();
+
+                let Some(time) = parse_ffmpeg_progress_time(&text) else {
+                    if !text.trim().is_empty() {
+                        if tail.len() == 5 {
+                            tail.pop_front();
+                        }
+                        tail.push_back(text);
+                    }
+                    continue;
+                };
+                if last_emit.is_some_and(|t| t.elapsed() < Duration::from_millis(250)) {
+                    continue;
+                }
+                last_emit = Some(Instant::now());
+                let percent = if duration > 0.0 { (time / duration * 100.0).clamp(0.0, 100.0) } else { 0.0 };
+                on_progress(percent, time);
+            }
+        }
+
+        // The final message may not end in a newline
+        let text = String::from_utf8_lossy(&line).to_string();
+        if !text.trim().is_empty() {
+            if tail.len() == 5 {
+                tail.pop_front();
+            }
+            tail.push_back(text);
+        }
+        Vec::from(tail).join("\n")
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct TrimByFramesResult {
+    output_path: String,
+    start_time: f64,   // Seconds the start frame mapped to
+    end_time: f64,     // Seconds just after the end frame
+    fps: f64,          // Frame rate used for the mapping
+    approximate: bool, // Source is variable frame rate, so frame numbers are only approximate
+}
+
+#[tauri::command]
+async fn trim_by_frames(
+    input_path: String,
+    output_path: String,
+    start_frame: u64,
+    end_frame: u64,
+    export_options: Option<ExportOptions>,
+    window: tauri::Window
+) -> Result<TrimByFramesResult, CommandError> {
+    println!("[trim_by_frames] {} frames {} to {}", input_path, start_frame, end_frame);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(CommandError::invalid_input(format!("Input file not found: {}", input_path)));
+    }
+    if end_frame < start_frame {
+        return Err(CommandError::invalid_input(format!(
+            "end_frame ({}) must not be before start_frame ({})",
+            end_frame, start_frame
+        )));
+    }
+
+    // VFR files have no fixed frame duration, so map through their average rate instead
+    let mut fps = probe_frame_rate(&input_path)?;
+    let mut approximate = false;
+    if let Ok(report) = detect_vfr(input_path.clone()).await {
+        if report.is_vfr && report.average_fps > 0.0 {
+            println!(
+                "[trim_by_frames] Warning: variable frame rate source, frame-to-time mapping is approximate ({:.3} fps average)",
+                report.average_fps
+            );
+            fps = report.average_fps;
+            approximate = true;
+        }
+    }
+
+    // The range is inclusive: the end frame is kept, so the cut lands just after it
+    let duration = probe_duration(&input_path)?;
+    let start_time = start_frame as f64 / fps;
+    let end_time = ((end_frame + 1) as f64 / fps).min(duration);
+    if start_time >= duration {
+        return Err(CommandError::invalid_input(format!("start_frame {} is past the end of the video", start_frame)));
+    }
+    println!("[trim_by_frames] Mapped to {}s - {}s at {} fps", start_time, end_time, fps);
+
+    let output_path = trim_video(input_path, output_path, start_time, end_time, export_options, window).await?;
+
+    Ok(TrimByFramesResult {
+        output_path,
+        start_time,
+        end_time,
+        fps,
+        approximate,
+    })
+}
+
+#[tauri::command]
+async fn trim_video(
+    input_path: String,
+    output_path: String,
+    start_time: f64,
+    end_time: f64,
+    export_options: Option<ExportOptions>,
+    window: tauri::Window
+) -> Result<String, CommandError> {
+    println!("[trim_video] Starting trim operation");
+    println!("[trim_video] Input: {}", input_path);
+    println!("[trim_video] Output: {}", output_path);
+    println!("[trim_video] Start time: {}", start_time);
+    println!("[trim_video] End time: {}", end_time);
+
+    let job = ExportJobGuard::register("trim");
+
+    let start_str = format_time(start_time);
+    let duration = end_time - start_time;
+    println!("[trim_video] Start string: {}", start_str);
+    println!("[trim_video] Duration: {}", duration);
+
+    // Parse export options
+    let opts = export_options.unwrap_or_else(|| ExportOptions {
+        resolution: Some("source".to_string()),
+        ..Default::default()
+    });
+
+    // Determine resolution and bitrate for export
+    let (should_scale, scale_filter, bitrate) = match opts.resolution.as_deref() {
+        Some("720p") => {
+            println!("[trim_video] Exporting at 720p resolution");
+            (true, "scale=1280:720", "2500k")
+        }
+        Some("1080p") => {
+            println!("[trim_video] Exporting at 1080p resolution");
+            (true, "scale=1920:1080", "5000k")
+        }
+        Some("source") | None => {
+            println!("[trim_video] Exporting at source resolution (no scaling)");
+            (false, "", "8000k")
+        }
+        Some(res) => {
+            return Err(CommandError::invalid_input(format!("Invalid resolution: {}", res)));
+        }
+    };
+
+    let video_only = opts.video_only.unwrap_or(false);
+    let audio_only = opts.audio_only.unwrap_or(false);
+    if video_only && audio_only {
+        return Err(CommandError::invalid_input("video_only and audio_only cannot both be set"));
+    }
+
+    let prores = match opts.encoder.as_deref().unwrap_or("libx264") {
+        "libx264" => None,
+        "prores_ks" => {
+            if audio_only {
+                return Err(CommandError::invalid_input("encoder \"prores_ks\" cannot be combined with audio_only"));
+            }
+            if opts.x264_params.is_some() {
+                return Err(CommandError::invalid_input("x264_params cannot be combined with encoder \"prores_ks\""));
+            }
+            Some(prores_codec_args(opts.profile.as_deref(), &output_path).map_err(CommandError::invalid_input)?)
+        }
+        other => return Err(CommandError::invalid_input(format!("Invalid encoder: {}", other))),
+    };
+
+    let tonemap = if audio_only { None } else { hdr_tonemap_filter(&input_path, opts.tonemap)? };
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+
+    // Rotated phone footage is re-encoded upright instead of copied sideways. FFmpeg's
+    // autorotation turns the decoded frames and drops the display matrix on re-encode,
+    // so players don't rotate the upright frames a second time.
+    let reorient = opts.auto_orient.unwrap_or(true) && !audio_only && probe_rotation(&input_path) != 0;
+    if reorient {
+        println!("[trim_video] Re-encoding rotated source upright");
+    }
+
+    // Smart cut only applies when the video would otherwise be stream-copied
+    // ProRes always transcodes the whole range
+    let mut force_reencode = prores.is_some() || reorient;
+    if opts.smart_cut
+        && prores.is_none()
+        && !video_only
+        && !audio_only
+        && !should_scale
+        && opts.progress_bar_overlay.is_none()
+        && opts.section_titles.is_none()
+        && tonemap.is_none()
+        && !reorient
+    {
+        match smart_cut_trim(&input_path, &output_path, start_time, end_time, &profile).await {
+            Ok(true) => {
+                verify_output_file(&output_path)?;
+                preserve_source_timestamps(&opts, &input_path, &output_path);
+                println!("[trim_video] Smart cut completed: {}", output_path);
+                return Ok(output_path);
+            }
+            Ok(false) => {
+                println!("[trim_video] Smart cut not possible, falling back to full re-encode");
+                force_reencode = true;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Build FFmpeg arguments
+    let source_path = input_path.clone();
+    let mut args = vec![
+        "-y".to_string(),                        // Overwrite output file
+        "-ss".to_string(), start_str,            // Start time
+        "-i".to_string(), input_path,            // Input file
+        "-t".to_string(), duration.to_string(),  // Duration
+    ];
+
+    // Collect video filters (tone mapping, scaling and/or overlays)
+    let mut video_filters: Vec<String> = tonemap.into_iter().collect();
+    if should_scale {
+        video_filters.push(scale_filter.to_string());
+    }
+    if let Some(ref bar) = opts.progress_bar_overlay {
+        println!("[trim_video] Burning progress bar overlay: {:?}", bar);
+        video_filters.push(build_progress_bar_filter(bar, 0.0, duration)?);
+    }
+    if let Some(ref titles) = opts.section_titles {
+        println!("[trim_video] Burning {} section titles", titles.len());
+        video_filters.extend(build_section_title_filters(titles)?);
+    }
+    if audio_only {
+        // Nothing to draw on; the video stream is dropped entirely
+        video_filters.clear();
+    }
+
+    if !video_filters.is_empty() {
+        args.push("-vf".to_string());
+        args.push(video_filters.join(","));
+    }
+
+    // Add encoding options
+    if audio_only {
+        println!("[trim_video] Dropping video stream");
+        args.push("-vn".to_string());
+        args.extend(audio_only_codec_args(&output_path, "192k")?);
+    } else if let Some((ref video_codec, ref audio_codec)) = prores {
+        println!("[trim_video] Encoding ProRes ({})", opts.profile.as_deref().unwrap_or("standard"));
+        args.extend(video_codec.iter().cloned());
+        args.extend(audio_codec.iter().cloned());
+    } else if !video_filters.is_empty() || force_reencode {
+        // Re-encode when filtering (or when a frame-accurate cut was requested)
+        args.push("-c:v".to_string());
+        args.push("libx264".to_string());
+        args.extend(profile.video_args(["-b:v", bitrate]));
+        if opts.audio_copy.unwrap_or(false) && !video_only {
+            if audio_copy_is_safe(&source_path, &output_path, start_time) {
+                println!("[trim_video] Copying source audio");
+                args.push("-c:a".to_string());
+                args.push("copy".to_string());
+            } else {
+                println!("[trim_video] Source audio can't be cut cleanly without re-encoding; re-encoding it");
+            }
+        }
+    } else {
+        // Copy codec for source resolution (fast)
+        args.push("-c".to_string());
+        args.push("copy".to_string());
+    }
+    if video_only {
+        println!("[trim_video] Dropping audio stream");
+        args.push("-an".to_string());
+    }
+
+    args.push("-avoid_negative_ts".to_string());
+    args.push("make_zero".to_string());
+
+    // Put the moov atom at the front so MP4 output can start playing before fully downloaded
+    args.extend(profile.movflags_args(&output_path));
+
+    args.push(output_path.clone());
+
+    // Hardware decode only matters when re-encoding (stream copy never decodes)
+    let mut software_args = None;
+    if !video_filters.is_empty() || force_reencode {
+        let hwaccel_args = resolve_hwaccel_args(opts.hwaccel.as_deref());
+        if !hwaccel_args.is_empty() {
+            software_args = Some(args.clone());
+            args.splice(1..1, hwaccel_args);
+        }
+    }
+
+    println!("[trim_video] FFmpeg args: {:?}", args);
+
+    // stderr is piped for progress; the reader thread drains it so FFmpeg never blocks on it
+    println!("[trim_video] Spawning FFmpeg process...");
+    let ffmpeg_path = get_ffmpeg_path();
+    let mut child = Command::new(&ffmpeg_path)
+        .args(&args)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            let err = CommandError::spawn_failed(e, "Failed to start FFmpeg");
+            println!("[trim_video] ERROR: {}", err);
+            err
+        })?;
+
+    let progress_reader = child
+        .stderr
+        .take()
+        .map(|stderr| {
+            let set_percent = job.percent_setter();
+            let window = window.clone();
+            spawn_ffmpeg_progress_reader(stderr, duration, move |percent, time| {
+                set_percent(percent);
+                let _ = window.emit("trim-progress", TrimProgress { percent, time });
+            })
+        });
+
+    // Wait for FFmpeg to finish without blocking the main thread
+    println!("[trim_video] Waiting for FFmpeg to complete...");
+    let (status, stderr_tail) = tokio::task::spawn_blocking(move || {
+        let status = child.wait();
+        let tail = progress_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        status.map(|status| (status, tail))
+    })
+        .await
+        .map_err(|e| {
+            let err_msg = format!("Task join error: {}", e);
+            println!("[trim_video] ERROR: {}", err_msg);
+            err_msg
+        })?
+        .map_err(|e| {
+            let err_msg = format!("Failed to wait for FFmpeg: {}", e);
+            println!("[trim_video] ERROR: {}", err_msg);
+            err_msg
+        })?;
+
+    let succeeded = match software_args {
+        _ if status.success() => true,
+        Some(software_args) => {
+            println!("[trim_video] Warning: hardware decode failed ({}), retrying with software decode: {}", status, stderr_tail);
+            let set_percent = job.percent_setter();
+            let window = window.clone();
+            run_ffmpeg_with_progress(software_args, "trim_video", duration, move |percent| {
+                set_percent(percent);
+                let _ = window.emit("trim-progress", TrimProgress { percent, time: percent / 100.0 * duration });
+            })
+            .await?;
+            true
+        }
+        None => false,
+    };
+
+    if succeeded {
+        println!("[trim_video] FFmpeg completed successfully!");
+        let _ = window.emit("trim-progress", TrimProgress { percent: 100.0, time: duration });
+        verify_output_file(&output_path)?;
+        preserve_source_timestamps(&opts, &source_path, &output_path);
+        println!("[trim_video] Output file: {}", output_path);
+        Ok(output_path)
+    } else {
+        let err_msg = format!("FFmpeg exited with status: {}", status);
+        println!("[trim_video] ERROR: {}\n{}", err_msg, stderr_tail);
+        Err(CommandError::new(ErrorCode::NonZeroExit, err_msg).with_detail(stderr_tail))
+    }
+}
+
+#[tauri::command]
+async fn trim_video_bytes(input_bytes: Vec<u8>, start_time: f64, end_time: f64) -> Result<Vec<u8>, String> {
+    println!(
+        "[trim_video_bytes] Trimming {} bytes from {}s to {}s",
+        input_bytes.len(), start_time, end_time
+    );
+
+    if input_bytes.is_empty() {
+        return Err("No input data provided".to_string());
+    }
+    if start_time < 0.0 || end_time <= start_time {
+        return Err(format!("Invalid trim range: {}s to {}s", start_time, end_time));
+    }
+
+    // FFmpeg needs seekable input, so round-trip through uniquely named temp files
+    let unique = format!(
+        "{}_{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0)
+    );
+    let input_path = std::env::temp_dir().join(format!("clipforge_trim_in_{}.mp4", unique));
+    let output_path = std::env::temp_dir().join(format!("clipforge_trim_out_{}.mp4", unique));
+
+    std::fs::write(&input_path, &input_bytes)
+        .map_err(|e| format!("Failed to write temp input: {}", e))?;
+
+    let args = vec![
+        "-y".to_string(),
+        "-ss".to_string(), start_time.to_string(),
+        "-i".to_string(), input_path.to_string_lossy().to_string(),
+        "-t".to_string(), (end_time - start_time).to_string(),
+        "-c".to_string(), "copy".to_string(),
+        "-avoid_negative_ts".to_string(), "make_zero".to_string(),
+        "-movflags".to_string(), "+faststart".to_string(),
+        output_path.to_string_lossy().to_string(),
+    ];
+
+    let result = run_ffmpeg(args, "trim_video_bytes")
+        .await
+        .map_err(String::from)
+        .and_then(|_| std::fs::read(&output_path).map_err(|e| format!("Failed to read trimmed output: {}", e)));
+
+    let _ = std::fs::remove_file(&input_path);
+    let _ = std::fs::remove_file(&output_path);
+
+    let output_bytes = result?;
+    println!("[trim_video_bytes] Trimmed to {} bytes", output_bytes.len());
+    Ok(output_bytes)
+}
+
+// Helper function to list the keyframe timestamps of the first video stream
+// Reads packet flags only, so no decoding is required
+fn probe_keyframe_times(video_path: &str) -> Result<Vec<f64>, String> {
+    let packets = probe_entry(video_path, "packet=pts_time,flags", Some("v:0"))?;
+    let mut keyframes: Vec<f64> = packets
+        .lines()
+        .filter_map(|line| {
+            let (pts, flags) = line.split_once(',')?;
+            if flags.contains('K') {
+                pts.trim().parse::<f64>().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+    keyframes.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(keyframes)
+}
+
+// Helper function for a "lossless cut": re-encode only the partial GOP before the first
+// keyframe and after the last keyframe inside the range, stream-copy everything between,
+// then join the parts and re-encode audio for the exact range.
+// Returns Ok(false) when the source can't be smart-cut (caller should fully re-encode).
+async fn smart_cut_trim(
+    input_path: &str,
+    output_path: &str,
+    start_time: f64,
+    end_time: f64,
+    profile: &OutputProfile,
+) -> Result<bool, CommandError> {
+    // Only codecs we can re-encode to a concat-compatible bitstream
+    let codec = probe_entry(input_path, "stream=codec_name", Some("v:0"))?;
+    let encoder = match codec.lines().next().unwrap_or("").trim() {
+        "h264" => "libx264",
+        "hevc" => "libx265",
+        other => {
+            println!("[smart_cut] Codec '{}' is not smart-cut compatible", other);
+            return Ok(false);
+        }
+    };
+    let pix_fmt = probe_entry(input_path, "stream=pix_fmt", Some("v:0"))?;
+    let pix_fmt = pix_fmt.lines().next().unwrap_or("yuv420p").trim().to_string();
+    // Encode the edges with the source's profile ("High" -> "high", "Main 10" -> "main10")
+    let codec_profile = probe_entry(input_path, "stream=profile", Some("v:0"))?;
+    let codec_profile = match codec_profile.lines().next().unwrap_or("").trim().to_lowercase().replace(' ', "").as_str() {
+        "constrainedbaseline" => "baseline".to_string(),
+        "" | "unknown" => String::new(),
+        other => other.to_string(),
+    };
+
+    let keyframes = probe_keyframe_times(input_path)?;
+    let first_key = keyframes.iter().copied().find(|&k| k >= start_time && k < end_time);
+    let last_key = keyframes.iter().copied().rev().find(|&k| k > start_time && k <= end_time);
+    let (first_key, last_key) = match (first_key, last_key) {
+        (Some(first), Some(last)) if last > first => (first, last),
+        _ => {
+            println!("[smart_cut] No complete GOP inside {}..{}", start_time, end_time);
+            return Ok(false);
+        }
+    };
+    println!(
+        "[smart_cut] Re-encoding {}..{} and {}..{}, copying {}..{}",
+        start_time, first_key, last_key, end_time, first_key, last_key
+    );
+
+    let temp_dir = std::env::temp_dir().join(format!(
+        "clipforge_smartcut_{}_{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0)
+    ));
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| CommandError::new(ErrorCode::Failed, format!("Failed to create temp directory: {}", e)))?;
+
+    // Video-only parts in MPEG-TS so each carries its own parameter sets
+    let encode_part = |from: f64, to: f64, name: &str| -> Vec<String> {
+        let mut args = vec![
+            "-y".to_string(),
+            "-ss".to_string(), from.to_string(),
+            "-i".to_string(), input_path.to_string(),
+            "-t".to_string(), (to - from).to_string(),
+            "-map".to_string(), "0:v:0".to_string(),
+            "-an".to_string(),
+            "-c:v".to_string(), encoder.to_string(),
+            "-preset".to_string(), "fast".to_string(),
+            "-crf".to_string(), "18".to_string(),
+            "-pix_fmt".to_string(), pix_fmt.clone(),
+        ];
+        if !codec_profile.is_empty() {
+            args.extend(["-profile:v".to_string(), codec_profile.clone()]);
+        }
+        args.push(temp_dir.join(name).to_string_lossy().to_string());
+        args
+    };
+
+    let mut parts = Vec::new();
+    let mut jobs = Vec::new();
+    if first_key - start_time > 0.001 {
+        jobs.push(encode_part(start_time, first_key, "head.ts"));
+        parts.push("head.ts");
+    }
+    // Seek slightly past the keyframe so rounding can't land on the previous GOP
+    jobs.push(vec![
+        "-y".to_string(),
+        "-ss".to_string(), (first_key + 0.0005).to_string(),
+        "-i".to_string(), input_path.to_string(),
+        "-t".to_string(), (last_key - first_key).to_string(),
+        "-map".to_string(), "0:v:0".to_string(),
+        "-an".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+        temp_dir.join("middle.ts").to_string_lossy().to_string(),
+    ]);
+    parts.push("middle.ts");
+    if end_time - last_key > 0.001 {
+        jobs.push(encode_part(last_key, end_time, "tail.ts"));
+        parts.push("tail.ts");
+    }
+
+    for job in jobs {
+        if let Err(e) = run_ffmpeg(job, "smart_cut").await {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+            return Err(e);
+        }
+    }
+
+    // The demuxer joins the parts as one stream, so the re-encoded edges must match the copied
+    // middle in profile, pixel format, size and timebase; otherwise fall back to a full re-encode
+    let signatures = parts
+        .iter()
+        .map(|part| {
+            probe_entry(
+                &temp_dir.join(part).to_string_lossy(),
+                "stream=profile,pix_fmt,width,height,time_base",
+                Some("v:0"),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>();
+    match signatures {
+        Ok(signatures) if signatures.windows(2).all(|pair| pair[0] == pair[1]) => {}
+        Ok(signatures) => {
+            println!("[smart_cut] Re-encoded parts don't match the source stream: {:?}", signatures);
+            let _ = std::fs::remove_dir_all(&temp_dir);
+            return Ok(false);
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+            return Err(e.into());
+        }
+    }
+
+    let concat_list_path = temp_dir.join("concat_list.txt");
+    let concat_content = parts
+        .iter()
+        .map(|part| format!("file '{}'", temp_dir.join(part).to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Err(e) = std::fs::write(&concat_list_path, concat_content) {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        return Err(CommandError::new(ErrorCode::Failed, format!("Failed to write concat list: {}", e)));
+    }
+
+    // Join the video parts and take audio from the exact requested range of the source
+    let mut args = vec![
+        "-y".to_string(),
+        "-f".to_string(), "concat".to_string(),
+        "-safe".to_string(), "0".to_string(),
+        "-i".to_string(), concat_list_path.to_string_lossy().to_string(),
+        "-ss".to_string(), start_time.to_string(),
+        "-t".to_string(), (end_time - start_time).to_string(),
+        "-i".to_string(), input_path.to_string(),
+        "-map".to_string(), "0:v".to_string(),
+        "-map".to_string(), "1:a?".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+    ];
+    args.extend(profile.audio_args());
+    args.extend(profile.movflags_args(output_path));
+    args.push(output_path.to_string());
+
+    let result = run_ffmpeg(args, "smart_cut").await;
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    result?;
+
+    // Verify the joined result covers the requested range
+    let expected = end_time - start_time;
+    let actual = probe_duration(output_path)?;
+    if (actual - expected).abs() > 0.1 {
+        return Err(CommandError::new(
+            ErrorCode::Failed,
+            format!("Smart cut duration {:.3}s does not match requested {:.3}s", actual, expected),
+        ));
+    }
+
+    Ok(true)
+}
+
+// Helper function to describe the stream parameters the concat demuxer needs to match
+fn concat_stream_signature(path: &str) -> Result<String, String> {
+    let video = probe_entry(path, "stream=codec_name,width,height,pix_fmt,r_frame_rate", Some("v:0"))?;
+    let audio = probe_entry(path, "stream=codec_name,sample_rate,channels", Some("a:0"))?;
+    Ok(format!("{}|{}", video.trim(), audio.trim()))
+}
+
+// Helper function to check whether stream-copying a clip's range is frame-accurate
+// A copied range starts at the keyframe before it, so only whole files, or ranges that start
+// on a keyframe and end on one (or at the end of the file), come out exactly as requested.
+fn clip_range_is_keyframe_aligned(clip: &ClipSegment) -> Result<bool, String> {
+    // Within half a frame counts as the same timestamp
+    let tolerance = 0.5 / probe_frame_rate(&clip.path).unwrap_or(30.0);
+    let from_beginning = clip.clip_start <= tolerance;
+    let to_end = clip.clip_end >= probe_duration(&clip.path)? - tolerance;
+    if from_beginning && to_end {
+        return Ok(true);
+    }
+
+    let keyframes = probe_keyframe_times(&clip.path)?;
+    let is_keyframe = |time: f64| keyframes.iter().any(|&k| (k - time).abs() <= tolerance);
+    Ok((from_beginning || is_keyframe(clip.clip_start)) && (to_end || is_keyframe(clip.clip_end)))
+}
+
+// Helper function to join clips with the concat filter in a single encode
+// Every clip is normalized to the same frame size, rate and audio format first,
+// so inputs with differing parameters can be joined; clips without audio get silence.
+async fn concat_with_filter(
+    clips: &[ClipSegment],
+    opts: &ExportOptions,
+    target_size: Option<(i32, i32)>,
+    total_duration: f64,
+    output_path: &str,
+) -> Result<(), CommandError> {
+    // Canvas: explicit target, otherwise the first clip's size (scaled to the preset height)
+    let (canvas_width, canvas_height) = match target_size {
+        Some(size) => size,
+        None => {
+            let dimensions = probe_entry(&clips[0].path, "stream=width,height", Some("v:0"))?;
+            let mut parts = dimensions.lines().next().unwrap_or("").split(',');
+            let width = parts.next().and_then(|w| w.trim().parse::<f64>().ok()).unwrap_or(1920.0);
+            let height = parts.next().and_then(|h| h.trim().parse::<f64>().ok()).unwrap_or(1080.0);
+            let preset_height = match opts.resolution.as_deref() {
+                Some("720p") => 720.0,
+                Some("1080p") => 1080.0,
+                _ => height,
+            };
+            let scaled_width = (width * preset_height / height / 2.0).round() * 2.0;
+            (scaled_width as i32, (preset_height / 2.0).round() as i32 * 2)
+        }
+    };
+    let frame_rate = probe_frame_rate(&clips[0].path).unwrap_or(30.0);
+    println!(
+        "[concatenate_clips] Filter concat at {}x{} @ {:.3} fps",
+        canvas_width, canvas_height, frame_rate
+    );
+
+    let mut args = vec!["-y".to_string()];
+    let mut parts = Vec::new();
+    let mut concat_inputs = String::new();
+
+    for (i, clip) in clips.iter().enumerate() {
+        let duration = clip.clip_end - clip.clip_start;
+        args.extend([
+            "-ss".to_string(), clip.clip_start.to_string(),
+            "-t".to_string(), duration.to_string(),
+            "-i".to_string(), clip.path.clone(),
+        ]);
+
+        let tonemap = hdr_tonemap_filter(&clip.path, opts.tonemap)?
+            .map(|chain| format!("{},", chain))
+            .unwrap_or_default();
+        let fit = build_canvas_fit_filter(
+            &format!("[{}:v]{}", i, tonemap),
+            i,
+            canvas_width,
+            canvas_height,
+            opts.fill_mode.as_deref(),
+        )?;
+        parts.push(format!("{},setsar=1,fps={},format=yuv420p[v{}]", fit, frame_rate, i));
+        if has_audio_stream(&clip.path) {
+            // Pad or cut the audio to the segment length so A/V stay aligned across the join
+            parts.push(format!(
+                "[{}:a]aresample=48000,aformat=sample_fmts=fltp:channel_layouts=stereo,apad=whole_dur={},atrim=duration={}[a{}]",
+                i, duration, duration, i
+            ));
+        } else {
+            parts.push(format!(
+                "anullsrc=channel_layout=stereo:sample_rate=48000,atrim=duration={}[a{}]",
+                duration, i
+            ));
+        }
+        concat_inputs.push_str(&format!("[v{}][a{}]", i, i));
+    }
+
+    parts.push(format!("{}concat=n={}:v=1:a=1[vcat][aout]", concat_inputs, clips.len()));
+
+    // The progress bar and section titles span the joined timeline, so they're drawn once after concatenation
+    let mut overlays = Vec::new();
+    if let Some(ref bar) = opts.progress_bar_overlay {
+        overlays.push(build_progress_bar_filter(bar, 0.0, total_duration)?);
+    }
+    if let Some(ref titles) = opts.section_titles {
+        overlays.extend(build_section_title_filters(titles)?);
+    }
+    if overlays.is_empty() {
+        parts.push("[vcat]null[vout]".to_string());
+    } else {
+        parts.push(format!("[vcat]{}[vout]", overlays.join(",")));
+    }
+
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+    args.extend([
+        "-filter_complex".to_string(), parts.join(";"),
+        "-map".to_string(), "[vout]".to_string(),
+        "-map".to_string(), "[aout]".to_string(),
+        "-c:v".to_string(), "libx264".to_string(),
+    ]);
+    args.extend(profile.video_args(["-crf", "18"]));
+    args.extend(profile.audio_args());
+    args.push(output_path.to_string());
+
+    run_ffmpeg(args, "concatenate_clips").await
+}
+
+// Helper function to build the audio half of the PiP overlay graph, ending in [a] (and [a2]
+// for a separate PiP track). Input 0 is the joined main video, input 1 the PiP.
+// `ducking` is the sidechain chain from build_ducking_filter: None when ducking is off,
+// Some(None) when sidechaincompress is unavailable and static weights are used instead.
+fn build_pip_audio_filter(
+    main_has_audio: bool,
+    pip_has_audio: bool,
+    separate_audio: bool,
+    pip_volume: f64,
+    main_duration: f64,
+    ducking: Option<Option<&str>>,
+) -> String {
+    match (main_has_audio, pip_has_audio) {
+        // Main and PiP audio as their own streams (the PiP audio still keys any ducking)
+        (true, true) if separate_audio => {
+            let pip_stream = format!("volume={},atrim=end={}[a2]", pip_volume, main_duration);
+            match ducking {
+                Some(Some(chain)) => format!("[1:a]asplit=2[duckkeysrc][pipa];{};[ducked]anull[a];[pipa]{}", chain, pip_stream),
+                Some(None) => format!("[0:a]volume={}[a];[1:a]{}", DUCKING_FALLBACK_WEIGHT, pip_stream),
+                None => format!("[0:a]anull[a];[1:a]{}", pip_stream),
+            }
+        }
+        // Main audio ducked under the PiP commentary
+        (true, true) if ducking.is_some() => match ducking.flatten() {
+            Some(chain) => format!(
+                "[1:a]asplit=2[duckkeysrc][pipa];{};[ducked][pipa]amix=inputs=2:duration=first:weights={} {}[a]",
+                chain, 1.0, pip_volume
+            ),
+            None => format!(
+                "[0:a][1:a]amix=inputs=2:duration=first:weights={} {}[a]",
+                DUCKING_FALLBACK_WEIGHT, pip_volume
+            ),
+        },
+        // Main audio at full volume, PiP audio at specified volume
+        (true, true) => format!(
+            "[0:a][1:a]amix=inputs=2:duration=first:weights={} {}[a]",
+            1.0, pip_volume
+        ),
+        (true, false) => "[0:a]anull[a]".to_string(),
+        (false, pip_audio) => {
+            // Generate silence matching the main video so the output length is unchanged
+            let silence = format!(
+                "anullsrc=channel_layout=stereo:sample_rate=48000,atrim=duration={}",
+                main_duration
+            );
+            if pip_audio {
+                format!(
+                    "{}[silence];[silence][1:a]amix=inputs=2:duration=first:weights={} {}[a]",
+                    silence, 1.0, pip_volume
+                )
+            } else {
+                format!("{}[a]", silence)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SpliceResult {
+    output_path: String,
+    duration: f64, // Total duration of the spliced output (seconds)
+}
+
+#[tauri::command]
+async fn splice_video(
+    base_path: String,
+    insert_path: String,
+    at_time: f64,
+    output_path: String,
+    export_options: Option<ExportOptions>,
+    window: tauri::Window
+) -> Result<SpliceResult, CommandError> {
+    println!("[splice_video] Inserting {} into {} at {}s", insert_path, base_path, at_time);
+
+    for path in [&base_path, &insert_path] {
+        if !std::path::Path::new(path).exists() {
+            return Err(CommandError::invalid_input(format!("Input file not found: {}", path)));
+        }
+    }
+
+    let base_duration = probe_duration(&base_path)?;
+    let insert_duration = probe_duration(&insert_path)?;
+    if !(0.0..=base_duration).contains(&at_time) {
+        return Err(CommandError::invalid_input(format!(
+            "Insert point {}s is outside the base video (0 - {}s)",
+            at_time, base_duration
+        )));
+    }
+
+    // Base up to the insert point, the whole insert, then the rest of the base
+    // (an insert at either end just prepends or appends)
+    let clips: Vec<ClipSegment> = [
+        (base_path.clone(), 0.0, at_time),
+        (insert_path, 0.0, insert_duration),
+        (base_path, at_time, base_duration),
+    ]
+    .into_iter()
+    .filter(|(_, start, end)| end > start)
+    .map(|(path, clip_start, clip_end)| ClipSegment { path, clip_start, clip_end })
+    .collect();
+
+    // Both halves of the base share its stream parameters, which would let concatenate_clips
+    // stream-copy them and snap the cut to a keyframe; the concat filter cuts on the exact frame
+    // and normalizes size, frame rate and audio when the parts differ
+    let mut opts = export_options.unwrap_or_else(|| ExportOptions {
+        resolution: Some("source".to_string()),
+        ..Default::default()
+    });
+    opts.concat_mode = Some("filter".to_string());
+    let output_path = concatenate_clips(clips, output_path, Some(opts), None, window).await?;
+    let duration = probe_duration(&output_path)?;
+    println!("[splice_video] Spliced output: {} ({}s)", output_path, duration);
+
+    Ok(SpliceResult { output_path, duration })
+}
+
+#[tauri::command]
+async fn concatenate_clips(
+    clips: Vec<ClipSegment>,
+    output_path: String,
+    export_options: Option<ExportOptions>,
+    pip_track: Option<PipTrackData>,
+    _window: tauri::Window
+) -> Result<String, CommandError> {
+    println!("[concatenate_clips] Starting concatenation of {} clips", clips.len());
+    println!("[concatenate_clips] Output: {}", output_path);
+    if let Some(ref pip) = pip_track {
+        println!("[concatenate_clips] PiP track: {} (offset: {}s, duration: {}s, position: {})",
+            pip.path, pip.offset, pip.duration, pip.position);
+    }
+
+    if clips.is_empty() {
+        return Err(CommandError::invalid_input("No clips provided for concatenation"));
+    }
+    if let Some(margin) = pip_track.as_ref().and_then(|pip| pip.margin) {
+        if margin < 0 {
+            return Err(CommandError::invalid_input(format!("Invalid PiP margin: {}", margin)));
+        }
+    }
+    let pip_style = match pip_track.as_ref().map(pip_style_filter) {
+        Some(Err(e)) => return Err(CommandError::invalid_input(e)),
+        Some(Ok(style)) if !style.is_empty() => format!(",{}", style),
+        _ => String::new(),
+    };
+
+    let job = ExportJobGuard::register("concat");
+
+    // Parse export options
+    let opts = export_options.unwrap_or_else(|| ExportOptions {
+        resolution: Some("source".to_string()),
+        ..Default::default()
+    });
+
+    // Exact target dimensions take precedence over the resolution presets so every
+    // segment (and therefore the PiP canvas) has an identical frame size
+    let target_size = match (opts.target_width, opts.target_height) {
+        (Some(w), Some(h)) => {
+            if w <= 0 || h <= 0 || w % 2 != 0 || h % 2 != 0 {
+                return Err(CommandError::invalid_input(format!("Target dimensions must be positive even numbers: {}x{}", w, h)));
+            }
+            Some((w, h))
+        }
+        (None, None) => None,
+        _ => return Err(CommandError::invalid_input("Both target_width and target_height must be provided")),
+    };
+
+    // Total output duration, used to animate the progress bar across all segments
+    let total_duration: f64 = clips.iter().map(|c| c.clip_end - c.clip_start).sum();
+    if let Some(ref bar) = opts.progress_bar_overlay {
+        // Validate the overlay before doing any work
+        build_progress_bar_filter(bar, 0.0, total_duration)?;
+    }
+    if let Some(ref titles) = opts.section_titles {
+        build_section_title_filters(titles)?;
+    }
+    if let Some(ref fill_mode) = opts.fill_mode {
+        if fill_mode != "pad" && fill_mode != "blur" {
+            return Err(CommandError::invalid_input(format!("Invalid fill_mode: {}", fill_mode)));
+        }
+    }
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+
+    // Pick how the clips are joined. The demuxer stream-copies and needs identical stream
+    // parameters and no video filtering; the concat filter handles everything in one encode.
+    let needs_filtering = target_size.is_some()
+        || matches!(opts.resolution.as_deref(), Some("720p") | Some("1080p"))
+        || opts.progress_bar_overlay.is_some()
+        || opts.section_titles.is_some()
+        || (opts.tonemap.unwrap_or(false) && clips.iter().any(|clip| is_hdr_source(&clip.path)));
+    let signatures = clips
+        .iter()
+        .map(|clip| concat_stream_signature(&clip.path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let params_match = signatures.windows(2).all(|pair| pair[0] == pair[1]);
+
+    let concat_mode = match opts.concat_mode.as_deref().unwrap_or("auto") {
+        "demuxer" => {
+            if needs_filtering {
+                return Err(CommandError::invalid_input("concat_mode \"demuxer\" cannot scale or draw overlays; use \"filter\""));
+            }
+            if !params_match {
+                return Err(CommandError::invalid_input("concat_mode \"demuxer\" requires all clips to share codec, size, frame rate and audio format"));
+            }
+            "demuxer"
+        }
+        "filter" => "filter",
+        // Stream copy is only picked when it gives the same frames as the encode would
+        "auto" => {
+            let copy_is_exact = params_match
+                && !needs_filtering
+                && clips
+                    .iter()
+                    .map(clip_range_is_keyframe_aligned)
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .all(|aligned| aligned);
+            if copy_is_exact { "demuxer" } else { "filter" }
+        }
+        other => return Err(CommandError::invalid_input(format!("Invalid concat_mode: {}", other))),
+    };
+    println!("[concatenate_clips] Concat mode: {}", concat_mode);
+
+    // Create a temporary directory for intermediate files
+    // Resumable exports use a directory named after the inputs and export settings so a retry
+    // finds its segments, and a retry with different settings starts over
+    let temp_dir = if opts.resume {
+        use std::hash::{Hash, Hasher};
+        remove_stale_concat_dirs();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for clip in &clips {
+            concat_segment_key(clip).hash(&mut hasher);
+        }
+        format!("{:?}|{}|{:?}", opts, concat_mode, target_size).hash(&mut hasher);
+        std::env::temp_dir().join(format!("clipforge_concat_{:016x}", hasher.finish()))
+    } else {
+        std::env::temp_dir().join(format!("clipforge_{}", std::process::id()))
+    };
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+
+    let mut segment_paths = Vec::new();
+    if concat_mode == "filter" {
+        // One encode produces the joined timeline; it then goes through the same final step
+        let joined_path = temp_dir.join("filter_concat.mp4");
+        let marker_path = temp_dir.join("filter_concat.done");
+        let joined_key = clips.iter().map(concat_segment_key).collect::<Vec<_>>().join("\n");
+
+        // Reuse the joined timeline when only the final step failed last time
+        if opts.resume
+            && std::fs::read_to_string(&marker_path).map(|key| key == joined_key).unwrap_or(false)
+            && verify_output_file(joined_path.to_str().unwrap()).is_ok()
+        {
+            println!("[concatenate_clips] Reusing joined timeline from a previous attempt");
+        } else {
+            let _ = std::fs::remove_file(&marker_path);
+            if let Err(e) = concat_with_filter(&clips, &opts, target_size, total_duration, joined_path.to_str().unwrap()).await {
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                return Err(e);
+            }
+            if opts.resume {
+                if let Err(e) = std::fs::write(&marker_path, &joined_key) {
+                    println!("[concatenate_clips] Warning: failed to write segment marker: {}", e);
+                }
+            }
+        }
+        segment_paths.push(joined_path);
+    } else {
+        // Stream-copy each clip's range (cuts snap to keyframes, as with any copy)
+        for (i, clip) in clips.iter().enumerate() {
+            println!("[concatenate_clips] Processing clip {}: {} ({}s to {}s)",
+                i, clip.path, clip.clip_start, clip.clip_end);
+            // Segments are most of the work; the final join is the last step
+            job.set_percent(i as f64 / (clips.len() + 1) as f64 * 100.0);
+
+            let segment_path = temp_dir.join(format!("segment_{}.mp4", i));
+            let marker_path = temp_dir.join(format!("segment_{}.done", i));
+            let segment_key = concat_segment_key(clip);
+
+            // Reuse a segment finished by an earlier attempt
+            if opts.resume
+                && std::fs::read_to_string(&marker_path).map(|key| key == segment_key).unwrap_or(false)
+                && verify_output_file(segment_path.to_str().unwrap()).is_ok()
+            {
+                println!("[concatenate_clips] Reusing segment {} from a previous attempt", i);
+                segment_paths.push(segment_path);
+                continue;
+            }
+            let _ = std::fs::remove_file(&marker_path);
+
+            let ffmpeg_args =
+                concat_segment_args(clip, segment_path.to_str().unwrap(), has_audio_stream(&clip.path), &profile);
+
+            println!("[concatenate_clips] FFmpeg args for segment {}: {:?}", i, ffmpeg_args);
+
+            let ffmpeg_path = get_ffmpeg_path();
+            let status = Command::new(&ffmpeg_path)
+                .args(&ffmpeg_args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+            if !status.success() {
+                // Clean up temp directory (kept for resume so finished segments survive)
+                if !opts.resume {
+                    let _ = std::fs::remove_dir_all(&temp_dir);
+                }
+                return Err(CommandError::new(ErrorCode::NonZeroExit, format!("FFmpeg failed to process segment {}", i)));
+            }
+
+            if opts.resume {
+                if let Err(e) = std::fs::write(&marker_path, &segment_key) {
+                    println!("[concatenate_clips] Warning: failed to write segment marker: {}", e);
+                }
+            }
+            segment_paths.push(segment_path);
+        }
+    }
+
+    // Create concat list file
+    let concat_list_path = temp_dir.join("concat_list.txt");
+    let concat_content = segment_paths
+        .iter()
+        .map(|p| format!("file '{}'", p.to_str().unwrap()))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    std::fs::write(&concat_list_path, concat_content)
+        .map_err(|e| format!("Failed to write concat list: {}", e))?;
+
+    println!("[concatenate_clips] Concatenating segments into final output");
+
+    // If no PiP track, use simple concat
+    if pip_track.is_none() {
+        let mut concat_args = vec![
+            "-y".to_string(),
+            "-f".to_string(), "concat".to_string(),
+            "-safe".to_string(), "0".to_string(),
+            "-i".to_string(), concat_list_path.to_str().unwrap().to_string(),
+            "-c".to_string(), "copy".to_string(),
+        ];
+
+        concat_args.extend(profile.movflags_args(&output_path));
+
+        concat_args.push(output_path.clone());
+
+        println!("[concatenate_clips] Final concat args (no PiP): {:?}", concat_args);
+
+        let ffmpeg_path = get_ffmpeg_path();
+        let status = Command::new(&ffmpeg_path)
+            .args(&concat_args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| format!("Failed to execute FFmpeg for concatenation: {}", e))?;
+
+        // Clean up temp directory (a resumable export keeps its segments until it succeeds)
+        if status.success() || !opts.resume {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+        }
+
+        if status.success() {
+            println!("[concatenate_clips] Concatenation completed successfully");
+            verify_output_file(&output_path)?;
+            // The export takes its date from the first clip
+            preserve_source_timestamps(&opts, &clips[0].path, &output_path);
+            return Ok(output_path);
+        } else {
+            return Err(CommandError::new(ErrorCode::NonZeroExit, "FFmpeg concatenation failed"));
+        }
+    }
+
+    // With PiP track, we need to apply overlay filter
+    let pip = pip_track.unwrap();
+
+    let overlay_position = pip_overlay_position(&pip.position, pip.margin.unwrap_or(20));
+
+    // Build video part of the complex filter for PiP overlay
+    // Border and rounded corners wrap the scaled PiP, so the margin is measured to the border's edge
+    let video_filter = format!(
+        "{};[pipscaled]setsar=1{}[pip];[main][pip]overlay={}:enable='between(t,{},{})'[v]",
+        build_pip_scale_filter(pip.size_percent),
+        pip_style,
+        overlay_position,
+        pip.offset,
+        pip.offset + pip.duration
+    );
+
+    // First, concatenate the main clips without PiP
+    let temp_concat_path = temp_dir.join("temp_concat.mp4");
+    let concat_args = vec![
+        "-y".to_string(),
+        "-f".to_string(), "concat".to_string(),
+        "-safe".to_string(), "0".to_string(),
+        "-i".to_string(), concat_list_path.to_str().unwrap().to_string(),
+        "-c".to_string(), "copy".to_string(),
+        temp_concat_path.to_str().unwrap().to_string(),
+    ];
+
+    println!("[concatenate_clips] Creating temp concat (before PiP): {:?}", concat_args);
+
+    let ffmpeg_path = get_ffmpeg_path();
+    let status = Command::new(&ffmpeg_path)
+        .args(&concat_args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("Failed to execute FFmpeg for temp concatenation: {}", e))?;
+
+    if !status.success() {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        return Err(CommandError::new(ErrorCode::NonZeroExit, "FFmpeg temp concatenation failed"));
+    }
+
+    // Build the audio part based on which inputs actually carry audio
+    let temp_concat_str = temp_concat_path.to_str().unwrap().to_string();
+    let main_has_audio = has_audio_stream(&temp_concat_str);
+    let pip_has_audio = has_audio_stream(&pip.path);
+    println!(
+        "[concatenate_clips] Audio present - main: {}, PiP: {}",
+        main_has_audio, pip_has_audio
+    );
+
+    // Separate tracks only apply when both sides have audio to keep apart
+    let separate_audio = opts.separate_audio_tracks.unwrap_or(false) && main_has_audio && pip_has_audio;
+
+    // Silence is generated to the main video's length, and a separate PiP track is cut to it
+    let main_duration = if separate_audio || !main_has_audio {
+        match probe_duration(&temp_concat_str) {
+            Ok(duration) => duration,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                return Err(e.into());
+            }
+        }
+    } else {
+        0.0
+    };
+    let ducking = match opts.ducking.as_ref().filter(|_| main_has_audio && pip_has_audio) {
+        Some(ducking) => match build_ducking_filter(ducking, "[0:a]", "[duckkeysrc]", "[ducked]") {
+            Ok(chain) => Some(chain),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                return Err(e.into());
+            }
+        },
+        None => None,
+    };
+    let audio_filter = build_pip_audio_filter(
+        main_has_audio,
+        pip_has_audio,
+        separate_audio,
+        pip.volume,
+        main_duration,
+        ducking.as_ref().map(|chain| chain.as_deref()),
+    );
+
+    let filter_complex = format!("{};{}", video_filter, audio_filter);
+    println!("[concatenate_clips] PiP overlay filter: {}", filter_complex);
+
+    // Now apply PiP overlay
+    let mut pip_args = vec![
+        "-y".to_string(),
+        "-i".to_string(), temp_concat_str.clone(),
+        "-i".to_string(), pip.path.clone(),
+        "-filter_complex".to_string(), filter_complex,
+        "-map".to_string(), "[v]".to_string(),
+        "-map".to_string(), "[a]".to_string(),
+    ];
+    if separate_audio {
+        pip_args.extend(["-map".to_string(), "[a2]".to_string()]);
+        pip_args.extend(audio_stream_metadata_args(&[
+            ("Main".to_string(), "und".to_string()),
+            ("Picture-in-picture".to_string(), "und".to_string()),
+        ]));
+    }
+    pip_args.extend(["-c:v".to_string(), "libx264".to_string()]);
+    pip_args.extend(profile.video_args(["-crf", "18"]));
+    pip_args.extend(profile.audio_args());
+    pip_args.extend(profile.movflags_args(&output_path));
+
+    pip_args.push(output_path.clone());
+
+    println!("[concatenate_clips] Applying PiP overlay: {:?}", pip_args);
+
+    let ffmpeg_path = get_ffmpeg_path();
+    let status = Command::new(&ffmpeg_path)
+        .args(&pip_args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("Failed to execute FFmpeg for PiP overlay: {}", e))?;
+
+    // Clean up temp directory (a resumable export keeps its segments until it succeeds)
+    if status.success() || !opts.resume {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    if status.success() {
+        println!("[concatenate_clips] PiP overlay completed successfully");
+        verify_output_file(&output_path)?;
+        if separate_audio {
+            verify_audio_stream_count(&output_path, 2)?;
+        }
+        preserve_source_timestamps(&opts, &clips[0].path, &output_path);
+        Ok(output_path)
+    } else {
+        Err(CommandError::new(ErrorCode::NonZeroExit, "FFmpeg PiP overlay failed"))
+    }
+}
+
+#[tauri::command]
+async fn save_file_dialog(default_filename: String, app: tauri::AppHandle) -> Result<String, String> {
+    println!("[save_file_dialog] Opening save dialog");
+    println!("[save_file_dialog] Default filename: {}", default_filename);
+
+    // Use spawn_blocking to avoid blocking the main thread
+    let file_path = tokio::task::spawn_blocking(move || {
+        app
+            .dialog()
+            .file()
+            .set_file_name(&default_filename)
+            .add_filter("Video Files", &["mp4"])
+            .blocking_save_file()
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?;
+
+    match file_path {
+        Some(path) => {
+            let path_str = path.to_string();
+            println!("[save_file_dialog] File selected: {}", path_str);
+            Ok(path_str)
+        },
+        None => {
+            println!("[save_file_dialog] No file selected (user cancelled)");
+            Err("No file selected".to_string())
+        },
+    }
+}
+
+#[tauri::command]
+async fn open_file_dialog(app: tauri::AppHandle) -> Result<String, String> {
+    let file_path = app
+        .dialog()
+        .file()
+        .add_filter("Video Files", &["mp4", "mov"])
+        .blocking_pick_file();
+
+    match file_path {
+        Some(path) => Ok(path.to_string()),
+        None => Err("No file selected".to_string()),
+    }
+}
+
+#[tauri::command]
+fn get_video_metadata(video_path: String) -> Result<String, String> {
+    use std::process::Command;
+
+    if !ffprobe_available() {
+        println!("[get_video_metadata] ffprobe not found, falling back to ffmpeg -i parsing");
+        return ffmpeg_metadata_json(&video_path);
+    }
+
+    // Run ffprobe to get video metadata as JSON
+    let ffprobe_path = get_ffprobe_path();
+    let output = output_with_timeout(Command::new(&ffprobe_path).args(&[
+        "-v", "quiet",
+        "-print_format", "json",
+        "-show_format",
+        "-show_streams",
+        &video_path
+    ]))
+    .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("FFprobe error: {}", error));
+    }
+
+    let json = String::from_utf8_lossy(&output.stdout).to_string();
+    Ok(json)
+}
+
+// Helper function to build ffprobe-shaped metadata JSON from `ffmpeg -i` output
+// Only the common fields are filled in; `low_fidelity` tells the frontend this isn't real ffprobe output
+fn ffmpeg_metadata_json(video_path: &str) -> Result<String, String> {
+    let info = ffmpeg_probe(video_path)?;
+
+    let streams: Vec<serde_json::Value> = info
+        .streams
+        .iter()
+        .enumerate()
+        .map(|(index, stream)| {
+            let mut value = serde_json::json!({
+                "index": index,
+                "codec_type": stream.codec_type,
+                "codec_name": stream.codec_name,
+            });
+            if let (Some(w), Some(h)) = (stream.width, stream.height) {
+                value["width"] = w.into();
+                value["height"] = h.into();
+            }
+            if let Some(fps) = stream.frame_rate {
+                value["r_frame_rate"] = format!("{}/1", fps).into();
+            }
+            if let Some(rate) = stream.sample_rate {
+                value["sample_rate"] = rate.to_string().into();
+            }
+            // Same keys ffprobe uses, so HDR detection works on either path
+            for (key, field) in [
+                ("pix_fmt", &stream.pix_fmt),
+                ("color_space", &stream.color_space),
+                ("color_transfer", &stream.color_transfer),
+                ("color_primaries", &stream.color_primaries),
+            ] {
+                if let Some(field) = field {
+                    value[key] = field.clone().into();
+                }
+            }
+            value
+        })
+        .collect();
+
+    let mut format = serde_json::json!({ "filename": video_path });
+    if let Some(duration) = info.duration {
+        format["duration"] = duration.to_string().into();
+    }
+
+    let metadata = serde_json::json!({
+        "streams": streams,
+        "format": format,
+        "low_fidelity": true,
+    });
+
+    serde_json::to_string(&metadata).map_err(|e| format!("Failed to serialize metadata: {}", e))
+}
+
+// Helper function to query a single ffprobe entry as a plain value (csv, no keys)
+fn probe_entry(video_path: &str, entries: &str, stream_selector: Option<&str>) -> Result<String, String> {
+    let mut args = vec!["-v", "error"];
+    if let Some(selector) = stream_selector {
+        args.push("-select_streams");
+        args.push(selector);
+    }
+    args.extend(["-show_entries", entries, "-of", "csv=p=0", video_path]);
+
+    let ffprobe_path = get_ffprobe_path();
+    let output = output_with_timeout(Command::new(&ffprobe_path).args(&args))
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("FFprobe error: {}", error));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Helper function to get a file's duration in seconds
+// Uses the container duration, falling back to the first video stream's duration
+fn probe_duration(video_path: &str) -> Result<f64, String> {
+    if !ffprobe_available() {
+        return ffmpeg_probe(video_path)?
+            .duration
+            .ok_or_else(|| format!("Could not determine duration of {}", video_path));
+    }
+
+    let format_duration = probe_entry(video_path, "format=duration", None)?;
+    if let Ok(duration) = format_duration.parse::<f64>() {
+        return Ok(duration);
+    }
+
+    let stream_duration = probe_entry(video_path, "stream=duration", Some("v:0"))?;
+    stream_duration
+        .lines()
+        .next()
+        .and_then(|line| line.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("Could not determine duration of {}", video_path))
+}
+
+// Helper function to check whether a file has at least one audio stream
+fn has_audio_stream(video_path: &str) -> bool {
+    if !ffprobe_available() {
+        return ffmpeg_probe(video_path)
+            .map(|info| info.streams.iter().any(|s| s.codec_type == "audio"))
+            .unwrap_or(false);
+    }
+
+    probe_entry(video_path, "stream=codec_type", Some("a"))
+        .map(|streams| !streams.is_empty())
+        .unwrap_or(false)
+}
+
+#[tauri::command]
+fn get_duration(video_path: String) -> Result<f64, String> {
+    probe_duration(&video_path)
+}
+
+// Helper function to map a thumbnail format and quality to encoder arguments
+// JPEG quality is FFmpeg's -q:v scale (2 = best, 31 = smallest; default 5 for small fast
+// thumbnails). PNG is lossless, so quality is the compression level (0-9, default 3).
+fn image_encoder_args(format: Option<&str>, quality: Option<u8>) -> Result<Vec<String>, String> {
+    match format {
+        Some("jpg") | Some("jpeg") | None => {
+            let quality = quality.unwrap_or(5);
+            if !(2..=31).contains(&quality) {
+                return Err(format!("Invalid JPEG quality: {} (expected 2-31)", quality));
+            }
+            Ok(vec![
+                "-c:v".to_string(), "mjpeg".to_string(),
+                "-q:v".to_string(), quality.to_string(),
+            ])
+        }
+        Some("png") => {
+            let quality = quality.unwrap_or(3);
+            if quality > 9 {
+                return Err(format!("Invalid PNG compression level: {} (expected 0-9)", quality));
+            }
+            Ok(vec![
+                "-c:v".to_string(), "png".to_string(),
+                "-compression_level".to_string(), quality.to_string(),
+            ])
+        }
+        Some(other) => Err(format!("Invalid image format: {} (expected jpg or png)", other)),
+    }
+}
+
+#[tauri::command]
+async fn get_frame_at(
+    video_path: String,
+    timestamp: f64,
+    format: Option<String>,
+    quality: Option<u8>,
+) -> Result<String, String> {
+    if !std::path::Path::new(&video_path).exists() {
+        return Err(format!("Video file not found: {}", video_path));
+    }
+    if timestamp < 0.0 {
+        return Err(format!("Invalid timestamp: {}", timestamp));
+    }
+    let encoder_args = image_encoder_args(format.as_deref(), quality)?;
+
+    // Input seeking (-ss before -i) jumps to the nearest keyframe and decodes only up to the frame
+    let ffmpeg_path = get_ffmpeg_path();
+    let output = tokio::task::spawn_blocking(move || {
+        output_with_timeout(
+            Command::new(&ffmpeg_path)
+                .args([
+                    "-ss", &timestamp.to_string(),
+                    "-i", &video_path,
+                    "-frames:v", "1",
+                    "-f", "image2pipe",
+                ])
+                .args(&encoder_args)
+                .arg("-"),
+        )
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+    .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(format!("No frame available at {}s", timestamp));
+    }
+
+    Ok(general_purpose::STANDARD.encode(&output.stdout))
+}
+
+// Helper function to parse an ffprobe rational like "30000/1001" or "30/1"
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let rate = rate.trim();
+    match rate.split_once('/') {
+        Some((num, den)) => {
+            let num = num.parse::<f64>().ok()?;
+            let den = den.parse::<f64>().ok()?;
+            if den == 0.0 { None } else { Some(num / den) }
+        }
+        None => rate.parse::<f64>().ok(),
+    }
+}
+
+// Helper function to get the frame rate of the first video stream
+fn probe_frame_rate(video_path: &str) -> Result<f64, String> {
+    if !ffprobe_available() {
+        return ffmpeg_probe(video_path)?
+            .streams
+            .iter()
+            .find(|s| s.codec_type == "video")
+            .and_then(|s| s.frame_rate)
+            .ok_or_else(|| format!("Could not determine frame rate of {}", video_path));
+    }
+
+    let rate = probe_entry(video_path, "stream=r_frame_rate", Some("v:0"))?;
+    rate.lines()
+        .next()
+        .and_then(parse_frame_rate)
+        .filter(|fps| *fps > 0.0)
+        .ok_or_else(|| format!("Could not determine frame rate of {}", video_path))
+}
+
+#[tauri::command]
+fn get_video_file(video_path: &str) -> Result<Vec<u8>, String> {
+    use std::fs;
+    fs::read(video_path)
+        .map_err(|e| format!("Failed to read video file: {}", e))
+}
+
+#[tauri::command]
+fn reveal_in_file_manager(path: String) -> Result<(), String> {
+    println!("[reveal_in_file_manager] Revealing {}", path);
+
+    let file_path = std::path::Path::new(&path);
+    if !file_path.exists() {
+        return Err(format!("File not found: {}", path));
+    }
+
+    let mut command = if cfg!(target_os = "macos") {
+        // Opens a Finder window with the file selected
+        let mut command = Command::new("open");
+        command.args(["-R", &path]);
+        command
+    } else if cfg!(target_os = "windows") {
+        // Explorer only accepts backslashes, and needs "/select," and the quoted path as one argument
+        let windows_path = path.replace('/', "\\");
+        let mut command = Command::new("explorer");
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            command.raw_arg(format!("/select,\"{}\"", windows_path));
+        }
+        #[cfg(not(target_os = "windows"))]
+        command.arg(format!("/select,{}", windows_path));
+        command
+    } else {
+        // No standard select-file call on Linux; open the containing directory instead
+        let directory = if file_path.is_dir() {
+            file_path
+        } else {
+            file_path.parent().ok_or("File has no parent directory")?
+        };
+        let mut command = Command::new("xdg-open");
+        command.arg(directory);
+        command
+    };
+
+    // Explorer exits non-zero even on success, so only a failure to launch is an error
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to open file manager: {}", e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn get_video_file_path(video_path: String) -> Result<String, String> {
+    // Validate path exists
+    let path = std::path::Path::new(&video_path);
+    if !path.exists() {
+        return Err(format!("Video file not found: {}", video_path));
+    }
+
+    // Return absolute path
+    match path.canonicalize() {
+        Ok(canonical_path) => Ok(canonical_path.to_string_lossy().to_string()),
+        Err(e) => Err(format!("Failed to resolve path: {}", e))
+    }
+}
+
+#[tauri::command]
+async fn start_screen_recording(
+    output_path: String,
+    options: Option<RecordingOptions>,
+    window: tauri::Window
+) -> Result<String, CommandError> {
+    println!("[start_screen_recording] Starting screen recording");
+
+    // An empty path records to an auto-named file, which is returned instead of the status message
+    let auto_named = output_path.is_empty();
+    let output_path = if auto_named { default_output_path("screen", "mp4")? } else { output_path };
+    println!("[start_screen_recording] Output path: {}", output_path);
+
+    // Parse resolution options
+    let opts = options.unwrap_or_else(|| RecordingOptions {
+        resolution: "720p".to_string(),
+        ..Default::default()
+    });
+
+    // Determine resolution and bitrate
+    let (width, height, bitrate) = match opts.resolution.as_str() {
+        "720p" => (1280, 720, "2500k"),
+        "1080p" => (1920, 1080, "5000k"),
+        "source" => {
+            if let (Some(w), Some(h)) = (opts.source_width, opts.source_height) {
+                (w, h, "8000k")
+            } else {
+                return Err(CommandError::invalid_input("Source resolution not available"));
+            }
+        }
+        _ => return Err(CommandError::invalid_input(format!("Invalid resolution: {}", opts.resolution))),
+    };
+
+    println!("[start_screen_recording] Resolution: {}x{} @ {} bitrate", width, height, bitrate);
+
+    if opts.max_duration == Some(0) {
+        return Err(CommandError::invalid_input("max_duration must be greater than zero"));
+    }
+    let trim_start = recording_trim_start(&opts).map_err(CommandError::invalid_input)?;
+    let color_range = recording_color_range(&opts)?;
+    let (x264_preset, x264_tune) = recording_x264_settings(&opts)?;
+    let capture_fps = resolve_capture_framerate(&opts).map_err(CommandError::invalid_input)?.to_string();
+
+    // Audio is recorded when a device is selected by name or by index, unless explicitly overridden
+    let has_audio_device = opts.audio_device.is_some() || opts.audio_device_index.is_some();
+    let record_audio = opts.record_audio.unwrap_or(has_audio_device);
+    if record_audio {
+        if cfg!(target_os = "windows") && !has_audio_device {
+            return Err(CommandError::invalid_input("record_audio requires an audio device on Windows"));
+        }
+        println!(
+            "[start_screen_recording] Audio device: {:?} (index {:?})",
+            opts.audio_device, opts.audio_device_index
+        );
+    } else if has_audio_device {
+        println!("[start_screen_recording] Audio disabled for this recording");
+    }
+    let audio_filter = if record_audio { recording_audio_filter(&opts)? } else { None };
+
+    // Resolve the window to capture, if any, so a missing window fails before FFmpeg starts
+    let window_title = opts.window_title.as_deref().filter(|title| !title.is_empty());
+    let mut window_crop = None;
+    if let Some(title) = window_title {
+        println!("[start_screen_recording] Window capture: {}", title);
+        if cfg!(target_os = "windows") {
+            if !list_windows()?.iter().any(|t| t == title) {
+                return Err(CommandError::invalid_input(format!("Window not found: {}", title)));
+            }
+        } else if cfg!(target_os = "macos") {
+            // avfoundation can only capture whole screens, so crop to the window's bounds
+            window_crop = Some(macos_window_crop_filter(title)?);
+        } else {
+            return Err(CommandError::invalid_input("Window capture is not supported on this platform"));
+        }
+    }
+
+    // Platform-specific FFmpeg arguments
+    let scale_filter = match &window_crop {
+        Some(crop) => format!("{},scale={}:{}", crop, width, height),
+        None => format!("scale={}:{}", width, height),
+    };
+    let video_filter = build_recording_video_filter(&opts, Some(scale_filter.clone())).unwrap_or(scale_filter);
+    let gdigrab_input = match window_title {
+        Some(title) => format!("title={}", title),
+        None => "desktop".to_string(),
+    };
+
+    // Prepare Windows input string (if needed) before args to ensure proper lifetime
+    let (windows_audio_input, windows_audio_number) = if cfg!(target_os = "windows") && record_audio {
+        if let Some(index) = opts.audio_device_index {
+            let devices = list_audio_video_devices()?;
+            let (name, ordinal) = resolve_dshow_device(&devices.audio_devices, index)?;
+            (format!("audio={}", name), ordinal)
+        } else if let Some(audio_dev) = &opts.audio_device {
+            (format!("audio={}", audio_dev), 0)
+        } else {
+            (String::new(), 0)
+        }
+    } else {
+        (String::new(), 0)
+    };
+
+    // Linux audio input (PulseAudio source or ALSA device)
+    let linux_audio_input = if cfg!(target_os = "linux") && record_audio {
+        Some(resolve_linux_audio_input(&opts)?)
+    } else {
+        None
+    };
+
+    let mut args = if cfg!(target_os = "macos") {
+        // macOS: avfoundation supports audio input
+        // Format: "1:0" means screen device 1, audio device 0 (default microphone)
+        let mut args = vec![
+            "-f".to_string(), "avfoundation".to_string(),
+            "-framerate".to_string(), capture_fps.clone(),
+        ];
+
+        let screen_index = opts.video_device_index.unwrap_or(1);
+        if record_audio {
+            // Include audio: screen and the selected microphone
+            let audio_index = resolve_avfoundation_audio_index(&opts)?;
+            args.push("-i".to_string());
+            args.push(format!("{}:{}", screen_index, audio_index));
+        } else {
+            // Video only
+            args.push("-i".to_string());
+            args.push(screen_index.to_string());
+        }
+
+        args.push("-vf".to_string());
+        args.push(video_filter.clone());
+        args.push("-pix_fmt".to_string());
+        args.push("yuv420p".to_string());
+        args.push("-c:v".to_string());
+        args.push("libx264".to_string());
+        args.push("-preset".to_string());
+        args.push(x264_preset.to_string());
+        args.push("-b:v".to_string());
+        args.push(bitrate.to_string());
+
+        // Add audio encoding if audio device is specified
+        if record_audio {
+            args.push("-c:a".to_string());
+            args.push("aac".to_string());
+            args.push("-b:a".to_string());
+            args.push("192k".to_string());
+        }
+
+        args
+    } else if cfg!(target_os = "windows") {
+        // Windows: Use gdigrab for screen + dshow for audio (if specified)
+        let mut args = vec![
+            "-f".to_string(), "gdigrab".to_string(),
+            "-framerate".to_string(), capture_fps.clone(),
+            "-i".to_string(), gdigrab_input.clone(),
+        ];
+
+        // Add audio input if specified
+        if record_audio {
+            args.push("-f".to_string());
+            args.push("dshow".to_string());
+            if windows_audio_number > 0 {
+                args.push("-audio_device_number".to_string());
+                args.push(windows_audio_number.to_string());
+            }
+            args.push("-i".to_string());
+            args.push(windows_audio_input.clone());
+        }
+
+        args.push("-vf".to_string());
+        args.push(video_filter.clone());
+        args.push("-pix_fmt".to_string());
+        args.push("yuv420p".to_string());
+        args.push("-c:v".to_string());
+        args.push("libx264".to_string());
+        args.push("-preset".to_string());
+        args.push(x264_preset.to_string());
+        args.push("-b:v".to_string());
+        args.push(bitrate.to_string());
+
+        // Add audio encoding if audio device is specified
+        if record_audio {
+            args.push("-c:a".to_string());
+            args.push("aac".to_string());
+            args.push("-b:a".to_string());
+            args.push("192k".to_string());
+        }
+
+        args
+    } else if cfg!(target_os = "linux") {
+        // Linux: x11grab for the X display + pulse/alsa for audio (if specified)
+        let display = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+        let mut args = vec![
+            "-f".to_string(), "x11grab".to_string(),
+            "-framerate".to_string(), capture_fps.clone(),
+            "-i".to_string(), display,
+        ];
+
+        if let Some((format, device)) = &linux_audio_input {
+            args.push("-f".to_string());
+            args.push(format.to_string());
+            args.push("-i".to_string());
+            args.push(device.clone());
+        }
+
+        args.push("-vf".to_string());
+        args.push(video_filter.clone());
+        args.push("-pix_fmt".to_string());
+        args.push("yuv420p".to_string());
+        args.push("-c:v".to_string());
+        args.push("libx264".to_string());
+        args.push("-preset".to_string());
+        args.push(x264_preset.to_string());
+        args.push("-b:v".to_string());
+        args.push(bitrate.to_string());
+
+        if record_audio {
+            args.push("-c:a".to_string());
+            args.push("aac".to_string());
+            args.push("-b:a".to_string());
+            args.push("192k".to_string());
+        }
+
+        args
+    } else {
+        return Err(CommandError::new(ErrorCode::Failed, "Unsupported platform"));
+    };
+
+    // Tag the stream with the range the filter converted to
+    if let Some(range) = color_range {
+        args.push("-color_range".to_string());
+        args.push(range.to_string());
+    }
+
+    if let Some(tune) = x264_tune {
+        args.push("-tune".to_string());
+        args.push(tune.to_string());
+    }
+
+    if let Some(audio_filter) = audio_filter {
+        args.push("-af".to_string());
+        args.push(audio_filter);
+    }
+
+    // Stop automatically once the safety cap is reached
+    if let Some(max_duration) = opts.max_duration {
+        args.push("-t".to_string());
+        args.push(max_duration.to_string());
+    }
+
+    // Drop the encoder warm-up at the start
+    if let Some(seconds) = trim_start {
+        args.push("-ss".to_string());
+        args.push(seconds.to_string());
+    }
+
+    // Record to a temp file; stop_screen_recording moves it into place
+    let mut recording = PendingRecording::new(&output_path);
+    recording.segments = SegmentRetention::from_options(&recording, &opts)?;
+    if let Some(retention) = &recording.segments {
+        // Ring buffer: fixed-length segments, keyframed at each cut so they split cleanly
+        let _ = std::fs::remove_dir_all(&retention.dir);
+        std::fs::create_dir_all(&retention.dir)
+            .map_err(|e| format!("Failed to create segment directory: {}", e))?;
+        println!(
+            "[start_screen_recording] Ring buffer: keep {:?} segments, max {:?} bytes",
+            retention.keep_segments, retention.max_bytes
+        );
+        args.extend([
+            "-force_key_frames".to_string(), format!("expr:gte(t,n_forced*{})", RECORDING_SEGMENT_SECONDS),
+            "-f".to_string(), "segment".to_string(),
+            "-segment_format".to_string(), "mpegts".to_string(),
+            "-segment_time".to_string(), RECORDING_SEGMENT_SECONDS.to_string(),
+            "-reset_timestamps".to_string(), "1".to_string(),
+        ]);
+        args.push(
+            std::path::Path::new(&retention.dir)
+                .join("segment_%05d.ts")
+                .to_string_lossy()
+                .to_string(),
+        );
+    } else {
+        args.push(recording.temp_path.clone());
+    }
+
+    println!("[start_screen_recording] FFmpeg args: {:?}", args);
+
+    // Start FFmpeg process with stdin pipe for graceful shutdown
+    let ffmpeg_path = get_ffmpeg_path();
+    let child = Command::new(&ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            CommandError::spawn_failed(e, "Failed to start FFmpeg. Make sure you have granted screen recording permissions.")
+        })?;
+
+    let device = if record_audio { "screen or microphone" } else { "screen" };
+    let child = match check_recording_startup(child, device, &recording.temp_path).await {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = std::fs::remove_file(&recording.temp_path);
+            if let Some(retention) = &recording.segments {
+                let _ = std::fs::remove_dir_all(&retention.dir);
+            }
+            return Err(e);
+        }
+    };
+
+    println!("[start_screen_recording] FFmpeg process started");
+
+    // Store process in global state
+    let pid = child.id();
+    let retention = recording.segments.clone();
+    *RECORDING_OUTPUT.lock().unwrap() = Some(recording);
+    let mut process = RECORDING_PROCESS.lock().unwrap();
+    *process = Some(child);
+    drop(process);
+
+    if let Some(retention) = retention {
+        spawn_segment_cleanup(RECORDING_PROCESS.clone(), pid, retention);
+    }
+
+    if let Some(max_duration) = opts.max_duration {
+        spawn_auto_stop_watcher(
+            RECORDING_PROCESS.clone(),
+            Some(RECORDING_OUTPUT.clone()),
+            pid,
+            "screen",
+            // -t counts output time, which starts after the discarded warm-up
+            max_duration + trim_start.map(|s| s.ceil() as u32).unwrap_or(0),
+            window,
+        );
+    }
+
+    if auto_named {
+        return Ok(output_path);
+    }
+    Ok("Recording started".to_string())
+}
+
+#[tauri::command]
+async fn snapshot_recording(output_path: String) -> Result<String, String> {
+    println!("[snapshot_recording] Grabbing current frame to {}", output_path);
+
+    let recording = RECORDING_OUTPUT
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| "No recording in progress".to_string())?;
+
+    // Ring-buffer recordings read the newest finished segment (the last one is still open);
+    // otherwise the temp file FFmpeg is writing to, which for MP4 outputs is a readable MPEG-TS
+    let source = match &recording.segments {
+        Some(retention) => {
+            let files = retention.segment_files();
+            if files.len() < 2 {
+                return Err("Recording has no finished segment yet; try again in a few seconds".to_string());
+            }
+            files[files.len() - 2].to_string_lossy().to_string()
+        }
+        None => recording.temp_path.clone(),
+    };
+    if std::fs::metadata(&source).map(|m| m.len()).unwrap_or(0) == 0 {
+        return Err("Recording file hasn't been written yet; try again in a moment".to_string());
+    }
+
+    // Decode the last couple of seconds and keep overwriting the image, so the final
+    // write is the newest complete frame. The recording itself is only read.
+    let args = vec![
+        "-y".to_string(),
+        "-sseof".to_string(), "-2".to_string(),
+        "-i".to_string(), source.clone(),
+        "-an".to_string(),
+        "-update".to_string(), "1".to_string(),
+        "-q:v".to_string(), "2".to_string(),
+        output_path.clone(),
+    ];
+    run_ffmpeg(args, "snapshot_recording").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[snapshot_recording] Snapshot saved to {}", output_path);
+    Ok(output_path)
+}
+
+#[tauri::command]
+fn stop_screen_recording() -> Result<String, CommandError> {
+    use std::io::Write;
+
+    println!("[stop_screen_recording] Stopping screen recording");
+
+    let mut process = RECORDING_PROCESS.lock().unwrap();
+
+    if let Some(mut child) = process.take() {
+        let recording = RECORDING_OUTPUT.lock().unwrap().take();
+        let mut killed = false;
+
+        // Send 'q' to FFmpeg stdin to gracefully stop
+        if let Some(mut stdin) = child.stdin.take() {
+            println!("[stop_screen_recording] Sending 'q' to FFmpeg to stop gracefully");
+            if let Err(e) = stdin.write_all(b"q") {
+                println!("[stop_screen_recording] Warning: Failed to send 'q' to FFmpeg: {}", e);
+                // Fall back to kill if we can't write to stdin
+                child.kill()
+                    .map_err(|e| format!("Failed to stop recording: {}", e))?;
+                killed = true;
+            } else {
+                // Flush to ensure 'q' is sent
+                let _ = stdin.flush();
+                drop(stdin); // Close stdin
+            }
+        } else {
+            println!("[stop_screen_recording] No stdin available, using kill");
+            child.kill()
+                .map_err(|e| format!("Failed to stop recording: {}", e))?;
+            killed = true;
+        }
+
+        // Wait for FFmpeg to finish encoding
+        println!("[stop_screen_recording] Waiting for FFmpeg to finish encoding...");
+        let status = child.wait()
+            .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+
+        // Move the temp file to the requested path (or keep it as .partial)
+        let recording = recording.ok_or_else(|| CommandError::new(ErrorCode::Failed, "Recording output was not tracked"))?;
+        let output_path = finalize_recording(recording, !killed && status.success())?;
+        println!("[stop_screen_recording] Recording saved to {}", output_path);
+        Ok(output_path)
+    } else {
+        Err(CommandError::invalid_input("No recording in progress"))
+    }
+}
+
+#[tauri::command]
+fn is_recording() -> bool {
+    let process = RECORDING_PROCESS.lock().unwrap();
+    process.is_some()
+}
+
+// MIME type of the fragmented MP4 preview stream (H.264 constrained baseline, level 3.1),
+// suitable for MediaSource.addSourceBuffer in the webview
+const FMP4_PREVIEW_MIME: &str = "video/mp4; codecs=\"avc1.42E01F\"";
+
+#[derive(Debug, Serialize, Clone)]
+struct PreviewInitSegment {
+    mime_type: String,
+    data: String, // Base64 ftyp + moov boxes
+}
+
+// Helper function to split a fragmented MP4 byte stream into MSE-ready pieces
+// Emits the init segment (everything up to and including moov) once, then one event per
+// moof + mdat fragment, so the webview appends whole fragments instead of single frames.
+fn stream_fmp4_preview(mut stdout: impl std::io::Read, window: tauri::Window) {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut temp_buf = [0u8; 65536];
+    let mut init_segment: Option<Vec<u8>> = Some(Vec::new());
+    let mut fragment: Vec<u8> = Vec::new();
+
+    loop {
+        match stdout.read(&mut temp_buf) {
+            Ok(0) => {
+                println!("[start_screen_preview] EOF reached, stopping preview thread");
+                break;
+            }
+            Ok(n) => {
+                buffer.extend_from_slice(&temp_buf[..n]);
+
+                // Take every complete top-level box out of the buffer
+                while buffer.len() >= 8 {
+                    let size = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+                    let box_type = [buffer[4], buffer[5], buffer[6], buffer[7]];
+                    let box_size = if size == 1 {
+                        // 64-bit size follows the header
+                        if buffer.len() < 16 {
+                            break;
+                        }
+                        let mut large = [0u8; 8];
+                        large.copy_from_slice(&buffer[8..16]);
+                        u64::from_be_bytes(large) as usize
+                    } else {
+                        size
+                    };
+                    if box_size < 8 {
+                        println!("[start_screen_preview] Invalid MP4 box in preview stream, stopping");
+                        return;
+                    }
+                    if buffer.len() < box_size {
+                        break;
+                    }
+
+                    let mp4_box: Vec<u8> = buffer.drain(..box_size).collect();
+                    if let Some(mut init) = init_segment.take() {
+                        init.extend_from_slice(&mp4_box);
+                        if &box_type == b"moov" {
+                            let _ = window.emit("screen-preview-init", PreviewInitSegment {
+                                mime_type: FMP4_PREVIEW_MIME.to_string(),
+                                data: general_purpose::STANDARD.encode(&init),
+                            });
+                        } else {
+                            init_segment = Some(init);
+                        }
+                        continue;
+                    }
+
+                    fragment.extend_from_slice(&mp4_box);
+                    if &box_type == b"mdat" {
+                        let _ = window.emit("screen-preview-segment", general_purpose::STANDARD.encode(&fragment));
+                        fragment.clear();
+                    }
+                }
+            }
+            Err(e) => {
+                println!("[start_screen_preview] Error reading stdout: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+// Helper function for the platform-specific screen capture input used by previews
+// Use lower quality and framerate for preview
+fn preview_capture_args() -> Result<Vec<&'static str>, String> {
+    if cfg!(target_os = "macos") {
+        Ok(vec![
+            "-f", "avfoundation",
+            "-framerate", "15",          // Lower framerate for preview
+            "-video_size", "640x360",    // Lower resolution for preview
+            "-i", "1",                    // Screen capture (1 = main display)
+        ])
+    } else if cfg!(target_os = "windows") {
+        Ok(vec![
+            "-f", "gdigrab",
+            "-framerate", "15",
+            "-video_size", "640x360",
+            "-i", "desktop",
+        ])
+    } else {
+        Err("Unsupported platform".to_string())
+    }
+}
+
+#[tauri::command]
+fn start_screen_preview(window: tauri::Window, mode: Option<String>) -> Result<String, String> {
+    use std::io::Read;
+    use std::thread;
+
+    println!("[start_screen_preview] Starting screen preview");
+
+    // "mjpeg" (default) emits one base64 JPEG per frame; "fmp4" emits a low-latency H.264
+    // fragmented MP4 stream for MediaSource playback
+    let fmp4 = match mode.as_deref() {
+        Some("mjpeg") | None => false,
+        Some("fmp4") => true,
+        Some(other) => return Err(format!("Invalid preview mode: {}", other)),
+    };
+
+    // Check if preview is already running
+    // A preview whose FFmpeg has exited (capture lost, window closed) no longer holds the slot
+    let mut process = SCREEN_PREVIEW_PROCESS.lock().unwrap();
+    if let Some(child) = process.as_mut() {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                println!("[start_screen_preview] Previous preview exited ({}); starting a new one", status);
+                *process = None;
+            }
+            _ => return Err("Preview already running".to_string()),
+        }
+    }
+
+    let mut args = preview_capture_args()?;
+
+    if fmp4 {
+        args.extend([
+            "-c:v", "libx264",
+            "-preset", "ultrafast",
+            "-tune", "zerolatency",
+            "-profile:v", "baseline",
+            "-level", "3.1",
+            "-pix_fmt", "yuv420p",
+            "-g", "15",                   // Keyframe (and fragment) every second
+            "-f", "mp4",
+            "-movflags", "frag_keyframe+empty_moov+default_base_moof",
+            "-"                           // Output to stdout
+        ]);
+    } else {
+        args.extend([
+            "-f", "image2pipe",
+            "-vcodec", "mjpeg",
+            "-q:v", "10",                 // JPEG quality (2-31, lower is better)
+            "-"                           // Output to stdout
+        ]);
+    }
+
+    println!("[start_screen_preview] FFmpeg args: {:?}", args);
+
+    // Start FFmpeg process with stdout piped
+    let ffmpeg_path = get_ffmpeg_path();
+    let mut child = Command::new(&ffmpeg_path)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start preview: {}", e))?;
+
+    // Get stdout handle
+    let mut stdout = child.stdout.take()
+        .ok_or("Failed to get stdout")?;
+
+    println!("[start_screen_preview] FFmpeg process started, spawning reader thread");
+
+    if fmp4 {
+        thread::spawn(move || stream_fmp4_preview(stdout, window));
+        *process = Some(child);
+        return Ok("Preview started".to_string());
+    }
+
+    // Spawn a thread to read frames and emit events
+    thread::spawn(move || {
         let mut buffer = Vec::new();
         let mut temp_buf = [0u8; 8192];
         let jpeg_start = [0xFF, 0xD8]; // JPEG start marker
         let jpeg_end = [0xFF, 0xD9];   // JPEG end marker
 
-        loop {
-            match stdout.read(&mut temp_buf) {
-                Ok(0) => {
-                    println!("[start_screen_preview] EOF reached, stopping preview thread");
-                    break;
-                }
-                Ok(n) => {
-                    buffer.extend_from_slice(&temp_buf[..n]);
+        loop {
+            match stdout.read(&mut temp_buf) {
+                Ok(0) => {
+                    println!("[start_screen_preview] EOF reached, stopping preview thread");
+                    break;
+                }
+                Ok(n) => {
+                    buffer.extend_from_slice(&temp_buf[..n]);
+
+                    // Look for complete JPEG frames
+                    while let Some(start_pos) = buffer.windows(2).position(|w| w == jpeg_start) {
+                        if start_pos > 0 {
+                            // Remove any data before the JPEG start
+                            buffer.drain(..start_pos);
+                        }
+
+                        // Look for JPEG end marker
+                        if let Some(end_pos) = buffer.windows(2).position(|w| w == jpeg_end) {
+                            // Extract complete JPEG frame (including end marker)
+                            let frame_data = buffer.drain(..(end_pos + 2)).collect::<Vec<u8>>();
+
+                            // Encode as base64 and emit event
+                            let base64_frame = general_purpose::STANDARD.encode(&frame_data);
+                            let _ = window.emit("screen-preview-frame", base64_frame);
+                        } else {
+                            // No end marker yet, wait for more data
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("[start_screen_preview] Error reading stdout: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    *process = Some(child);
+    Ok("Preview started".to_string())
+}
+
+#[tauri::command]
+async fn save_preview_recording(output_path: String, duration: f64) -> Result<String, String> {
+    println!("[save_preview_recording] Capturing {}s preview to {}", duration, output_path);
+
+    if !(duration > 0.0 && duration <= 600.0) {
+        return Err(format!("Invalid preview recording duration: {} (expected up to 600s)", duration));
+    }
+
+    // Same capture as the live preview, encoded to a small H.264 file instead of MJPEG frames.
+    // Runs as its own process, separate from the recording and preview slots.
+    let mut args: Vec<String> = vec!["-y".to_string()];
+    args.extend(preview_capture_args()?.into_iter().map(|arg| arg.to_string()));
+    args.extend([
+        "-t".to_string(), duration.to_string(),
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "veryfast".to_string(),
+        "-crf".to_string(), "28".to_string(),
+        "-pix_fmt".to_string(), "yuv420p".to_string(),
+    ]);
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "save_preview_recording").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[save_preview_recording] Saved {}", output_path);
+    Ok(output_path)
+}
+
+#[tauri::command]
+fn stop_screen_preview() -> Result<String, String> {
+    println!("[stop_screen_preview] Stopping screen preview");
+
+    let mut process = SCREEN_PREVIEW_PROCESS.lock().unwrap();
+
+    if let Some(mut child) = process.take() {
+        if let Ok(Some(status)) = child.try_wait() {
+            println!("[stop_screen_preview] Preview had already exited ({})", status);
+            return Ok("Preview stopped".to_string());
+        }
+
+        child.kill()
+            .map_err(|e| format!("Failed to stop preview: {}", e))?;
+
+        child.wait()
+            .map_err(|e| format!("Failed to wait for preview: {}", e))?;
+
+        println!("[stop_screen_preview] Preview stopped successfully");
+        Ok("Preview stopped".to_string())
+    } else {
+        Err("No preview running".to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct MicLevel {
+    db: f64,    // RMS level in dBFS (-inf for silence)
+    level: f64, // 0.0-1.0, mapped linearly from -60 dBFS to 0 dBFS
+}
+
+#[tauri::command]
+fn start_mic_monitor(audio_device: Option<String>, window: tauri::Window) -> Result<String, String> {
+    use std::io::BufRead;
+    use std::thread;
+
+    println!("[start_mic_monitor] Starting mic monitor: {:?}", audio_device);
+
+    // A monitor whose FFmpeg has exited (microphone unplugged) no longer holds the slot
+    let mut process = MIC_MONITOR_PROCESS.lock().unwrap();
+    if let Some(child) = process.as_mut() {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                println!("[start_mic_monitor] Previous monitor exited ({}); starting a new one", status);
+                *process = None;
+            }
+            _ => return Err("Mic monitor already running".to_string()),
+        }
+    }
+
+    let input_args = if cfg!(target_os = "macos") {
+        let opts = RecordingOptions {
+            audio_device: audio_device.clone(),
+            ..Default::default()
+        };
+        let audio_index = resolve_avfoundation_audio_index(&opts)?;
+        vec!["-f".to_string(), "avfoundation".to_string(), "-i".to_string(), format!(":{}", audio_index)]
+    } else if cfg!(target_os = "windows") {
+        // dshow has no "default" device, so fall back to the first microphone
+        let name = match audio_device.as_deref() {
+            None | Some("") | Some("default") => list_audio_video_devices()?
+                .audio_devices
+                .first()
+                .map(|d| d.name.clone())
+                .ok_or("No audio input devices found")?,
+            Some(name) => name.to_string(),
+        };
+        vec!["-f".to_string(), "dshow".to_string(), "-i".to_string(), format!("audio={}", name)]
+    } else {
+        let opts = RecordingOptions {
+            audio_device: audio_device.clone(),
+            ..Default::default()
+        };
+        let (format, name) = resolve_linux_audio_input(&opts)?;
+        vec!["-f".to_string(), format.to_string(), "-i".to_string(), name]
+    };
+
+    // Measure the RMS level of every 100ms block and print it to stdout
+    let mut args = vec!["-hide_banner".to_string(), "-nostats".to_string()];
+    args.extend(input_args);
+    args.extend([
+        "-af".to_string(),
+        "aresample=48000,asetnsamples=n=4800,astats=metadata=1:reset=1,ametadata=print:key=lavfi.astats.Overall.RMS_level:file=pipe\\:1".to_string(),
+        "-f".to_string(), "null".to_string(),
+        "-".to_string(),
+    ]);
+
+    println!("[start_mic_monitor] FFmpeg args: {:?}", args);
+
+    let ffmpeg_path = get_ffmpeg_path();
+    let mut child = Command::new(&ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start mic monitor: {}", e))?;
+
+    let stdout = child.stdout.take().ok_or("Failed to get stdout")?;
+
+    // Lines look like "lavfi.astats.Overall.RMS_level=-32.5" (or "=-inf" when silent)
+    thread::spawn(move || {
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            let Some(value) = line.trim().strip_prefix("lavfi.astats.Overall.RMS_level=") else {
+                continue;
+            };
+            let db = value.parse::<f64>().unwrap_or(f64::NEG_INFINITY);
+            let level = if db.is_finite() { ((db + 60.0) / 60.0).clamp(0.0, 1.0) } else { 0.0 };
+            let _ = window.emit("mic-level", MicLevel { db, level });
+        }
+        println!("[start_mic_monitor] Monitor output ended");
+    });
+
+    *process = Some(child);
+    Ok("Mic monitor started".to_string())
+}
+
+#[tauri::command]
+fn stop_mic_monitor() -> Result<String, String> {
+    println!("[stop_mic_monitor] Stopping mic monitor");
+
+    let mut process = MIC_MONITOR_PROCESS.lock().unwrap();
+
+    if let Some(mut child) = process.take() {
+        if let Ok(Some(status)) = child.try_wait() {
+            println!("[stop_mic_monitor] Mic monitor had already exited ({})", status);
+            return Ok("Mic monitor stopped".to_string());
+        }
+
+        child.kill()
+            .map_err(|e| format!("Failed to stop mic monitor: {}", e))?;
+
+        child.wait()
+            .map_err(|e| format!("Failed to wait for mic monitor: {}", e))?;
+
+        println!("[stop_mic_monitor] Mic monitor stopped");
+        Ok("Mic monitor stopped".to_string())
+    } else {
+        Err("No mic monitor running".to_string())
+    }
+}
+
+#[tauri::command]
+async fn start_camera_recording(
+    output_path: String,
+    options: Option<RecordingOptions>,
+    window: tauri::Window
+) -> Result<String, CommandError> {
+    println!("[start_camera_recording] Starting camera recording");
+
+    // An empty path records to an auto-named file, which is returned instead of the status message
+    let auto_named = output_path.is_empty();
+    let output_path = if auto_named { default_output_path("camera", "mp4")? } else { output_path };
+    println!("[start_camera_recording] Output path: {}", output_path);
+
+    // Parse resolution options
+    let opts = options.unwrap_or_else(|| RecordingOptions {
+        resolution: "720p".to_string(),
+        ..Default::default()
+    });
+
+    // Determine resolution and bitrate
+    let (width, height, bitrate) = match opts.resolution.as_str() {
+        "720p" => (1280, 720, "2500k"),
+        "1080p" => (1920, 1080, "5000k"),
+        "source" => {
+            if let (Some(w), Some(h)) = (opts.source_width, opts.source_height) {
+                (w, h, "8000k")
+            } else {
+                return Err(CommandError::invalid_input("Source resolution not available"));
+            }
+        }
+        _ => return Err(CommandError::invalid_input(format!("Invalid resolution: {}", opts.resolution))),
+    };
+
+    println!("[start_camera_recording] Resolution: {}x{} @ {} bitrate", width, height, bitrate);
+
+    if opts.retain_last_seconds.is_some() || opts.max_total_size_mb.is_some() {
+        return Err(CommandError::invalid_input("Recording retention limits are only supported for screen recordings"));
+    }
+
+    if opts.max_duration == Some(0) {
+        return Err(CommandError::invalid_input("max_duration must be greater than zero"));
+    }
+    let trim_start = recording_trim_start(&opts).map_err(CommandError::invalid_input)?;
+    let color_range = recording_color_range(&opts)?;
+    let (x264_preset, x264_tune) = recording_x264_settings(&opts)?;
+
+    // Platform-specific FFmpeg arguments for camera
+    let resolution_str = format!("{}x{}", width, height);
+
+    // Audio is recorded when a device is selected by name or by index, unless explicitly overridden
+    let has_audio_device = opts.audio_device.is_some() || opts.audio_device_index.is_some();
+    let record_audio = opts.record_audio.unwrap_or(has_audio_device);
+    if cfg!(target_os = "windows") && record_audio && !has_audio_device {
+        return Err(CommandError::invalid_input("record_audio requires an audio device on Windows"));
+    }
+    // The macOS camera has always captured the default microphone, so only an explicit false disables it
+    let macos_record_audio = opts.record_audio.unwrap_or(true);
+    let camera_has_audio = if cfg!(target_os = "macos") { macos_record_audio } else { record_audio };
+    let audio_filter = if camera_has_audio { recording_audio_filter(&opts)? } else { None };
+
+    // Prepare Windows dshow inputs (if needed) before args to ensure proper lifetime
+    // Camera and microphone are opened as separate dshow inputs, so they can be different
+    // devices; names are checked against the device list so a typo fails before FFmpeg starts
+    let (windows_video_input, windows_video_number, windows_audio_input, windows_audio_number) = if cfg!(target_os = "windows") {
+        let wants_video_device = opts.video_device_index.is_some() || opts.video_device.is_some();
+        let wants_audio_device = record_audio && (opts.audio_device_index.is_some() || opts.audio_device.is_some());
+        let devices = if wants_video_device || wants_audio_device {
+            Some(list_audio_video_devices()?)
+        } else {
+            None
+        };
+
+        let find_by_name = |list: &[DeviceInfo], name: &str, kind: &str| -> Result<(String, usize), CommandError> {
+            if list.iter().any(|d| d.name == name) {
+                Ok((name.to_string(), 0))
+            } else {
+                let available: Vec<&str> = list.iter().map(|d| d.name.as_str()).collect();
+                Err(CommandError::invalid_input(format!(
+                    "{} not found: {} (available: {})",
+                    kind, name, available.join(", ")
+                )))
+            }
+        };
+
+        let (video_name, video_number) = match (opts.video_device_index, &opts.video_device, &devices) {
+            (Some(index), _, Some(devices)) => resolve_dshow_device(&devices.video_devices, index)?,
+            (None, Some(name), Some(devices)) => find_by_name(&devices.video_devices, name, "Camera")?,
+            _ => ("Integrated Camera".to_string(), 0),
+        };
+
+        let audio = match (opts.audio_device_index, &opts.audio_device, &devices) {
+            _ if !record_audio => None,
+            (Some(index), _, Some(devices)) => Some(resolve_dshow_device(&devices.audio_devices, index)?),
+            (None, Some(name), Some(devices)) => Some(find_by_name(&devices.audio_devices, name, "Microphone")?),
+            _ => None,
+        };
+
+        println!("[start_camera_recording] dshow camera: {} (#{}), microphone: {:?}", video_name, video_number, audio);
+        (
+            format!("video={}", video_name),
+            video_number.to_string(),
+            audio.as_ref().map(|(name, _)| format!("audio={}", name)),
+            audio.map(|(_, number)| number).unwrap_or(0).to_string(),
+        )
+    } else {
+        (String::new(), "0".to_string(), None, "0".to_string())
+    };
+
+    // macOS avfoundation input: "{camera index}:{microphone index}", or just the camera for video-only
+    let macos_input_str = if cfg!(target_os = "macos") {
+        if macos_record_audio {
+            format!(
+                "{}:{}",
+                opts.video_device_index.unwrap_or(0),
+                resolve_avfoundation_audio_index(&opts)?
+            )
+        } else {
+            opts.video_device_index.unwrap_or(0).to_string()
+        }
+    } else {
+        String::new()
+    };
+
+    // Linux v4l2 camera (/dev/videoN) and pulse/alsa microphone
+    let (linux_video_device, linux_audio_input) = if cfg!(target_os = "linux") {
+        let audio = if record_audio { Some(resolve_linux_audio_input(&opts)?) } else { None };
+        (format!("/dev/video{}", opts.video_device_index.unwrap_or(0)), audio)
+    } else {
+        (String::new(), None)
+    };
+
+    // Optional overlays (e.g. burned-in timestamp); camera capture is not scaled
+    let video_filter = build_recording_video_filter(&opts, None);
+    let max_duration_str = opts.max_duration.map(|d| d.to_string());
+    let trim_start_str = trim_start.map(|s| s.to_string());
+
+    let mut args = if cfg!(target_os = "macos") {
+        let mut args = vec![
+            "-f", "avfoundation",
+            "-framerate", "30",
+            "-video_size", &resolution_str,
+            "-i", &macos_input_str, // Camera device : microphone device (default 0:0)
+        ];
+
+        if let Some(ref filter) = video_filter {
+            args.push("-vf");
+            args.push(filter);
+        }
+
+        args.extend([
+            "-pix_fmt", "yuv420p",
+            "-c:v", "libx264",
+            "-preset", x264_preset,
+            "-b:v", bitrate,
+        ]);
+
+        if macos_record_audio {
+            args.extend([
+                "-c:a", "aac",          // Audio codec
+                "-b:a", "192k",         // Audio bitrate
+            ]);
+        }
+        args
+    } else if cfg!(target_os = "windows") {
+        let mut args = vec![
+            "-f", "dshow",
+            "-framerate", "30",
+            "-video_size", &resolution_str,
+            "-video_device_number", &windows_video_number,
+            "-i", &windows_video_input,
+        ];
+
+        // Microphone as its own dshow input, mapped alongside the camera
+        if let Some(ref audio_input) = windows_audio_input {
+            args.extend([
+                "-f", "dshow",
+                "-audio_device_number", &windows_audio_number,
+                "-i", audio_input,
+            ]);
+        }
+        args.extend(["-map", "0:v:0"]);
+        if windows_audio_input.is_some() {
+            args.extend(["-map", "1:a:0"]);
+        }
+
+        if let Some(ref filter) = video_filter {
+            args.push("-vf");
+            args.push(filter);
+        }
+
+        args.extend([
+            "-pix_fmt", "yuv420p",
+            "-c:v", "libx264",
+            "-preset", x264_preset,
+            "-b:v", bitrate,
+        ]);
+
+        // Add audio codec parameters if audio device is provided
+        if record_audio {
+            args.push("-c:a");
+            args.push("aac");
+            args.push("-b:a");
+            args.push("192k");
+        }
+
+        args
+    } else if cfg!(target_os = "linux") {
+        let mut args = vec![
+            "-f", "v4l2",
+            "-framerate", "30",
+            "-video_size", &resolution_str,
+            "-i", &linux_video_device,
+        ];
+
+        if let Some((format, device)) = &linux_audio_input {
+            args.extend(["-f", *format, "-i", device.as_str()]);
+        }
+
+        if let Some(ref filter) = video_filter {
+            args.push("-vf");
+            args.push(filter);
+        }
+
+        args.extend([
+            "-pix_fmt", "yuv420p",
+            "-c:v", "libx264",
+            "-preset", x264_preset,
+            "-b:v", bitrate,
+        ]);
+
+        if record_audio {
+            args.extend(["-c:a", "aac", "-b:a", "192k"]);
+        }
+
+        args
+    } else {
+        return Err(CommandError::new(ErrorCode::Failed, "Unsupported platform"));
+    };
+
+    // Tag the stream with the range the filter converted to
+    if let Some(range) = color_range {
+        args.push("-color_range");
+        args.push(range);
+    }
+
+    if let Some(tune) = x264_tune {
+        args.push("-tune");
+        args.push(tune);
+    }
+
+    if let Some(ref filter) = audio_filter {
+        args.push("-af");
+        args.push(filter);
+    }
+
+    // Stop automatically once the safety cap is reached
+    if let Some(ref max_duration) = max_duration_str {
+        args.push("-t");
+        args.push(max_duration);
+    }
+
+    // Drop the encoder warm-up at the start
+    if let Some(ref seconds) = trim_start_str {
+        args.push("-ss");
+        args.push(seconds);
+    }
+
+    // Record to a temp file; stop_camera_recording moves it into place
+    let recording = PendingRecording::new(&output_path);
+    args.push(&recording.temp_path);
+
+    println!("[start_camera_recording] FFmpeg args: {:?}", args);
+
+    // Start FFmpeg process with stdin pipe for graceful shutdown
+    let ffmpeg_path = get_ffmpeg_path();
+    let child = Command::new(&ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CommandError::spawn_failed(e, "Failed to start camera recording"))?;
+
+    // A busy camera/mic only shows up once FFmpeg tries to open it
+    let device = if camera_has_audio { "camera or microphone" } else { "camera" };
+    let child = match check_recording_startup(child, device, &recording.temp_path).await {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = std::fs::remove_file(&recording.temp_path);
+            return Err(e);
+        }
+    };
+
+    println!("[start_camera_recording] FFmpeg process started");
+
+    // Store process
+    let pid = child.id();
+    *CAMERA_RECORDING_OUTPUT.lock().unwrap() = Some(recording);
+    let mut process = CAMERA_RECORDING_PROCESS.lock().unwrap();
+    *process = Some(child);
+    drop(process);
+
+    if let Some(max_duration) = opts.max_duration {
+        let max_duration = max_duration + trim_start.map(|s| s.ceil() as u32).unwrap_or(0);
+        spawn_auto_stop_watcher(
+            CAMERA_RECORDING_PROCESS.clone(),
+            Some(CAMERA_RECORDING_OUTPUT.clone()),
+            pid,
+            "camera",
+            max_duration,
+            window,
+        );
+    }
+
+    if auto_named {
+        return Ok(output_path);
+    }
+    Ok("Camera recording started".to_string())
+}
+
+#[tauri::command]
+fn stop_camera_recording() -> Result<String, CommandError> {
+    use std::io::Write;
+
+    println!("[stop_camera_recording] Stopping camera recording");
+
+    let mut process = CAMERA_RECORDING_PROCESS.lock().unwrap();
+
+    if let Some(mut child) = process.take() {
+        let recording = CAMERA_RECORDING_OUTPUT.lock().unwrap().take();
+        let mut killed = false;
+
+        // Send 'q' to FFmpeg stdin to gracefully stop
+        if let Some(mut stdin) = child.stdin.take() {
+            println!("[stop_camera_recording] Sending 'q' to FFmpeg to stop gracefully");
+            if let Err(e) = stdin.write_all(b"q") {
+                println!("[stop_camera_recording] Warning: Failed to send 'q' to FFmpeg: {}", e);
+                child.kill()
+                    .map_err(|e| format!("Failed to stop camera recording: {}", e))?;
+                killed = true;
+            } else {
+                let _ = stdin.flush();
+                drop(stdin);
+            }
+        } else {
+            println!("[stop_camera_recording] No stdin available, using kill");
+            child.kill()
+                .map_err(|e| format!("Failed to stop camera recording: {}", e))?;
+            killed = true;
+        }
+
+        println!("[stop_camera_recording] Waiting for FFmpeg to finish encoding...");
+        let status = child.wait()
+            .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+
+        // Move the temp file to the requested path (or keep it as .partial)
+        let recording = recording.ok_or_else(|| CommandError::new(ErrorCode::Failed, "Camera recording output was not tracked"))?;
+        let output_path = finalize_recording(recording, !killed && status.success())?;
+        println!("[stop_camera_recording] Camera recording saved to {}", output_path);
+        Ok(output_path)
+    } else {
+        Err(CommandError::invalid_input("No camera recording in progress"))
+    }
+}
+
+#[tauri::command]
+fn is_camera_recording() -> bool {
+    let process = CAMERA_RECORDING_PROCESS.lock().unwrap();
+    process.is_some()
+}
+
+// Frame rate used when the display refresh rate can't be detected
+const DEFAULT_CAPTURE_FPS: u32 = 30;
+
+// Helper function to pull the refresh rate out of platform display tool output
+//   macOS system_profiler: "UI Looks like: 1728 x 1117 @ 120.00Hz"
+//   Windows CIM:           "144"
+//   Linux xrandr:          "   2560x1440    165.00*+ 143.97   60.00"
+fn parse_refresh_rate(output: &str) -> Option<f64> {
+    let valid = |hz: f64| (1.0..=1000.0).contains(&hz).then_some(hz);
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some((_, rate)) = line.rsplit_once('@') {
+            if let Ok(hz) = rate.trim().trim_end_matches("Hz").trim().parse::<f64>() {
+                return valid(hz);
+            }
+        } else if line.contains('*') {
+            // xrandr marks the active mode with '*'
+            let active = line.split_whitespace().find(|token| token.contains('*'))?;
+            return valid(active.trim_end_matches(['*', '+']).parse::<f64>().ok()?);
+        } else if let Ok(hz) = line.parse::<f64>() {
+            return valid(hz);
+        }
+    }
+    None
+}
+
+// Helper function to detect the main display's refresh rate in Hz
+fn detect_display_refresh_rate() -> Option<f64> {
+    let output = if cfg!(target_os = "macos") {
+        output_with_timeout(Command::new("system_profiler").arg("SPDisplaysDataType"))
+    } else if cfg!(target_os = "windows") {
+        output_with_timeout(Command::new("powershell").args([
+            "-NoProfile",
+            "-Command",
+            "(Get-CimInstance Win32_VideoController | Select-Object -First 1).CurrentRefreshRate",
+        ]))
+    } else {
+        output_with_timeout(Command::new("xrandr").arg("--current"))
+    }
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    parse_refresh_rate(&String::from_utf8_lossy(&output.stdout))
+}
+
+// Helper function to pick the screen capture rate: explicit framerate, else the display
+// refresh rate capped at max_framerate, else DEFAULT_CAPTURE_FPS
+fn resolve_capture_framerate(opts: &RecordingOptions) -> Result<u32, String> {
+    let max_framerate = opts.max_framerate.unwrap_or(60);
+    if max_framerate == 0 || opts.framerate == Some(0) {
+        return Err("Recording framerate must be greater than zero".to_string());
+    }
+    if let Some(framerate) = opts.framerate {
+        return Ok(framerate);
+    }
+
+    match detect_display_refresh_rate() {
+        Some(hz) => {
+            let framerate = (hz.round() as u32).min(max_framerate);
+            println!("[resolve_capture_framerate] Display refresh rate {:.2} Hz, capturing at {} fps", hz, framerate);
+            Ok(framerate)
+        }
+        None => {
+            println!("[resolve_capture_framerate] Could not detect refresh rate, using {} fps", DEFAULT_CAPTURE_FPS);
+            Ok(DEFAULT_CAPTURE_FPS.min(max_framerate))
+        }
+    }
+}
+
+#[tauri::command]
+fn get_screen_resolution() -> Result<ScreenResolution, String> {
+    // Note: These are default values. In a production app, you might want to query
+    // the actual screen resolution using platform-specific APIs.
+    // For now, returning common defaults that work with scaling filters
+    Ok(ScreenResolution {
+        width: 1920,
+        height: 1080,
+        refresh_rate: detect_display_refresh_rate().unwrap_or(DEFAULT_CAPTURE_FPS as f64),
+    })
+}
+
+#[tauri::command]
+fn get_camera_capabilities() -> Result<CameraCapabilities, String> {
+    // Note: These are default values. Camera capabilities vary by device.
+    // Supported resolutions should match what's being used in the recording commands.
+    Ok(CameraCapabilities {
+        native_width: 1920,
+        native_height: 1080,
+        supported_resolutions: vec!["720p".to_string(), "1080p".to_string(), "source".to_string()],
+    })
+}
+
+// Helper function to parse `ffmpeg -f avfoundation -list_devices true -i ""` stderr
+// AVFoundation prints a section header followed by indexed devices, e.g.:
+//   [AVFoundation indev @ 0x7f8] AVFoundation video devices:
+//   [AVFoundation indev @ 0x7f8] [0] FaceTime HD Camera
+//   [AVFoundation indev @ 0x7f8] [1] Capture screen 0
+//   [AVFoundation indev @ 0x7f8] AVFoundation audio devices:
+//   [AVFoundation indev @ 0x7f8] [0] MacBook Pro Microphone
+fn parse_avfoundation_devices(stderr: &str) -> AudioVideoDevices {
+    let mut video_devices = Vec::new();
+    let mut audio_devices = Vec::new();
+    let mut in_video_section = false;
+    let mut in_audio_section = false;
+
+    for line in stderr.lines() {
+        if !line.starts_with("[AVFoundation") {
+            continue;
+        }
+
+        // Strip the "[AVFoundation indev @ 0x...]" log prefix
+        let content = match line.find(']') {
+            Some(prefix_end) => line[prefix_end + 1..].trim(),
+            None => continue,
+        };
+
+        if content.contains("video devices:") {
+            in_video_section = true;
+            in_audio_section = false;
+            continue;
+        } else if content.contains("audio devices:") {
+            in_video_section = false;
+            in_audio_section = true;
+            continue;
+        }
+
+        // Device lines look like "[0] Device Name"
+        let Some(rest) = content.strip_prefix('[') else { continue };
+        let Some(index_end) = rest.find(']') else { continue };
+        let Ok(index) = rest[..index_end].parse::<i32>() else { continue };
+
+        let device_name = rest[index_end + 1..].trim();
+        if device_name.is_empty() {
+            continue;
+        }
+
+        let device = DeviceInfo {
+            index,
+            name: device_name.to_string(),
+        };
+
+        if in_video_section {
+            video_devices.push(device);
+        } else if in_audio_section {
+            audio_devices.push(device);
+        }
+    }
+
+    AudioVideoDevices {
+        video_devices,
+        audio_devices,
+    }
+}
+
+// Helper function to parse `pactl list sources` output
+// Each source starts with a "Source #N" header followed by indented properties, e.g.:
+//   Source #1
+//   	State: SUSPENDED
+//   	Name: alsa_input.pci-0000_00_1f.3.analog-stereo
+//   	Description: Built-in Audio Analog Stereo
+// The Name is what `-f pulse -i` expects, so that's what gets reported.
+fn parse_pactl_sources(stdout: &str) -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+    let mut current_index = None;
+
+    for line in stdout.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Source #") {
+            current_index = rest.trim().parse::<i32>().ok();
+        } else if let (Some(name), Some(index)) = (line.strip_prefix("Name:"), current_index) {
+            devices.push(DeviceInfo {
+                index,
+                name: name.trim().to_string(),
+            });
+            current_index = None;
+        }
+    }
+
+    devices
+}
+
+// Helper function to list ALSA capture cards when PulseAudio isn't running
+// /proc/asound/cards lines look like " 0 [PCH            ]: HDA-Intel - HDA Intel PCH"
+fn list_alsa_cards() -> Vec<DeviceInfo> {
+    let cards = std::fs::read_to_string("/proc/asound/cards").unwrap_or_default();
+    cards
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse::<i32>().ok())
+        .map(|card| DeviceInfo {
+            index: card,
+            name: format!("hw:{}", card),
+        })
+        .collect()
+}
+
+// Helper function to list v4l2 capture devices (/dev/video*) in device number order
+fn list_v4l2_devices() -> Vec<DeviceInfo> {
+    let mut devices: Vec<DeviceInfo> = std::fs::read_dir("/dev")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    let index = file_name.strip_prefix("video")?.parse::<i32>().ok()?;
+                    Some(DeviceInfo {
+                        index,
+                        name: format!("/dev/{}", file_name),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    devices.sort_by_key(|d| d.index);
+    devices
+}
+
+// Helper function to pick the Linux audio input for a recording
+// Returns the FFmpeg input format and device: ALSA names ("hw:0", "plughw:1,0") go
+// through -f alsa, everything else is treated as a PulseAudio source name.
+fn resolve_linux_audio_input(opts: &RecordingOptions) -> Result<(&'static str, String), String> {
+    let name = if let Some(index) = opts.audio_device_index {
+        let devices = list_audio_video_devices()?;
+        devices
+            .audio_devices
+            .iter()
+            .find(|d| d.index == index)
+            .map(|d| d.name.clone())
+            .ok_or_else(|| format!("Device index {} not found", index))?
+    } else {
+        match opts.audio_device.as_deref() {
+            None | Some("") => "default".to_string(),
+            Some(name) => name.to_string(),
+        }
+    };
+
+    let format = if name.starts_with("hw:") || name.starts_with("plughw:") { "alsa" } else { "pulse" };
+    Ok((format, name))
+}
+
+// Helper function to pick the avfoundation microphone index for a recording
+// An explicit index wins; otherwise the device name is looked up in the current device list.
+// No selection (or "default") uses index 0, the system default input.
+fn resolve_avfoundation_audio_index(opts: &RecordingOptions) -> Result<i32, String> {
+    if let Some(index) = opts.audio_device_index {
+        return Ok(index);
+    }
+
+    let name = match opts.audio_device.as_deref() {
+        None | Some("") | Some("default") => return Ok(0),
+        Some(name) => name,
+    };
+
+    let devices = list_audio_video_devices()?;
+    devices
+        .audio_devices
+        .iter()
+        .find(|d| d.name == name)
+        .or_else(|| devices.audio_devices.iter().find(|d| d.name.eq_ignore_ascii_case(name)))
+        .map(|d| d.index)
+        .ok_or_else(|| format!("Audio device not found: {}", name))
+}
+
+// Helper function to escape a string for use inside an AppleScript string literal
+fn escape_applescript(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Helper function to run an AppleScript snippet and return its trimmed stdout
+fn run_osascript(script: &str) -> Result<String, String> {
+    let output = output_with_timeout(Command::new("osascript").args(["-e", script]))
+        .map_err(|e| format!("Failed to run osascript: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("osascript failed: {}", stderr.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Helper function to build a crop filter covering a macOS window
+// Window bounds are in points, so they're converted to fractions of the desktop size
+// and applied to the captured frame, which keeps the crop correct on Retina displays.
+fn macos_window_crop_filter(title: &str) -> Result<String, String> {
+    let script = format!(
+        r#"tell application "System Events"
+    repeat with p in (every process whose visible is true)
+        repeat with w in (every window of p)
+            if name of w is "{}" then
+                set {{x, y}} to position of w
+                set {{ww, wh}} to size of w
+                return (x as text) & "," & (y as text) & "," & (ww as text) & "," & (wh as text)
+            end if
+        end repeat
+    end repeat
+end tell
+return """#,
+        escape_applescript(title)
+    );
+    let bounds = run_osascript(&script)?;
+    if bounds.is_empty() {
+        return Err(format!("Window not found: {}", title));
+    }
+
+    let desktop = run_osascript("tell application \"Finder\" to get bounds of window of desktop")?;
+
+    let parse = |text: &str| -> Result<Vec<f64>, String> {
+        text.split(',')
+            .map(|v| v.trim().parse::<f64>().map_err(|e| format!("Invalid window bounds '{}': {}", text, e)))
+            .collect()
+    };
+    let window_bounds = parse(&bounds)?;
+    let desktop_bounds = parse(&desktop)?;
+    if window_bounds.len() != 4 || desktop_bounds.len() != 4 {
+        return Err(format!("Unexpected window bounds: {} / {}", bounds, desktop));
+    }
+
+    let (x, y, w, h) = (window_bounds[0], window_bounds[1], window_bounds[2], window_bounds[3]);
+    let (desktop_w, desktop_h) = (desktop_bounds[2] - desktop_bounds[0], desktop_bounds[3] - desktop_bounds[1]);
+    if w <= 0.0 || h <= 0.0 || desktop_w <= 0.0 || desktop_h <= 0.0 {
+        return Err(format!("Window has no visible area: {}", title));
+    }
+
+    // Keep the crop even-sized for yuv420p
+    Ok(format!(
+        "crop=trunc(iw*{w}/{dw}/2)*2:trunc(ih*{h}/{dh}/2)*2:iw*{x}/{dw}:ih*{y}/{dh}",
+        w = w,
+        h = h,
+        x = x.max(0.0),
+        y = y.max(0.0),
+        dw = desktop_w,
+        dh = desktop_h
+    ))
+}
+
+#[tauri::command]
+fn list_windows() -> Result<Vec<String>, String> {
+    let mut titles: Vec<String> = if cfg!(target_os = "windows") {
+        // Top-level windows with a title, matching what gdigrab's title= input looks up
+        let output = output_with_timeout(Command::new("powershell").args([
+            "-NoProfile",
+            "-Command",
+            "Get-Process | Where-Object { $_.MainWindowTitle } | ForEach-Object { $_.MainWindowTitle }",
+        ]))
+        .map_err(|e| format!("Failed to run PowerShell: {}", e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Failed to list windows: {}", stderr.trim()));
+        }
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect()
+    } else if cfg!(target_os = "macos") {
+        let script = r#"set output to ""
+tell application "System Events"
+    repeat with p in (every process whose visible is true)
+        repeat with w in (every window of p)
+            set output to output & (name of w) & linefeed
+        end repeat
+    end repeat
+end tell
+return output"#;
+        run_osascript(script)?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect()
+    } else {
+        return Err("Unsupported platform".to_string());
+    };
+
+    titles.retain(|title| !title.is_empty() && title != "missing value");
+    titles.sort();
+    titles.dedup();
+    println!("[list_windows] Found {} windows", titles.len());
+    Ok(titles)
+}
+
+#[tauri::command]
+fn list_audio_video_devices() -> Result<AudioVideoDevices, String> {
+    if cfg!(target_os = "windows") {
+        // On Windows, use FFmpeg to list DirectShow devices
+        let ffmpeg_path = get_ffmpeg_path();
+        // This input always fails (the listing goes to stderr), and some dshow setups hang on it
+        let output = output_with_timeout(
+            Command::new(&ffmpeg_path).args(&["-list_devices", "true", "-f", "dshow", "-i", "dummy"]),
+        )
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+        // FFmpeg outputs device list to stderr
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut video_devices = Vec::new();
+        let mut audio_devices = Vec::new();
+        let mut in_video_section = false;
+        let mut in_audio_section = false;
+
+        for line in stderr.lines() {
+            if line.contains("DirectShow video devices") {
+                in_video_section = true;
+                in_audio_section = false;
+            } else if line.contains("DirectShow audio devices") {
+                in_video_section = false;
+                in_audio_section = true;
+            } else if line.starts_with("[dshow") && line.contains("\"") {
+                // Extract device name from lines like: [dshow @ ...] "Device Name"
+                if let Some(start) = line.find('"') {
+                    if let Some(end) = line[start + 1..].find('"') {
+                        let device_name = &line[start + 1..start + 1 + end];
+                        if in_video_section {
+                            video_devices.push(DeviceInfo {
+                                index: video_devices.len() as i32,
+                                name: device_name.to_string(),
+                            });
+                        } else if in_audio_section {
+                            audio_devices.push(DeviceInfo {
+                                index: audio_devices.len() as i32,
+                                name: device_name.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(AudioVideoDevices {
+            video_devices,
+            audio_devices,
+        })
+    } else if cfg!(target_os = "macos") {
+        // On macOS, use FFmpeg to list AVFoundation devices
+        let ffmpeg_path = get_ffmpeg_path();
+        let output = output_with_timeout(
+            Command::new(&ffmpeg_path).args(&["-f", "avfoundation", "-list_devices", "true", "-i", ""]),
+        )
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+        // FFmpeg outputs device list to stderr
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Ok(parse_avfoundation_devices(&stderr))
+    } else if cfg!(target_os = "linux") {
+        // On Linux, audio comes from PulseAudio sources (ALSA cards if pactl is unavailable)
+        let audio_devices = match output_with_timeout(Command::new("pactl").args(["list", "sources"])) {
+            Ok(output) if output.status.success() => parse_pactl_sources(&String::from_utf8_lossy(&output.stdout)),
+            _ => {
+                println!("[list_audio_video_devices] pactl unavailable, listing ALSA cards");
+                list_alsa_cards()
+            }
+        };
+
+        Ok(AudioVideoDevices {
+            video_devices: list_v4l2_devices(),
+            audio_devices,
+        })
+    } else {
+        Err("Unsupported platform".to_string())
+    }
+}
+
+#[tauri::command]
+fn start_device_monitoring(interval_ms: Option<u64>, window: tauri::Window) -> Result<String, String> {
+    use std::thread;
+    use std::time::Duration;
+
+    let mut monitor = DEVICE_MONITOR.lock().unwrap();
+    if monitor.is_some() {
+        return Err("Device monitoring already running".to_string());
+    }
+
+    let interval = Duration::from_millis(interval_ms.unwrap_or(2000).max(500));
+    println!("[start_device_monitoring] Polling devices every {:?}", interval);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+
+    thread::spawn(move || {
+        let mut last_devices = list_audio_video_devices().ok();
+
+        while thread_running.load(Ordering::SeqCst) {
+            thread::sleep(interval);
+            if !thread_running.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match list_audio_video_devices() {
+                Ok(devices) => {
+                    if last_devices.as_ref() != Some(&devices) {
+                        println!("[start_device_monitoring] Device list changed: {:?}", devices);
+                        let _ = window.emit("devices-changed", devices.clone());
+                        last_devices = Some(devices);
+                    }
+                }
+                Err(e) => {
+                    println!("[start_device_monitoring] Failed to enumerate devices: {}", e);
+                }
+            }
+        }
+
+        println!("[start_device_monitoring] Device monitoring thread stopped");
+    });
+
+    *monitor = Some(running);
+    Ok("Device monitoring started".to_string())
+}
+
+#[tauri::command]
+fn stop_device_monitoring() -> Result<String, String> {
+    let mut monitor = DEVICE_MONITOR.lock().unwrap();
+
+    if let Some(running) = monitor.take() {
+        running.store(false, Ordering::SeqCst);
+        println!("[stop_device_monitoring] Device monitoring stopped");
+        Ok("Device monitoring stopped".to_string())
+    } else {
+        Err("Device monitoring not running".to_string())
+    }
+}
+
+#[tauri::command]
+fn move_file(from: String, to: String) -> Result<String, String> {
+    use std::fs;
+    println!("[move_file] Moving file from {} to {}", from, to);
+
+    fs::rename(&from, &to)
+        .map_err(|e| format!("Failed to move file: {}", e))?;
+
+    println!("[move_file] File moved successfully");
+    Ok("File moved".to_string())
+}
+
+#[tauri::command]
+fn delete_file(path: String) -> Result<String, String> {
+    use std::fs;
+    println!("[delete_file] Deleting file: {}", path);
+
+    fs::remove_file(&path)
+        .map_err(|e| format!("Failed to delete file: {}", e))?;
+
+    println!("[delete_file] File deleted successfully");
+    Ok("File deleted".to_string())
+}
+
+#[tauri::command]
+async fn remux(input_path: String, output_path: String) -> Result<String, String> {
+    println!("[remux] Remuxing {} -> {}", input_path, output_path);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+
+    if input_path == output_path {
+        return Err("Input and output paths must differ".to_string());
+    }
+
+    // Stream copy only - FFmpeg infers the output container from the extension
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path,
+        "-map".to_string(), "0".to_string(),
+        "-c".to_string(), "copy".to_string(),
+    ];
+
+    // Move the moov atom to the front so MP4 output can stream before fully downloaded
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "remux").await?;
+
+    println!("[remux] Remux completed successfully");
+    Ok(output_path)
+}
+
+#[tauri::command]
+async fn denoise(input_path: String, output_path: String) -> Result<String, String> {
+    println!("[denoise] {} -> {}", input_path, output_path);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if input_path == output_path {
+        return Err("Input and output paths must differ".to_string());
+    }
+    if !has_audio_stream(&input_path) {
+        return Err("Input has no audio to denoise".to_string());
+    }
+    if !ffmpeg_has_filter("afftdn") {
+        return Err("Noise suppression requires an FFmpeg build with the afftdn filter".to_string());
+    }
+
+    // Video is copied untouched; only the audio is filtered and re-encoded
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path,
+        "-map".to_string(), "0:v?".to_string(),
+        "-map".to_string(), "0:a".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+        "-af".to_string(), DENOISE_FILTER.to_string(),
+        "-c:a".to_string(), "aac".to_string(),
+        "-b:a".to_string(), "192k".to_string(),
+    ];
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "denoise").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[denoise] Noise suppression completed");
+    Ok(output_path)
+}
+
+#[tauri::command]
+async fn mux_av(
+    video_path: String,
+    audio_path: String,
+    output_path: String,
+    offset: Option<f64>,
+) -> Result<String, String> {
+    // Positive offset: the audio started later than the video, so it is delayed.
+    // Negative offset: the audio started earlier, so its head is skipped.
+    let offset = offset.unwrap_or(0.0);
+    println!("[mux_av] {} + {} -> {} (audio offset {}s)", video_path, audio_path, output_path, offset);
+
+    for path in [&video_path, &audio_path] {
+        if !std::path::Path::new(path).exists() {
+            return Err(format!("Input file not found: {}", path));
+        }
+    }
+    if output_path == video_path || output_path == audio_path {
+        return Err("Output path must differ from the inputs".to_string());
+    }
+    if !has_audio_stream(&audio_path) {
+        return Err(format!("No audio stream in {}", audio_path));
+    }
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), video_path,
+    ];
+    if offset > 0.0 {
+        args.push("-itsoffset".to_string());
+        args.push(offset.to_string());
+    } else if offset < 0.0 {
+        args.push("-ss".to_string());
+        args.push((-offset).to_string());
+    }
+    args.push("-i".to_string());
+    args.push(audio_path.clone());
+
+    // AAC can be copied as-is; anything else (e.g. PCM from a WAV) is encoded to AAC
+    let audio_codec = probe_entry(&audio_path, "stream=codec_name", Some("a:0")).unwrap_or_default();
+    let copy_audio = audio_codec.lines().next().map(|c| c.trim() == "aac").unwrap_or(false);
+
+    args.extend([
+        "-map".to_string(), "0:v:0".to_string(),
+        "-map".to_string(), "1:a:0".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+    ]);
+    if copy_audio {
+        args.extend(["-c:a".to_string(), "copy".to_string()]);
+    } else {
+        args.extend([
+            "-c:a".to_string(), "aac".to_string(),
+            "-b:a".to_string(), "192k".to_string(),
+        ]);
+    }
+    args.push("-shortest".to_string());
+
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "mux_av").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[mux_av] Mux completed successfully");
+    Ok(output_path)
+}
+
+#[derive(Debug, Serialize)]
+struct SilenceTrimResult {
+    output_path: String,
+    in_point: f64,  // First non-silent timestamp in the source (seconds)
+    out_point: f64, // Last non-silent timestamp in the source (seconds)
+}
+
+// Helper function to collect (start, end) silence intervals from silencedetect output
+// A silence that runs to the end of the file has no silence_end line, so it ends at `duration`.
+fn parse_silencedetect(stderr: &str, duration: f64) -> Vec<(f64, f64)> {
+    let mut intervals = Vec::new();
+    let mut current_start: Option<f64> = None;
+
+    let value_after = |line: &str, key: &str| -> Option<f64> {
+        let rest = &line[line.find(key)? + key.len()..];
+        rest.split_whitespace().next()?.parse::<f64>().ok()
+    };
+
+    for line in stderr.lines() {
+        if let Some(start) = value_after(line, "silence_start: ") {
+            current_start = Some(start.max(0.0));
+        } else if let Some(end) = value_after(line, "silence_end: ") {
+            if let Some(start) = current_start.take() {
+                intervals.push((start, end));
+            }
+        }
+    }
+
+    if let Some(start) = current_start {
+        intervals.push((start, duration));
+    }
+
+    intervals
+}
+
+// Helper function to collect (start, end) black intervals from blackdetect output, e.g.
+//   [blackdetect @ 0x...] black_start:12.4 black_end:13.1 black_duration:0.7
+fn parse_blackdetect(stderr: &str) -> Vec<(f64, f64)> {
+    let value_after = |line: &str, key: &str| -> Option<f64> {
+        let rest = &line[line.find(key)? + key.len()..];
+        rest.split_whitespace().next()?.parse::<f64>().ok()
+    };
+
+    stderr
+        .lines()
+        .filter_map(|line| Some((value_after(line, "black_start:")?, value_after(line, "black_end:")?)))
+        .collect()
+}
+
+// Helper function to suggest chapter points from black and silent intervals
+// A break is where a black stretch and a silence overlap; the middle of the overlap is suggested.
+// Points at the very start or end are dropped, and points closer than `min_duration` to the
+// previous one are merged into it.
+fn break_points(blacks: &[(f64, f64)], silences: &[(f64, f64)], duration: f64, min_duration: f64) -> Vec<f64> {
+    let mut breaks: Vec<f64> = blacks
+        .iter()
+        .flat_map(|&(black_start, black_end)| {
+            silences.iter().filter_map(move |&(silence_start, silence_end)| {
+                let start = black_start.max(silence_start);
+                let end = black_end.min(silence_end);
+                (end > start).then_some((start + end) / 2.0)
+            })
+        })
+        .filter(|&point| point > 0.0 && point < duration)
+        .collect();
+    breaks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    breaks.dedup_by(|a, b| (*a - *b).abs() < min_duration);
+    breaks
+}
+
+#[tauri::command]
+async fn detect_breaks(
+    input_path: String,
+    black_threshold: Option<f64>,
+    silence_db: Option<f64>,
+    min_duration: Option<f64>,
+) -> Result<Vec<f64>, String> {
+    let black_threshold = black_threshold.unwrap_or(0.10);
+    let silence_db = silence_db.unwrap_or(-40.0);
+    let min_duration = min_duration.unwrap_or(0.5);
+    println!(
+        "[detect_breaks] {} (black <= {}, silence <= {} dB, >= {}s)",
+        input_path, black_threshold, silence_db, min_duration
+    );
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if !(0.0..=1.0).contains(&black_threshold) {
+        return Err(format!("Black threshold must be between 0 and 1: {}", black_threshold));
+    }
+    if silence_db >= 0.0 {
+        return Err(format!("Silence threshold must be negative dB: {}", silence_db));
+    }
+    if min_duration <= 0.0 {
+        return Err(format!("Minimum break duration must be positive: {}", min_duration));
+    }
+    if !has_audio_stream(&input_path) {
+        return Err("Input has no audio to detect silence in".to_string());
+    }
+
+    let duration = probe_duration(&input_path)?;
+
+    // One analysis pass runs both detectors (nothing is written)
+    let ffmpeg_path = get_ffmpeg_path();
+    let detect_input = input_path.clone();
+    let video_filter = format!("blackdetect=d={}:pix_th={}", min_duration, black_threshold);
+    let audio_filter = format!("silencedetect=noise={}dB:d={}", silence_db, min_duration);
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(&ffmpeg_path)
+            .args(["-hide_banner", "-i", &detect_input, "-vf", &video_filter, "-af", &audio_filter, "-f", "null", "-"])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+    .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    if !output.status.success() {
+        return Err("FFmpeg break detection failed".to_string());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let blacks = parse_blackdetect(&stderr);
+    let silences = parse_silencedetect(&stderr, duration);
+    println!("[detect_breaks] {} black intervals, {} silences", blacks.len(), silences.len());
+
+    let breaks = break_points(&blacks, &silences, duration, min_duration);
+
+    println!("[detect_breaks] Found {} candidate chapter points", breaks.len());
+    Ok(breaks)
+}
+
+#[tauri::command]
+async fn trim_silence_edges(
+    input_path: String,
+    output_path: String,
+    threshold_db: Option<f64>,
+) -> Result<SilenceTrimResult, String> {
+    let threshold_db = threshold_db.unwrap_or(-40.0);
+    println!("[trim_silence_edges] {} -> {} (threshold {} dB)", input_path, output_path, threshold_db);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if threshold_db >= 0.0 {
+        return Err(format!("Silence threshold must be negative dB: {}", threshold_db));
+    }
+    if !has_audio_stream(&input_path) {
+        return Err("Input has no audio to detect silence in".to_string());
+    }
+
+    let duration = probe_duration(&input_path)?;
+
+    // Run silencedetect over the whole file (analysis only, nothing is written)
+    let ffmpeg_path = get_ffmpeg_path();
+    let detect_input = input_path.clone();
+    let filter = format!("silencedetect=noise={}dB:d=0.1", threshold_db);
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(&ffmpeg_path)
+            .args(["-hide_banner", "-i", &detect_input, "-vn", "-af", &filter, "-f", "null", "-"])
+            .output()
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+    .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    if !output.status.success() {
+        return Err("FFmpeg silence detection failed".to_string());
+    }
+
+    let silences = parse_silencedetect(&String::from_utf8_lossy(&output.stderr), duration);
+
+    // Only silence touching the start or the end of the file is trimmed
+    const EDGE_TOLERANCE: f64 = 0.05;
+    let in_point = silences
+        .first()
+        .filter(|(start, _)| *start <= EDGE_TOLERANCE)
+        .map(|(_, end)| *end)
+        .unwrap_or(0.0);
+    let out_point = silences
+        .last()
+        .filter(|(_, end)| *end >= duration - EDGE_TOLERANCE)
+        .map(|(start, _)| *start)
+        .unwrap_or(duration);
+
+    if out_point <= in_point {
+        return Err("Input is silent throughout".to_string());
+    }
+    println!("[trim_silence_edges] Keeping {:.3}s to {:.3}s of {:.3}s", in_point, out_point, duration);
+
+    // Re-encode so the cut lands exactly on the detected points
+    let mut args = vec![
+        "-y".to_string(),
+        "-ss".to_string(), in_point.to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-t".to_string(), (out_point - in_point).to_string(),
+    ];
+    if probe_entry(&input_path, "stream=codec_type", Some("v:0")).map(|v| !v.is_empty()).unwrap_or(false) {
+        args.extend([
+            "-c:v".to_string(), "libx264".to_string(),
+            "-preset".to_string(), "fast".to_string(),
+            "-crf".to_string(), "18".to_string(),
+            "-pix_fmt".to_string(), "yuv420p".to_string(),
+        ]);
+    }
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "trim_silence_edges").await?;
+    verify_output_file(&output_path)?;
+
+    Ok(SilenceTrimResult {
+        output_path,
+        in_point,
+        out_point,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct VideoComparison {
+    same_duration: bool, // Within one frame at 30 fps
+    same_resolution: bool,
+    same_codec: bool,
+    duration_delta: f64, // Seconds, b minus a
+    ssim: Option<f64>,   // Average SSIM over the common length (1.0 = identical); None if sizes differ
+}
+
+// Helper function to get the first video stream's (codec, width, height)
+fn probe_video_stream(video_path: &str) -> Result<(String, i32, i32), String> {
+    if !ffprobe_available() {
+        return ffmpeg_probe(video_path)?
+            .streams
+            .iter()
+            .find(|s| s.codec_type == "video")
+            .map(|s| (s.codec_name.clone(), s.width.unwrap_or(0), s.height.unwrap_or(0)))
+            .ok_or_else(|| format!("No video stream in {}", video_path));
+    }
+
+    let entry = probe_entry(video_path, "stream=codec_name,width,height", Some("v:0"))?;
+    let mut parts = entry.lines().next().unwrap_or("").split(',');
+    let codec = parts.next().unwrap_or("").trim().to_string();
+    let width = parts.next().and_then(|w| w.trim().parse::<i32>().ok());
+    let height = parts.next().and_then(|h| h.trim().parse::<i32>().ok());
+    match (codec.is_empty(), width, height) {
+        (false, Some(width), Some(height)) => Ok((codec, width, height)),
+        _ => Err(format!("No video stream in {}", video_path)),
+    }
+}
+
+#[tauri::command]
+async fn compare_videos(path_a: String, path_b: String) -> Result<VideoComparison, String> {
+    println!("[compare_videos] Comparing {} and {}", path_a, path_b);
+
+    for path in [&path_a, &path_b] {
+        if !std::path::Path::new(path).exists() {
+            return Err(format!("Video file not found: {}", path));
+        }
+    }
+
+    let duration_a = probe_duration(&path_a)?;
+    let duration_b = probe_duration(&path_b)?;
+    let (codec_a, width_a, height_a) = probe_video_stream(&path_a)?;
+    let (codec_b, width_b, height_b) = probe_video_stream(&path_b)?;
+    let duration_delta = duration_b - duration_a;
+    let same_resolution = width_a == width_b && height_a == height_b;
+
+    // SSIM needs identical frame sizes; timestamps are reset so files with different start
+    // offsets line up, and comparison stops at the end of the shorter file
+    let ssim = if same_resolution {
+        let ffmpeg_path = get_ffmpeg_path();
+        let (input_a, input_b) = (path_a.clone(), path_b.clone());
+        let output = tokio::task::spawn_blocking(move || {
+            Command::new(&ffmpeg_path)
+                .args([
+                    "-hide_banner",
+                    "-i", &input_a,
+                    "-i", &input_b,
+                    "-lavfi", "[0:v]settb=AVTB,setpts=PTS-STARTPTS[a];[1:v]settb=AVTB,setpts=PTS-STARTPTS[b];[a][b]ssim=shortest=1",
+                    "-f", "null",
+                    "-",
+                ])
+                .output()
+        })
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+        // Summary line: "... SSIM Y:0.99 (20.1) U:... All:0.991 (20.5)"
+        let ssim = String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .filter(|line| line.contains("SSIM"))
+            .filter_map(|line| {
+                let rest = &line[line.find("All:")? + 4..];
+                rest.split_whitespace().next()?.parse::<f64>().ok()
+            })
+            .next_back();
+        if ssim.is_none() {
+            println!("[compare_videos] Warning: SSIM could not be computed");
+        }
+        ssim
+    } else {
+        None
+    };
+
+    let comparison = VideoComparison {
+        same_duration: duration_delta.abs() < 1.0 / 30.0,
+        same_resolution,
+        same_codec: codec_a == codec_b,
+        duration_delta,
+        ssim,
+    };
+    println!("[compare_videos] {:?}", comparison);
+    Ok(comparison)
+}
+
+#[derive(Debug, Serialize)]
+struct ExportSizeEstimate {
+    estimated_bytes: u64,
+    min_bytes: u64,
+    max_bytes: u64,
+    constant_quality: bool, // CRF export: size depends on content, so the range is wide
+}
+
+#[tauri::command]
+fn estimate_export_size(duration: f64, export_options: Option<ExportOptions>) -> Result<ExportSizeEstimate, String> {
+    if duration <= 0.0 {
+        return Err(format!("Duration must be positive: {}", duration));
+    }
+
+    let opts = export_options.unwrap_or_default();
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?;
+
+    // Same tiers trim_video uses for -b:v
+    let video_kbps: f64 = match opts.resolution.as_deref() {
+        Some("720p") => 2500.0,
+        Some("1080p") => 5000.0,
+        Some("source") | None => 8000.0,
+        Some(res) => return Err(format!("Invalid resolution: {}", res)),
+    };
+    let audio_kbps: f64 = profile.audio_bitrate.trim_end_matches('k').parse().unwrap_or(192.0);
+
+    let video_kbps = if opts.audio_only.unwrap_or(false) { 0.0 } else { video_kbps };
+    let audio_kbps = if opts.video_only.unwrap_or(false) { 0.0 } else { audio_kbps };
+
+    // kbit/s -> bytes, plus ~2% for container overhead
+    let to_bytes = |kbps: f64| (kbps * 1000.0 / 8.0 * duration * 1.02) as u64;
+    let audio_bytes = to_bytes(audio_kbps);
+
+    let estimate = if profile.crf.is_some() {
+        // Constant quality has no target bitrate; the tier is only a starting point
+        let video_bytes = to_bytes(video_kbps * 1.5);
+        ExportSizeEstimate {
+            estimated_bytes: video_bytes + audio_bytes,
+            min_bytes: video_bytes / 3 + audio_bytes,
+            max_bytes: video_bytes * 2 + audio_bytes,
+            constant_quality: true,
+        }
+    } else {
+        // Average bitrate lands within a few percent of the target over a whole clip
+        let video_bytes = to_bytes(video_kbps);
+        ExportSizeEstimate {
+            estimated_bytes: video_bytes + audio_bytes,
+            min_bytes: video_bytes * 9 / 10 + audio_bytes,
+            max_bytes: video_bytes * 11 / 10 + audio_bytes,
+            constant_quality: false,
+        }
+    };
+
+    println!(
+        "[estimate_export_size] {:.1}s at {} kbps video + {} kbps audio -> ~{} bytes",
+        duration, video_kbps, audio_kbps, estimate.estimated_bytes
+    );
+    Ok(estimate)
+}
+
+#[derive(Debug, Serialize)]
+struct EncoderBenchmark {
+    encoder: String,
+    fps: f64,             // Frames encoded per second of wall time
+    realtime_factor: f64, // fps relative to the 30 fps test source (1.0 = just keeps up)
+}
+
+#[tauri::command]
+async fn benchmark_encoder(encoder: String) -> Result<EncoderBenchmark, String> {
+    // 1080p30 is the heaviest preset the recorder and exporters use
+    const FRAME_RATE: f64 = 30.0;
+    const FRAME_COUNT: u32 = 150;
+
+    println!("[benchmark_encoder] Benchmarking {}", encoder);
+
+    if !ffmpeg_has_encoder(&encoder) {
+        return Err(format!("Encoder not available in this FFmpeg build: {}", encoder));
+    }
+
+    // Encode a generated test pattern and discard the output, timing only the encode run
+    let ffmpeg_path = get_ffmpeg_path();
+    let run_encoder = encoder.clone();
+    let (status, elapsed) = tokio::task::spawn_blocking(move || {
+        let started = std::time::Instant::now();
+        let status = Command::new(&ffmpeg_path)
+            .args([
+                "-hide_banner",
+                "-f", "lavfi",
+                "-i", &format!("testsrc2=size=1920x1080:rate={}", FRAME_RATE),
+                "-frames:v", &FRAME_COUNT.to_string(),
+                "-pix_fmt", "yuv420p",
+                "-c:v", &run_encoder,
+                "-f", "null",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        (status, started.elapsed())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?;
+
+    let status = status.map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+    if !status.success() {
+        return Err(format!("Encoder {} failed on this machine", encoder));
+    }
+
+    let fps = FRAME_COUNT as f64 / elapsed.as_secs_f64().max(0.001);
+    println!("[benchmark_encoder] {}: {:.1} fps", encoder, fps);
+    Ok(EncoderBenchmark {
+        encoder,
+        fps,
+        realtime_factor: fps / FRAME_RATE,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct VfrReport {
+    is_vfr: bool,
+    average_fps: f64,
+    min_frame_interval: f64, // Seconds
+    max_frame_interval: f64, // Seconds
+    frames_analyzed: usize,
+}
+
+#[tauri::command]
+async fn detect_vfr(video_path: String) -> Result<VfrReport, String> {
+    println!("[detect_vfr] Analyzing frame timing of {}", video_path);
+
+    if !std::path::Path::new(&video_path).exists() {
+        return Err(format!("Video file not found: {}", video_path));
+    }
+    if !ffprobe_available() {
+        return Err("Frame timing analysis requires ffprobe".to_string());
+    }
+
+    // Packet timestamps of the first minute are enough to see whether the frame rate varies
+    let ffprobe_path = get_ffprobe_path();
+    let output = tokio::task::spawn_blocking(move || {
+        output_with_timeout(Command::new(&ffprobe_path).args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-read_intervals", "%+60",
+            "-show_entries", "packet=pts_time",
+            "-of", "csv=p=0",
+            &video_path,
+        ]))
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+    .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("FFprobe error: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+
+    // Packets are in decode order, so sort into presentation order before taking intervals
+    let mut timestamps: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+        .collect();
+    timestamps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut intervals: Vec<f64> = timestamps
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .filter(|interval| *interval > 0.0)
+        .collect();
+    if intervals.len() < 2 {
+        return Err("Not enough video frames to analyze".to_string());
+    }
+
+    let average = intervals.iter().sum::<f64>() / intervals.len() as f64;
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let median = intervals[intervals.len() / 2];
+
+    // Timestamp rounding makes CFR intervals jitter slightly, so only count frames that are
+    // clearly early or late, and call it VFR once more than 1% of them are
+    let irregular = intervals
+        .iter()
+        .filter(|interval| (**interval - median).abs() > median * 0.2)
+        .count();
+    let is_vfr = irregular as f64 > intervals.len() as f64 * 0.01;
+
+    println!(
+        "[detect_vfr] {} of {} frame intervals irregular (median {:.4}s), VFR: {}",
+        irregular, intervals.len(), median, is_vfr
+    );
+
+    Ok(VfrReport {
+        is_vfr,
+        average_fps: 1.0 / average,
+        min_frame_interval: intervals[0],
+        max_frame_interval: intervals[intervals.len() - 1],
+        frames_analyzed: timestamps.len(),
+    })
+}
+
+#[tauri::command]
+async fn convert_to_cfr(input_path: String, output_path: String, target_fps: f64) -> Result<String, String> {
+    println!("[convert_to_cfr] {} -> {} at {} fps", input_path, output_path, target_fps);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if !(target_fps > 0.0 && target_fps <= 240.0) {
+        return Err(format!("Invalid target frame rate: {}", target_fps));
+    }
+
+    // Duplicate/drop frames onto a constant grid; audio is resampled against its timestamps
+    // so it stays aligned with the new video timing
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-vsync".to_string(), "cfr".to_string(),
+        "-r".to_string(), target_fps.to_string(),
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "fast".to_string(),
+        "-crf".to_string(), "18".to_string(),
+        "-pix_fmt".to_string(), "yuv420p".to_string(),
+    ];
+    if has_audio_stream(&input_path) {
+        args.extend([
+            "-af".to_string(), "aresample=async=1".to_string(),
+            "-c:a".to_string(), "aac".to_string(),
+            "-b:a".to_string(), "192k".to_string(),
+        ]);
+    }
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "convert_to_cfr").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[convert_to_cfr] Conversion completed: {}", output_path);
+    Ok(output_path)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Chapter {
+    #[serde(default)]
+    id: i64,
+    start_time: f64, // Seconds
+    end_time: f64,   // Seconds
+    #[serde(default)]
+    title: String,
+}
+
+#[tauri::command]
+fn get_chapters(video_path: String) -> Result<Vec<Chapter>, String> {
+    let ffprobe_path = get_ffprobe_path();
+    let output = output_with_timeout(Command::new(&ffprobe_path).args([
+        "-v", "error",
+        "-print_format", "json",
+        "-show_chapters",
+        &video_path
+    ]))
+    .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("FFprobe error: {}", error));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse ffprobe output: {}", e))?;
+
+    // ffprobe reports times as strings, e.g. "start_time": "12.500000"
+    let parse_time = |value: &serde_json::Value| -> f64 {
+        value
+            .as_str()
+            .and_then(|t| t.parse::<f64>().ok())
+            .or_else(|| value.as_f64())
+            .unwrap_or(0.0)
+    };
+
+    let chapters = json["chapters"]
+        .as_array()
+        .map(|chapters| {
+            chapters
+                .iter()
+                .map(|chapter| Chapter {
+                    id: chapter["id"].as_i64().unwrap_or(0),
+                    start_time: parse_time(&chapter["start_time"]),
+                    end_time: parse_time(&chapter["end_time"]),
+                    title: chapter["tags"]["title"].as_str().unwrap_or("").to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(chapters)
+}
+
+// Helper function to escape a value for an FFMETADATA file
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[tauri::command]
+async fn set_chapters(
+    input_path: String,
+    output_path: String,
+    chapters: Vec<Chapter>,
+) -> Result<String, String> {
+    println!("[set_chapters] Writing {} chapters to {}", chapters.len(), output_path);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+
+    for chapter in &chapters {
+        if chapter.start_time < 0.0 || chapter.end_time <= chapter.start_time {
+            return Err(format!(
+                "Invalid chapter range {}..{} ({})",
+                chapter.start_time, chapter.end_time, chapter.title
+            ));
+        }
+    }
+
+    // Build an FFMETADATA file with millisecond chapter timestamps
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for chapter in &chapters {
+        metadata.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+        metadata.push_str(&format!("START={}\n", (chapter.start_time * 1000.0).round() as i64));
+        metadata.push_str(&format!("END={}\n", (chapter.end_time * 1000.0).round() as i64));
+        metadata.push_str(&format!("title={}\n", escape_ffmetadata(&chapter.title)));
+    }
+
+    let metadata_path = std::env::temp_dir().join(format!(
+        "clipforge_chapters_{}_{}.txt",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0)
+    ));
+    std::fs::write(&metadata_path, metadata)
+        .map_err(|e| format!("Failed to write chapter metadata: {}", e))?;
+
+    // Copy all streams, keep global metadata from the input, take chapters from the metadata file
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path,
+        "-i".to_string(), metadata_path.to_string_lossy().to_string(),
+        "-map".to_string(), "0".to_string(),
+        "-map_metadata".to_string(), "0".to_string(),
+        "-map_chapters".to_string(), "1".to_string(),
+        "-c".to_string(), "copy".to_string(),
+    ];
+
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+
+    args.push(output_path.clone());
+
+    let result = run_ffmpeg(args, "set_chapters").await;
+    let _ = std::fs::remove_file(&metadata_path);
+    result?;
+
+    println!("[set_chapters] Chapters written successfully");
+    Ok(output_path)
+}
+
+#[tauri::command]
+async fn convert_framerate(
+    input_path: String,
+    output_path: String,
+    target_fps: f64,
+    interpolate: bool,
+) -> Result<String, String> {
+    println!("[convert_framerate] Converting {} to {} fps (interpolate: {})", input_path, target_fps, interpolate);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if !(target_fps > 0.0 && target_fps <= 240.0) {
+        return Err(format!("Invalid target frame rate: {}", target_fps));
+    }
+
+    let video_filter = if interpolate {
+        // Motion-compensated interpolation looks smooth but is very slow (often well below realtime)
+        println!("[convert_framerate] Warning: minterpolate is slow, expect a long encode");
+        format!("minterpolate=fps={}:mi_mode=mci:mc_mode=aobmc:vsbmc=1", target_fps)
+    } else {
+        // Drop or duplicate frames to hit the target rate
+        format!("fps={}", target_fps)
+    };
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path,
+        "-vf".to_string(), video_filter,
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "fast".to_string(),
+        "-crf".to_string(), "18".to_string(),
+        "-pix_fmt".to_string(), "yuv420p".to_string(),
+        "-c:a".to_string(), "copy".to_string(),
+    ];
+
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
 
-                    // Look for complete JPEG frames
-                    while let Some(start_pos) = buffer.windows(2).position(|w| w == jpeg_start) {
-                        if start_pos > 0 {
-                            // Remove any data before the JPEG start
-                            buffer.drain(..start_pos);
-                        }
+    args.push(output_path.clone());
 
-                        // Look for JPEG end marker
-                        if let Some(end_pos) = buffer.windows(2).position(|w| w == jpeg_end) {
-                            // Extract complete JPEG frame (including end marker)
-                            let frame_data = buffer.drain(..(end_pos + 2)).collect::<Vec<u8>>();
+    run_ffmpeg(args, "convert_framerate").await?;
 
-                            // Encode as base64 and emit event
-                            let base64_frame = general_purpose::STANDARD.encode(&frame_data);
-                            let _ = window.emit("screen-preview-frame", base64_frame);
-                        } else {
-                            // No end marker yet, wait for more data
-                            break;
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("[start_screen_preview] Error reading stdout: {}", e);
-                    break;
-                }
+    // Confirm the output actually reports the requested rate
+    let actual_fps = probe_frame_rate(&output_path)?;
+    if (actual_fps - target_fps).abs() > 0.01 {
+        return Err(format!(
+            "Output frame rate {:.3} does not match requested {:.3}",
+            actual_fps, target_fps
+        ));
+    }
+
+    println!("[convert_framerate] Output verified at {:.3} fps", actual_fps);
+    Ok(output_path)
+}
+
+// Helper function to find the frame numbers matched by an image2 pattern like "frame_%06d.png"
+// Returns the sorted numbers of the files in `dir` that fit the pattern
+fn image_sequence_numbers(dir: &std::path::Path, pattern: &str) -> Result<Vec<u64>, String> {
+    let invalid = || format!("Invalid frame pattern (expected e.g. frame_%06d.png): {}", pattern);
+
+    // Split "frame_%06d.png" into prefix "frame_", width 6 and suffix ".png"
+    let (prefix, rest) = pattern.split_once('%').ok_or_else(invalid)?;
+    let (spec, suffix) = rest.split_once('d').ok_or_else(invalid)?;
+    if suffix.contains('%') || prefix.contains(['/', '\\']) || suffix.contains(['/', '\\']) {
+        return Err(invalid());
+    }
+    let width: usize = match spec {
+        "" => 0,
+        digits if digits.starts_with('0') => digits.parse().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut numbers: Vec<u64> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let digits = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
             }
+            // %06d pads to at least 6 digits; unpadded %d never has leading zeros
+            if (width > 0 && digits.len() < width) || (width == 0 && digits.len() > 1 && digits.starts_with('0')) {
+                return None;
+            }
+            digits.parse().ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+#[tauri::command]
+async fn frames_to_video(
+    input_dir: String,
+    pattern: String,
+    output_path: String,
+    fps: f64,
+    audio_path: Option<String>,
+    export_options: Option<ExportOptions>,
+) -> Result<String, String> {
+    println!("[frames_to_video] {}/{} @ {} fps -> {}", input_dir, pattern, fps, output_path);
+
+    let dir = std::path::Path::new(&input_dir);
+    if !dir.is_dir() {
+        return Err(format!("Frame directory not found: {}", input_dir));
+    }
+    if fps <= 0.0 || fps > 240.0 {
+        return Err(format!("Invalid frame rate: {}", fps));
+    }
+    if let Some(ref audio) = audio_path {
+        if !std::path::Path::new(audio).exists() {
+            return Err(format!("Audio file not found: {}", audio));
         }
-    });
+    }
+
+    let numbers = image_sequence_numbers(dir, &pattern)?;
+    let first_number = *numbers
+        .first()
+        .ok_or_else(|| format!("No files in {} match {}", input_dir, pattern))?;
+    println!("[frames_to_video] {} frames starting at {}", numbers.len(), first_number);
+
+    let opts = export_options.unwrap_or_default();
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+
+    // Same resolution/bitrate tiers as trim_video; source size is rounded down to even for yuv420p
+    let (scale_filter, bitrate) = match opts.resolution.as_deref() {
+        Some("720p") => ("scale=1280:720", "2500k"),
+        Some("1080p") => ("scale=1920:1080", "5000k"),
+        Some("source") | None => ("scale=trunc(iw/2)*2:trunc(ih/2)*2", "8000k"),
+        Some(res) => return Err(format!("Invalid resolution: {}", res)),
+    };
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-framerate".to_string(), fps.to_string(),
+        // image2 only probes the first few numbers on its own
+        "-start_number".to_string(), first_number.to_string(),
+        "-i".to_string(), dir.join(&pattern).to_string_lossy().to_string(),
+    ];
+    if let Some(ref audio) = audio_path {
+        args.extend(["-i".to_string(), audio.clone()]);
+    }
+    args.extend([
+        "-map".to_string(), "0:v".to_string(),
+        "-vf".to_string(), format!("{},format=yuv420p", scale_filter),
+        "-c:v".to_string(), "libx264".to_string(),
+    ]);
+    args.extend(profile.video_args(["-b:v", bitrate]));
+    if audio_path.is_some() {
+        // The frames set the length; extra audio is cut off
+        args.extend(["-map".to_string(), "1:a:0".to_string()]);
+        args.extend(profile.audio_args());
+        args.push("-shortest".to_string());
+    }
+    args.extend(profile.movflags_args(&output_path));
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "frames_to_video").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[frames_to_video] Output file: {}", output_path);
+    Ok(output_path)
+}
+
+#[derive(Debug, Deserialize)]
+struct BlurRegion {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    #[serde(default)]
+    start: Option<f64>, // Seconds; blurred from the beginning when unset
+    #[serde(default)]
+    end: Option<f64>,   // Seconds; blurred to the end when unset
+}
+
+#[tauri::command]
+async fn blur_region(
+    input_path: String,
+    output_path: String,
+    regions: Vec<BlurRegion>,
+    export_options: Option<ExportOptions>,
+) -> Result<String, String> {
+    println!("[blur_region] {} -> {} ({} regions)", input_path, output_path, regions.len());
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if regions.is_empty() {
+        return Err("No regions to blur".to_string());
+    }
+
+    let opts = export_options.unwrap_or_default();
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+
+    // Same resolution/bitrate tiers as trim_video; scaling happens after blurring so
+    // regions are given in source pixels
+    let (scale_filter, bitrate) = match opts.resolution.as_deref() {
+        Some("720p") => (Some("scale=1280:720"), "2500k"),
+        Some("1080p") => (Some("scale=1920:1080"), "5000k"),
+        Some("source") | None => (None, "8000k"),
+        Some(res) => return Err(format!("Invalid resolution: {}", res)),
+    };
+
+    // Each region is cut out of a copy of the frame, blurred, and laid back over its own spot
+    // while its time window is active
+    let mut parts = Vec::new();
+    let copies: String = (0..regions.len()).map(|i| format!("[crop{}]", i)).collect();
+    parts.push(format!("[0:v]split={}[base]{}", regions.len() + 1, copies));
+
+    let mut current = "base".to_string();
+    for (i, region) in regions.iter().enumerate() {
+        if region.x < 0 || region.y < 0 || region.width < 2 || region.height < 2 {
+            return Err(format!(
+                "Invalid blur region {}: {}x{} at {},{}",
+                i, region.width, region.height, region.x, region.y
+            ));
+        }
+        let start = region.start.unwrap_or(0.0);
+        if start < 0.0 || region.end.is_some_and(|end| end <= start) {
+            return Err(format!("Invalid time window for blur region {}: {:?}..{:?}", i, region.start, region.end));
+        }
+
+        // boxblur's radius can't exceed half the (chroma) plane, so small regions get a smaller blur
+        let radius = (region.width.min(region.height) / 4).clamp(1, 20);
+        parts.push(format!(
+            "[crop{}]crop={}:{}:{}:{},boxblur={}:2[blur{}]",
+            i, region.width, region.height, region.x, region.y, radius, i
+        ));
+
+        let enable = match region.end {
+            Some(end) => format!("between(t,{},{})", start, end),
+            None => format!("gte(t,{})", start),
+        };
+        let next = format!("v{}", i);
+        parts.push(format!(
+            "[{}][blur{}]overlay={}:{}:enable='{}'[{}]",
+            current, i, region.x, region.y, enable, next
+        ));
+        current = next;
+    }
+
+    let mut tail: Vec<&str> = scale_filter.into_iter().collect();
+    tail.push("format=yuv420p");
+    parts.push(format!("[{}]{}[vout]", current, tail.join(",")));
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-filter_complex".to_string(), parts.join(";"),
+        "-map".to_string(), "[vout]".to_string(),
+        "-map".to_string(), "0:a?".to_string(),
+        "-c:v".to_string(), "libx264".to_string(),
+    ];
+    args.extend(profile.video_args(["-b:v", bitrate]));
+    args.push("-c:a".to_string());
+    args.push("copy".to_string());
+    args.extend(profile.movflags_args(&output_path));
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "blur_region").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[blur_region] Redacted output written to {}", output_path);
+    Ok(output_path)
+}
+
+#[derive(Debug, Serialize)]
+struct AvSyncReport {
+    video_start: f64,
+    audio_start: f64,
+    offset: f64,                 // audio_start - video_start; positive means the audio starts late
+    video_duration: Option<f64>,
+    audio_duration: Option<f64>, // A large gap against video_duration hints at drift over the recording
+}
+
+#[derive(Debug, Serialize)]
+struct AvSyncFix {
+    output_path: String,
+    measured_offset: f64, // Offset reported by check_av_sync before the fix
+    applied_offset: f64,  // Shift applied to the audio in seconds (positive delays it)
+}
+
+// Helper function to read the first video and audio stream start times and durations
+fn probe_av_sync(input_path: &str) -> Result<AvSyncReport, String> {
+    if !has_audio_stream(input_path) {
+        return Err("Input has no audio stream to sync".to_string());
+    }
+
+    // csv output is "start_time,duration"; either may be "N/A"
+    let read = |selector: &str| -> Result<(f64, Option<f64>), String> {
+        let entry = probe_entry(input_path, "stream=start_time,duration", Some(selector))?;
+        let mut parts = entry.lines().next().unwrap_or("").split(',');
+        let start = parts.next().and_then(|v| v.trim().parse::<f64>().ok()).unwrap_or(0.0);
+        let duration = parts.next().and_then(|v| v.trim().parse::<f64>().ok());
+        Ok((start, duration))
+    };
+    let (video_start, video_duration) = read("v:0")?;
+    let (audio_start, audio_duration) = read("a:0")?;
+
+    Ok(AvSyncReport {
+        video_start,
+        audio_start,
+        offset: audio_start - video_start,
+        video_duration,
+        audio_duration,
+    })
+}
+
+#[tauri::command]
+fn check_av_sync(input_path: String) -> Result<AvSyncReport, String> {
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+
+    let report = probe_av_sync(&input_path)?;
+    println!(
+        "[check_av_sync] {}: video starts at {:.3}s, audio at {:.3}s (offset {:+.3}s)",
+        input_path, report.video_start, report.audio_start, report.offset
+    );
+    Ok(report)
+}
+
+#[tauri::command]
+async fn fix_av_sync(input_path: String, output_path: String, offset: Option<f64>) -> Result<AvSyncFix, String> {
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+
+    let measured_offset = probe_av_sync(&input_path)?.offset;
+    // Without an explicit shift, cancel the measured start offset
+    let applied_offset = offset.unwrap_or(-measured_offset);
+    if !applied_offset.is_finite() || applied_offset.abs() > 60.0 {
+        return Err(format!("A/V sync offset must be within 60 seconds: {}", applied_offset));
+    }
+    println!(
+        "[fix_av_sync] {} -> {} (measured {:+.3}s, shifting audio {:+.3}s)",
+        input_path, output_path, measured_offset, applied_offset
+    );
+
+    // The audio comes from a second, time-shifted read of the same file; aresample=async
+    // stretches/squeezes it against its timestamps so later drift is corrected too
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-itsoffset".to_string(), applied_offset.to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-map".to_string(), "0:v:0".to_string(),
+        "-map".to_string(), "1:a:0".to_string(),
+        "-c:v".to_string(), "copy".to_string(),
+        "-af".to_string(), "aresample=async=1:first_pts=0".to_string(),
+        "-c:a".to_string(), "aac".to_string(),
+        "-b:a".to_string(), "192k".to_string(),
+    ];
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "fix_av_sync").await?;
+    verify_output_file(&output_path)?;
+
+    Ok(AvSyncFix {
+        output_path,
+        measured_offset,
+        applied_offset,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct RotationMetadataResult {
+    output_path: String,
+    degrees: i32,
+    warning: String, // Shown to the user: the frames themselves are unchanged
+}
+
+#[tauri::command]
+async fn set_rotation_metadata(
+    input_path: String,
+    output_path: String,
+    degrees: i32,
+) -> Result<RotationMetadataResult, String> {
+    println!("[set_rotation_metadata] {} -> {} ({} degrees)", input_path, output_path, degrees);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if degrees.rem_euclid(90) != 0 {
+        return Err(format!("Rotation must be a multiple of 90 degrees: {}", degrees));
+    }
+    let degrees = degrees.rem_euclid(360);
+
+    // Newer FFmpeg writes the display matrix from -display_rotation (counter-clockwise) and
+    // ignores a "rotate" tag on output; older builds only understand the tag (clockwise).
+    let display_matrix_args = vec![
+        "-y".to_string(),
+        "-display_rotation:v:0".to_string(), (-degrees).to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-map".to_string(), "0".to_string(),
+        "-c".to_string(), "copy".to_string(),
+        output_path.clone(),
+    ];
+    if let Err(e) = run_ffmpeg(display_matrix_args, "set_rotation_metadata").await {
+        println!("[set_rotation_metadata] -display_rotation not supported, using rotate tag: {}", e);
+        let tag_args = vec![
+            "-y".to_string(),
+            "-i".to_string(), input_path.clone(),
+            "-map".to_string(), "0".to_string(),
+            "-c".to_string(), "copy".to_string(),
+            "-metadata:s:v:0".to_string(), format!("rotate={}", degrees),
+            output_path.clone(),
+        ];
+        run_ffmpeg(tag_args, "set_rotation_metadata").await?;
+    }
+    verify_output_file(&output_path)?;
+
+    let written = probe_rotation(&output_path);
+    if ffprobe_available() && written != degrees {
+        println!("[set_rotation_metadata] Warning: output reports {} degrees, expected {}", written, degrees);
+    }
+
+    Ok(RotationMetadataResult {
+        output_path,
+        degrees,
+        warning: "Only the rotation metadata was changed. Some players and sites ignore it; \
+                  export with auto-orient to rotate the frames themselves."
+            .to_string(),
+    })
+}
+
+#[tauri::command]
+async fn apply_lut(
+    input_path: String,
+    output_path: String,
+    lut_path: String,
+    export_options: Option<ExportOptions>,
+) -> Result<String, String> {
+    println!("[apply_lut] {} -> {} (LUT: {})", input_path, output_path, lut_path);
+
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    let lut = std::path::Path::new(&lut_path);
+    if !lut.is_file() {
+        return Err(format!("LUT file not found: {}", lut_path));
+    }
+    let is_cube = lut
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("cube"))
+        .unwrap_or(false);
+    if !is_cube {
+        return Err(format!("LUT must be a .cube file: {}", lut_path));
+    }
+
+    let opts = export_options.unwrap_or_default();
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+
+    // Same resolution/bitrate tiers as trim_video
+    let (scale_filter, bitrate) = match opts.resolution.as_deref() {
+        Some("720p") => (Some("scale=1280:720"), "2500k"),
+        Some("1080p") => (Some("scale=1920:1080"), "5000k"),
+        Some("source") | None => (None, "8000k"),
+        Some(res) => return Err(format!("Invalid resolution: {}", res)),
+    };
+
+    // Forward slashes work on every platform; colons (drive letters) are escaped like fontfile
+    let lut_option = lut_path.replace('\\', "/").replace(':', "\\:");
+    let mut filters = vec![format!("lut3d=file='{}'", lut_option)];
+    filters.extend(scale_filter.map(|f| f.to_string()));
+    filters.push("format=yuv420p".to_string());
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-vf".to_string(), filters.join(","),
+        "-c:v".to_string(), "libx264".to_string(),
+    ];
+    args.extend(profile.video_args(["-b:v", bitrate]));
+    args.push("-c:a".to_string());
+    args.push("copy".to_string());
+    args.extend(profile.movflags_args(&output_path));
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "apply_lut").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[apply_lut] Graded output written to {}", output_path);
+    Ok(output_path)
+}
+
+#[derive(Debug, Serialize)]
+struct StoryboardTile {
+    time: f64, // Seconds into the video
+    x: i32,    // Left edge of the tile in the sprite sheet (pixels)
+    y: i32,    // Top edge of the tile in the sprite sheet (pixels)
+}
+
+#[derive(Debug, Serialize)]
+struct Storyboard {
+    output_path: String,
+    thumb_width: i32,
+    thumb_height: i32,
+    columns: u32,
+    rows: u32,
+    interval: f64,
+    tiles: Vec<StoryboardTile>,
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_storyboard(
+    video_path: String,
+    output_path: String,
+    columns: u32,
+    rows: u32,
+    thumb_width: i32,
+    interval: f64,
+    format: Option<String>,
+    quality: Option<u8>,
+) -> Result<Storyboard, String> {
+    println!(
+        "[generate_storyboard] {} -> {} ({}x{} tiles, {}px wide, every {}s)",
+        video_path, output_path, columns, rows, thumb_width, interval
+    );
+
+    if !std::path::Path::new(&video_path).exists() {
+        return Err(format!("Video file not found: {}", video_path));
+    }
+    if columns == 0 || rows == 0 {
+        return Err("Storyboard needs at least one column and one row".to_string());
+    }
+    let grid_cells = columns
+        .checked_mul(rows)
+        .ok_or_else(|| format!("Storyboard grid is too large: {}x{}", columns, rows))?;
+    if thumb_width <= 0 {
+        return Err(format!("Invalid thumbnail width: {}", thumb_width));
+    }
+    if interval <= 0.0 {
+        return Err(format!("Invalid interval: {}", interval));
+    }
+
+    // The format defaults to the output extension and must agree with it when given
+    let extension = std::path::Path::new(&output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    let extension_format = if extension == "png" { "png" } else { "jpg" };
+    let format = format.as_deref().map(|f| if f == "jpeg" { "jpg" } else { f }).unwrap_or(extension_format);
+    if format != extension_format {
+        return Err(format!("Storyboard format {} does not match output file {}", format, output_path));
+    }
+    let encoder_args = image_encoder_args(Some(format), quality)?;
+
+    // Keep the source aspect ratio, rounded to an even height
+    let dimensions = probe_entry(&video_path, "stream=width,height", Some("v:0"))?;
+    let mut parts = dimensions.lines().next().unwrap_or("").split(',');
+    let source_width = parts.next().and_then(|w| w.trim().parse::<f64>().ok());
+    let source_height = parts.next().and_then(|h| h.trim().parse::<f64>().ok());
+    let (source_width, source_height) = match (source_width, source_height) {
+        (Some(w), Some(h)) if w > 0.0 && h > 0.0 => (w, h),
+        _ => return Err(format!("Could not determine video dimensions: {}", dimensions)),
+    };
+    let thumb_height = ((thumb_width as f64 * source_height / source_width / 2.0).round() as i32 * 2).max(2);
+
+    // Only as many tiles as the video has sample points, capped by the grid
+    let duration = probe_duration(&video_path)?;
+    let sample_count = (duration / interval).ceil().max(1.0) as u32;
+    let tile_count = sample_count.min(grid_cells);
+
+    // Pick the first frame at or after each interval boundary, then lay them out in a grid
+    let filter = format!(
+        "select='isnan(prev_selected_t)+gte(t-prev_selected_t\\,{})',scale={}:{},tile={}x{}",
+        interval, thumb_width, thumb_height, columns, rows
+    );
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), video_path.clone(),
+        "-vf".to_string(), filter,
+        "-vsync".to_string(), "vfr".to_string(),
+        "-frames:v".to_string(), "1".to_string(),
+        "-an".to_string(),
+    ];
+    args.extend(encoder_args);
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "generate_storyboard").await?;
+
+    if !std::path::Path::new(&output_path).exists() {
+        return Err("FFmpeg did not produce a storyboard image".to_string());
+    }
+
+    let tiles = (0..tile_count)
+        .map(|i| StoryboardTile {
+            time: i as f64 * interval,
+            x: (i % columns) as i32 * thumb_width,
+            y: (i / columns) as i32 * thumb_height,
+        })
+        .collect::<Vec<_>>();
+
+    println!("[generate_storyboard] Wrote {} tiles to {}", tiles.len(), output_path);
+    Ok(Storyboard {
+        output_path,
+        thumb_width,
+        thumb_height,
+        columns,
+        rows,
+        interval,
+        tiles,
+    })
+}
+
+#[tauri::command]
+async fn generate_contact_sheet(
+    video_path: String,
+    output_path: String,
+    columns: u32,
+    rows: u32,
+    show_timestamps: Option<bool>,
+) -> Result<String, String> {
+    // Width of each frame in the sheet; the height follows the source aspect ratio
+    const CELL_WIDTH: i32 = 320;
+    const LABEL_HEIGHT: i32 = 24;
+
+    println!("[generate_contact_sheet] {} -> {} ({}x{})", video_path, output_path, columns, rows);
+
+    if !std::path::Path::new(&video_path).exists() {
+        return Err(format!("Video file not found: {}", video_path));
+    }
+    let cell_count = match columns.checked_mul(rows) {
+        Some(count @ 1..=100) => count,
+        _ => return Err(format!("Contact sheet grid must be between 1 and 100 cells: {}x{}", columns, rows)),
+    };
+
+    let extension = std::path::Path::new(&output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    let encoder_args = image_encoder_args(Some(if extension == "png" { "png" } else { "jpg" }), None)?;
+
+    // One frame from the middle of each of `columns * rows` equal slices of the video
+    let duration = probe_duration(&video_path)?;
+    if duration <= 0.0 {
+        return Err(format!("Could not determine duration of {}", video_path));
+    }
+    let interval = duration / cell_count as f64;
+
+    let mut filters = vec![
+        format!("fps=fps=1/{}:start_time={}", interval, interval / 2.0),
+        format!("scale={}:-2", CELL_WIDTH),
+    ];
+    if show_timestamps.unwrap_or(true) {
+        // Label strip under each frame with its position in the video
+        filters.push(format!("pad=iw:ih+{}:0:0:color=black", LABEL_HEIGHT));
+        filters.push(format!(
+            "drawtext={}text='%{{pts\\:hms}}':fontcolor=white:fontsize=16:x=(w-tw)/2:y=h-{}+(({}-th)/2)",
+            drawtext_font_option(),
+            LABEL_HEIGHT,
+            LABEL_HEIGHT
+        ));
+    }
+    filters.push(format!("tile={}x{}:margin=4:padding=4:color=black", columns, rows));
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), video_path.clone(),
+        "-vf".to_string(), filters.join(","),
+        "-frames:v".to_string(), "1".to_string(),
+        "-an".to_string(),
+    ];
+    args.extend(encoder_args);
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "generate_contact_sheet").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[generate_contact_sheet] Wrote {} frames to {}", cell_count, output_path);
+    Ok(output_path)
+}
 
-    *process = Some(child);
-    Ok("Preview started".to_string())
+#[derive(Debug, Deserialize, Default)]
+struct CountdownOptions {
+    #[serde(default)]
+    background_color: Option<String>, // FFmpeg color name or hex (default black)
+    #[serde(default)]
+    text_color: Option<String>,       // FFmpeg color name or hex (default white)
 }
 
 #[tauri::command]
-fn stop_screen_preview() -> Result<String, String> {
-    println!("[stop_screen_preview] Stopping screen preview");
-
-    let mut process = SCREEN_PREVIEW_PROCESS.lock().unwrap();
+async fn add_countdown_intro(
+    input_path: String,
+    output_path: String,
+    seconds: u32,
+    export_options: Option<CountdownOptions>,
+) -> Result<String, String> {
+    println!("[add_countdown_intro] {}s countdown before {} -> {}", seconds, input_path, output_path);
 
-    if let Some(mut child) = process.take() {
-        child.kill()
-            .map_err(|e| format!("Failed to stop preview: {}", e))?;
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if seconds == 0 || seconds > 60 {
+        return Err(format!("Countdown must be between 1 and 60 seconds: {}", seconds));
+    }
 
-        child.wait()
-            .map_err(|e| format!("Failed to wait for preview: {}", e))?;
+    let opts = export_options.unwrap_or_default();
+    let background_color = opts.background_color.unwrap_or_else(|| "black".to_string());
+    let text_color = opts.text_color.unwrap_or_else(|| "white".to_string());
+    if !is_valid_ffmpeg_color(&background_color) {
+        return Err(format!("Invalid background color: {}", background_color));
+    }
+    if !is_valid_ffmpeg_color(&text_color) {
+        return Err(format!("Invalid text color: {}", text_color));
+    }
 
-        println!("[stop_screen_preview] Preview stopped successfully");
-        Ok("Preview stopped".to_string())
+    // The countdown matches the main clip so the two can be joined with the concat filter
+    let dimensions = probe_entry(&input_path, "stream=width,height", Some("v:0"))?;
+    let mut parts = dimensions.lines().next().unwrap_or("").split(',');
+    let width = parts.next().and_then(|w| w.trim().parse::<i32>().ok());
+    let height = parts.next().and_then(|h| h.trim().parse::<i32>().ok());
+    let (width, height) = match (width, height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+        _ => return Err(format!("Could not determine video dimensions: {}", dimensions)),
+    };
+    let frame_rate = probe_frame_rate(&input_path)?;
+    let main_has_audio = has_audio_stream(&input_path);
+
+    // Counts down "3", "2", "1" (one number per second)
+    let countdown_filter = format!(
+        "[0:v]drawtext={}text='%{{eif\\:{}-floor(t)\\:d}}':fontcolor={}:fontsize=h/3:x=(w-tw)/2:y=(h-th)/2,setsar=1,format=yuv420p[cv]",
+        drawtext_font_option(),
+        seconds,
+        text_color
+    );
+    let main_filter = format!(
+        "[2:v]scale={w}:{h},setsar=1,fps={fps},format=yuv420p[mv]",
+        w = width,
+        h = height,
+        fps = frame_rate
+    );
+    let filter_complex = if main_has_audio {
+        format!(
+            "{};{};[1:a]atrim=duration={}[ca];[2:a]aresample=48000,aformat=sample_fmts=fltp:channel_layouts=stereo[ma];[cv][ca][mv][ma]concat=n=2:v=1:a=1[v][a]",
+            countdown_filter, main_filter, seconds
+        )
     } else {
-        Err("No preview running".to_string())
+        format!("{};{};[cv][mv]concat=n=2:v=1:a=0[v]", countdown_filter, main_filter)
+    };
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-f".to_string(), "lavfi".to_string(),
+        "-i".to_string(), format!("color=c={}:s={}x{}:r={}:d={}", background_color, width, height, frame_rate, seconds),
+        "-f".to_string(), "lavfi".to_string(),
+        "-i".to_string(), "anullsrc=channel_layout=stereo:sample_rate=48000".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-filter_complex".to_string(), filter_complex,
+        "-map".to_string(), "[v]".to_string(),
+    ];
+    if main_has_audio {
+        args.extend([
+            "-map".to_string(), "[a]".to_string(),
+            "-c:a".to_string(), "aac".to_string(),
+            "-b:a".to_string(), "192k".to_string(),
+        ]);
+    }
+    args.extend([
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "fast".to_string(),
+        "-crf".to_string(), "18".to_string(),
+    ]);
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
     }
+    args.push(output_path.clone());
+
+    run_ffmpeg(args, "add_countdown_intro").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[add_countdown_intro] Countdown added: {}", output_path);
+    Ok(output_path)
 }
 
 #[tauri::command]
-fn start_camera_recording(
+async fn add_waveform_visualization(
+    input_path: String,
     output_path: String,
-    options: Option<RecordingOptions>,
-    _window: tauri::Window
+    position: Option<String>,
+    color: Option<String>,
+    height: Option<i32>,
 ) -> Result<String, String> {
-    println!("[start_camera_recording] Starting camera recording");
-    println!("[start_camera_recording] Output path: {}", output_path);
-
-    // Parse resolution options
-    let opts = options.unwrap_or_else(|| RecordingOptions {
-        resolution: "720p".to_string(),
-        source_width: None,
-        source_height: None,
-        audio_device: None,
-    });
-
-    // Determine resolution and bitrate
-    let (width, height, bitrate) = match opts.resolution.as_str() {
-        "720p" => (1280, 720, "2500k"),
-        "1080p" => (1920, 1080, "5000k"),
-        "source" => {
-            if let (Some(w), Some(h)) = (opts.source_width, opts.source_height) {
-                (w, h, "8000k")
-            } else {
-                return Err("Source resolution not available".to_string());
-            }
-        }
-        _ => return Err(format!("Invalid resolution: {}", opts.resolution)),
-    };
+    println!("[add_waveform_visualization] {} -> {}", input_path, output_path);
 
-    println!("[start_camera_recording] Resolution: {}x{} @ {} bitrate", width, height, bitrate);
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    if !has_audio_stream(&input_path) {
+        return Err(format!("Input has no audio to visualize: {}", input_path));
+    }
 
-    // Platform-specific FFmpeg arguments for camera
-    let resolution_str = format!("{}x{}", width, height);
+    let color = color.unwrap_or_else(|| "white".to_string());
+    if !is_valid_ffmpeg_color(&color) {
+        return Err(format!("Invalid waveform color: {}", color));
+    }
 
-    // Prepare Windows input string (if needed) before args to ensure proper lifetime
-    let windows_input_str = if cfg!(target_os = "windows") {
-        if let Some(audio_dev) = &opts.audio_device {
-            format!("video=Integrated Camera:audio={}", audio_dev)
-        } else {
-            "video=Integrated Camera".to_string()
-        }
-    } else {
-        String::new()
+    let dimensions = probe_entry(&input_path, "stream=width,height", Some("v:0"))?;
+    let mut parts = dimensions.lines().next().unwrap_or("").split(',');
+    let width = parts.next().and_then(|w| w.trim().parse::<i32>().ok());
+    let video_height = parts.next().and_then(|h| h.trim().parse::<i32>().ok());
+    let (width, video_height) = match (width, video_height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+        _ => return Err(format!("Could not determine video dimensions: {}", dimensions)),
     };
+    let frame_rate = probe_frame_rate(&input_path)?;
 
-    let args = if cfg!(target_os = "macos") {
-        vec![
-            "-f", "avfoundation",
-            "-framerate", "30",
-            "-video_size", &resolution_str,
-            "-i", "0:0",            // Camera device (0 = default camera, 0 = default microphone)
-            "-pix_fmt", "yuv420p",
-            "-c:v", "libx264",
-            "-preset", "ultrafast",
-            "-b:v", bitrate,
-            "-c:a", "aac",          // Audio codec
-            "-b:a", "192k",         // Audio bitrate
-            &output_path
-        ]
-    } else if cfg!(target_os = "windows") {
-        let mut args = vec![
-            "-f", "dshow",
-            "-framerate", "30",
-            "-video_size", &resolution_str,
-            "-i", &windows_input_str,
-            "-pix_fmt", "yuv420p",
-            "-c:v", "libx264",
-            "-preset", "ultrafast",
-            "-b:v", bitrate,
-        ];
-
-        // Add audio codec parameters if audio device is provided
-        if opts.audio_device.is_some() {
-            args.push("-c:a");
-            args.push("aac");
-            args.push("-b:a");
-            args.push("192k");
-        }
+    // Default to a quarter of the frame; yuv420p needs even sizes
+    let wave_height = height.unwrap_or(video_height / 4);
+    if wave_height <= 0 || wave_height > video_height {
+        return Err(format!("Invalid waveform height: {}", wave_height));
+    }
+    let wave_height = wave_height / 2 * 2;
 
-        args.push(&output_path);
-        args
-    } else {
-        return Err("Unsupported platform".to_string());
+    // showwaves draws on a transparent canvas, so it can sit directly on top of the video
+    let waves = format!(
+        "[0:a]showwaves=s={}x{}:mode=cline:rate={}:colors={}",
+        width, wave_height, frame_rate, color
+    );
+    let filter_complex = match position.as_deref().unwrap_or("bottom") {
+        "top" => format!("{},format=yuva420p[wave];[0:v][wave]overlay=x=0:y=0:shortest=1,format=yuv420p[v]", waves),
+        "center" => format!(
+            "{},format=yuva420p[wave];[0:v][wave]overlay=x=0:y=(H-h)/2:shortest=1,format=yuv420p[v]",
+            waves
+        ),
+        "bottom" => format!("{},format=yuva420p[wave];[0:v][wave]overlay=x=0:y=H-h:shortest=1,format=yuv420p[v]", waves),
+        // Below the video: the frame grows by the waveform height (on a black strip)
+        "below" => format!(
+            "{},format=yuv420p[wave];[0:v]format=yuv420p[base];[base][wave]vstack=inputs=2:shortest=1[v]",
+            waves
+        ),
+        other => return Err(format!("Invalid waveform position: {}", other)),
     };
 
-    println!("[start_camera_recording] FFmpeg args: {:?}", args);
-
-    // Start FFmpeg process with stdin pipe for graceful shutdown
-    let ffmpeg_path = get_ffmpeg_path();
-    let child = Command::new(&ffmpeg_path)
-        .args(&args)
-        .stdin(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            format!(
-                "Failed to start camera recording: {}. Make sure camera is not in use by another app.",
-                e
-            )
-        })?;
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-filter_complex".to_string(), filter_complex,
+        "-map".to_string(), "[v]".to_string(),
+        "-map".to_string(), "0:a".to_string(),
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "fast".to_string(),
+        "-crf".to_string(), "18".to_string(),
+        "-c:a".to_string(), "copy".to_string(),
+    ];
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
+    }
+    args.push(output_path.clone());
 
-    println!("[start_camera_recording] FFmpeg process started");
+    run_ffmpeg(args, "add_waveform_visualization").await?;
+    verify_output_file(&output_path)?;
 
-    // Store process
-    let mut process = CAMERA_RECORDING_PROCESS.lock().unwrap();
-    *process = Some(child);
+    println!("[add_waveform_visualization] Waveform added: {}", output_path);
+    Ok(output_path)
+}
 
-    Ok("Camera recording started".to_string())
+// Helper function to look up a platform's unsafe zones as fractions of the frame
+// Returns (top, bottom, right): the app UI overlays the caption/username area at the bottom,
+// the status/search bar at the top and the like/comment/share buttons down the right edge.
+// Based on the published 1080x1920 guides for each platform.
+fn safe_area_margins(platform: &str) -> Option<(f64, f64, f64)> {
+    match platform {
+        "tiktok" => Some((0.08, 0.20, 0.12)),
+        "reels" => Some((0.14, 0.20, 0.10)),
+        "shorts" => Some((0.12, 0.25, 0.14)),
+        _ => None,
+    }
 }
 
 #[tauri::command]
-fn stop_camera_recording() -> Result<String, String> {
-    use std::io::Write;
-
-    println!("[stop_camera_recording] Stopping camera recording");
-
-    let mut process = CAMERA_RECORDING_PROCESS.lock().unwrap();
-
-    if let Some(mut child) = process.take() {
-        // Send 'q' to FFmpeg stdin to gracefully stop
-        if let Some(mut stdin) = child.stdin.take() {
-            println!("[stop_camera_recording] Sending 'q' to FFmpeg to stop gracefully");
-            if let Err(e) = stdin.write_all(b"q") {
-                println!("[stop_camera_recording] Warning: Failed to send 'q' to FFmpeg: {}", e);
-                child.kill()
-                    .map_err(|e| format!("Failed to stop camera recording: {}", e))?;
-            } else {
-                let _ = stdin.flush();
-                drop(stdin);
-            }
-        } else {
-            println!("[stop_camera_recording] No stdin available, using kill");
-            child.kill()
-                .map_err(|e| format!("Failed to stop camera recording: {}", e))?;
-        }
+async fn preview_safe_areas(input_path: String, output_path: String, platform: String) -> Result<String, String> {
+    println!("[preview_safe_areas] {} safe areas: {} -> {}", platform, input_path, output_path);
 
-        println!("[stop_camera_recording] Waiting for FFmpeg to finish encoding...");
-        child.wait()
-            .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+    if !std::path::Path::new(&input_path).exists() {
+        return Err(format!("Input file not found: {}", input_path));
+    }
+    let (top, bottom, right) = safe_area_margins(&platform).ok_or_else(|| {
+        format!("Unknown platform: {} (expected \"tiktok\", \"reels\" or \"shorts\")", platform)
+    })?;
+
+    // Translucent boxes over the zones the platform UI covers; quick preview-quality encode
+    let boxes = [
+        format!("drawbox=x=0:y=0:w=iw:h=ih*{}:color=red@0.35:t=fill", top),
+        format!("drawbox=x=0:y=ih*(1-{b}):w=iw:h=ih*{b}:color=red@0.35:t=fill", b = bottom),
+        format!(
+            "drawbox=x=iw*(1-{r}):y=ih*{t}:w=iw*{r}:h=ih*(1-{t}-{b}):color=red@0.35:t=fill",
+            r = right,
+            t = top,
+            b = bottom
+        ),
+    ];
 
-        println!("[stop_camera_recording] Camera recording stopped successfully");
-        Ok("Camera recording stopped".to_string())
-    } else {
-        Err("No camera recording in progress".to_string())
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(), input_path.clone(),
+        "-vf".to_string(), boxes.join(","),
+        "-c:v".to_string(), "libx264".to_string(),
+        "-preset".to_string(), "veryfast".to_string(),
+        "-crf".to_string(), "28".to_string(),
+        "-c:a".to_string(), "copy".to_string(),
+    ];
+    if is_mp4_container(&output_path) {
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
     }
-}
+    args.push(output_path.clone());
 
-#[tauri::command]
-fn is_camera_recording() -> bool {
-    let process = CAMERA_RECORDING_PROCESS.lock().unwrap();
-    process.is_some()
+    run_ffmpeg(args, "preview_safe_areas").await?;
+    verify_output_file(&output_path)?;
+
+    println!("[preview_safe_areas] Preview written: {}", output_path);
+    Ok(output_path)
 }
 
-#[tauri::command]
-fn get_screen_resolution() -> Result<ScreenResolution, String> {
-    // Note: These are default values. In a production app, you might want to query
-    // the actual screen resolution using platform-specific APIs.
-    // For now, returning common defaults that work with scaling filters
-    Ok(ScreenResolution {
-        width: 1920,
-        height: 1080,
-    })
+#[derive(Debug, Deserialize)]
+struct AudioMergeInput {
+    path: String,
+    #[serde(default = "default_volume")]
+    volume: f64, // 0.0 to 1.0 (values above 1.0 amplify)
 }
 
-#[tauri::command]
-fn get_camera_capabilities() -> Result<CameraCapabilities, String> {
-    // Note: These are default values. Camera capabilities vary by device.
-    // Supported resolutions should match what's being used in the recording commands.
-    Ok(CameraCapabilities {
-        native_width: 1920,
-        native_height: 1080,
-        supported_resolutions: vec!["720p".to_string(), "1080p".to_string(), "source".to_string()],
-    })
+fn default_volume() -> f64 {
+    1.0
 }
 
 #[tauri::command]
-fn list_audio_video_devices() -> Result<AudioVideoDevices, String> {
-    if cfg!(target_os = "windows") {
-        // On Windows, use FFmpeg to list DirectShow devices
-        let ffmpeg_path = get_ffmpeg_path();
-        let output = Command::new(&ffmpeg_path)
-            .args(&["-list_devices", "true", "-f", "dshow", "-i", "dummy"])
-            .output()
-            .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+async fn merge_audio_tracks(
+    inputs: Vec<AudioMergeInput>,
+    output_path: String,
+    video_from: Option<String>,
+) -> Result<String, String> {
+    println!("[merge_audio_tracks] Merging {} audio inputs into {}", inputs.len(), output_path);
 
-        // FFmpeg outputs device list to stderr
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let mut video_devices = Vec::new();
-        let mut audio_devices = Vec::new();
-        let mut in_video_section = false;
-        let mut in_audio_section = false;
+    if inputs.is_empty() {
+        return Err("No audio inputs provided".to_string());
+    }
 
-        for line in stderr.lines() {
-            if line.contains("DirectShow video devices") {
-                in_video_section = true;
-                in_audio_section = false;
-            } else if line.contains("DirectShow audio devices") {
-                in_video_section = false;
-                in_audio_section = true;
-            } else if line.starts_with("[dshow") && line.contains("\"") {
-                // Extract device name from lines like: [dshow @ ...] "Device Name"
-                if let Some(start) = line.find('"') {
-                    if let Some(end) = line[start + 1..].find('"') {
-                        let device_name = &line[start + 1..start + 1 + end];
-                        if in_video_section {
-                            video_devices.push(device_name.to_string());
-                        } else if in_audio_section {
-                            audio_devices.push(device_name.to_string());
-                        }
-                    }
-                }
-            }
+    for input in &inputs {
+        if !std::path::Path::new(&input.path).exists() {
+            return Err(format!("Audio file not found: {}", input.path));
         }
+        if input.volume < 0.0 {
+            return Err(format!("Invalid volume {} for {}", input.volume, input.path));
+        }
+    }
 
-        Ok(AudioVideoDevices {
-            video_devices,
-            audio_devices,
-        })
-    } else if cfg!(target_os = "macos") {
-        // On macOS, use FFmpeg to list AVFoundation devices
-        let ffmpeg_path = get_ffmpeg_path();
-        let output = Command::new(&ffmpeg_path)
-            .args(&["-f", "avfoundation", "-list_devices", "true", "-i", ""])
-            .output()
-            .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+    let mut args = vec!["-y".to_string()];
+    for input in &inputs {
+        args.push("-i".to_string());
+        args.push(input.path.clone());
+    }
 
-        // FFmpeg outputs device list to stderr
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let mut video_devices = Vec::new();
-        let mut audio_devices = Vec::new();
+    // Resample every input to a common rate before mixing so amix doesn't pick one arbitrarily
+    let mut filter_parts = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+        filter_parts.push(format!(
+            "[{}:a]aresample=48000,volume={}[a{}]",
+            i, input.volume, i
+        ));
+    }
+
+    let mix_inputs: String = (0..inputs.len()).map(|i| format!("[a{}]", i)).collect();
+    filter_parts.push(format!(
+        "{}amix=inputs={}:duration=longest[aout]",
+        mix_inputs,
+        inputs.len()
+    ));
 
-        for line in stderr.lines() {
-            if line.contains("[AVFoundation") && line.contains("]") {
-                // Extract device info from lines like: [AVFoundation indev @ ...] [0] FaceTime HD Camera
-                // Find the first closing bracket, then look for the second opening bracket
-                if let Some(first_close) = line.find(']') {
-                    let remaining = &line[first_close + 1..];
-                    if let Some(second_open) = remaining.find('[') {
-                        if let Some(second_close) = remaining[second_open..].find(']') {
-                            // Device name is after the second closing bracket
-                            let device_start = first_close + 1 + second_open + second_close + 1;
-                            if device_start < line.len() {
-                                let device_name = line[device_start..].trim();
-                                if !device_name.is_empty() {
-                                    if line.contains("video device") || !line.contains("audio device") {
-                                        video_devices.push(device_name.to_string());
-                                    } else if line.contains("audio device") {
-                                        audio_devices.push(device_name.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    args.push("-filter_complex".to_string());
+    args.push(filter_parts.join(";"));
+
+    // Optionally take the video stream from a separate source (copied untouched)
+    if let Some(ref video_path) = video_from {
+        if !std::path::Path::new(video_path).exists() {
+            return Err(format!("Video file not found: {}", video_path));
         }
+        args.push("-i".to_string());
+        args.push(video_path.clone());
+        args.push("-map".to_string());
+        args.push(format!("{}:v:0", inputs.len()));
+        args.push("-c:v".to_string());
+        args.push("copy".to_string());
+    }
 
-        Ok(AudioVideoDevices {
-            video_devices,
-            audio_devices,
-        })
-    } else {
-        Err("Unsupported platform".to_string())
+    args.push("-map".to_string());
+    args.push("[aout]".to_string());
+
+    if is_mp4_container(&output_path) {
+        args.push("-c:a".to_string());
+        args.push("aac".to_string());
+        args.push("-b:a".to_string());
+        args.push("192k".to_string());
+        args.push("-movflags".to_string());
+        args.push("+faststart".to_string());
     }
-}
 
-#[tauri::command]
-fn move_file(from: String, to: String) -> Result<String, String> {
-    use std::fs;
-    println!("[move_file] Moving file from {} to {}", from, to);
+    args.push(output_path.clone());
 
-    fs::rename(&from, &to)
-        .map_err(|e| format!("Failed to move file: {}", e))?;
+    run_ffmpeg(args, "merge_audio_tracks").await?;
 
-    println!("[move_file] File moved successfully");
-    Ok("File moved".to_string())
+    println!("[merge_audio_tracks] Merge completed successfully");
+    Ok(output_path)
+}
+
+// Helper function to pick the audio encoder for an audio-only export from its extension
+fn audio_only_codec_args(output_path: &str, audio_bitrate: &str) -> Result<Vec<String>, String> {
+    let extension = std::path::Path::new(output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    let args: Vec<&str> = match extension.as_deref() {
+        Some("mp3") => vec!["-c:a", "libmp3lame", "-b:a", audio_bitrate],
+        Some("m4a") | Some("aac") => vec!["-c:a", "aac", "-b:a", audio_bitrate],
+        Some("wav") => vec!["-c:a", "pcm_s16le"],
+        Some("flac") => vec!["-c:a", "flac"],
+        _ => return Err(format!("Unsupported audio output format (use .mp3, .m4a, .wav or .flac): {}", output_path)),
+    };
+    Ok(args.iter().map(|arg| arg.to_string()).collect())
 }
 
 #[tauri::command]
-fn delete_file(path: String) -> Result<String, String> {
-    use std::fs;
-    println!("[delete_file] Deleting file: {}", path);
+async fn export_composite_audio(
+    output_path: String,
+    tracks: Vec<TrackExportData>,
+    export_options: Option<CompositeExportOptions>,
+) -> Result<String, CommandError> {
+    println!("[export_composite_audio] Mixing {} tracks into {}", tracks.len(), output_path);
 
-    fs::remove_file(&path)
-        .map_err(|e| format!("Failed to delete file: {}", e))?;
+    if tracks.is_empty() {
+        return Err(CommandError::invalid_input("No tracks to export"));
+    }
 
-    println!("[delete_file] File deleted successfully");
-    Ok("File deleted".to_string())
+    let job = ExportJobGuard::register("composite_audio");
+    let opts = export_options.unwrap_or_default();
+    let separate_audio = opts.separate_audio_tracks.unwrap_or(false);
+    if separate_audio && !is_mp4_container(&output_path) {
+        return Err(CommandError::invalid_input("separate_audio_tracks requires .m4a output"));
+    }
+
+    // Same render window and resampling as the video export, so the mix lines up with it
+    let render_start = opts.render_start.unwrap_or(0.0);
+    if render_start < 0.0 {
+        return Err(CommandError::invalid_input(format!("Invalid render_start: {}", render_start)));
+    }
+    let render_duration = match opts.render_end {
+        Some(end) if end <= render_start => {
+            return Err(CommandError::invalid_input(format!(
+                "render_end ({}) must be after render_start ({})",
+                end, render_start
+            )));
+        }
+        Some(end) => Some(end - render_start),
+        None => None,
+    };
+    let audio_sample_rate = opts.audio_sample_rate.unwrap_or(48000);
+    if !(8000..=192000).contains(&audio_sample_rate) {
+        return Err(CommandError::invalid_input(format!("Invalid audio sample rate: {}", audio_sample_rate)));
+    }
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), None)?;
+    let codec_args = audio_only_codec_args(&output_path, profile.audio_bitrate).map_err(CommandError::invalid_input)?;
+
+    // Same track order as the video export; silent tracks (e.g. screen recordings
+    // without a mic) are left out of the mix
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+    order.sort_by_key(|&i| tracks[i].z_index);
+    order.retain(|&i| has_audio_stream(&tracks[i].path));
+    let audio_tracks: Vec<&TrackExportData> = order.iter().map(|&i| &tracks[i]).collect();
+    if audio_tracks.len() < tracks.len() {
+        println!(
+            "[export_composite_audio] Skipping {} tracks without audio",
+            tracks.len() - audio_tracks.len()
+        );
+    }
+    if audio_tracks.is_empty() {
+        return Err(CommandError::invalid_input("None of the tracks have audio"));
+    }
+
+    let ducking = resolve_composite_ducking(opts.ducking.as_ref(), &tracks, &order)?;
+    let audio_graph =
+        build_composite_audio_graph(&audio_tracks, ducking.as_ref(), separate_audio, audio_sample_rate, render_start)?;
+
+    // Length of the mix, for progress: the longest track from render_start, cut off at render_end
+    let mix_duration = audio_tracks
+        .iter()
+        .filter_map(|t| probe_duration(&t.path).ok())
+        .fold(0.0, f64::max)
+        - render_start;
+    let mix_duration = render_duration.map_or(mix_duration, |duration| duration.min(mix_duration));
+
+    let mut args = vec!["-y".to_string()];
+    for track in &audio_tracks {
+        if render_start > 0.0 {
+            args.push("-ss".to_string());
+            args.push(render_start.to_string());
+        }
+        args.push("-i".to_string());
+        args.push(track.path.clone());
+    }
+
+    args.extend(["-filter_complex".to_string(), audio_graph.graph]);
+    for label in &audio_graph.outputs {
+        args.push("-map".to_string());
+        args.push(label.clone());
+    }
+    args.extend(audio_graph.metadata);
+    args.extend([
+        "-vn".to_string(),
+        "-ar".to_string(), audio_sample_rate.to_string(),
+    ]);
+    args.extend(codec_args);
+    if let Some(duration) = render_duration {
+        args.push("-t".to_string());
+        args.push(duration.to_string());
+    }
+    args.extend(profile.movflags_args(&output_path));
+    args.push(output_path.clone());
+
+    run_ffmpeg_with_progress(args, "export_composite_audio", mix_duration, job.percent_setter()).await?;
+    verify_output_file(&output_path)?;
+
+    println!("[export_composite_audio] Output file: {}", output_path);
+    Ok(output_path)
 }
 
 #[tauri::command]
@@ -1227,21 +8530,24 @@ async fn export_composite_video(
     canvas_height: i32,
     export_options: Option<CompositeExportOptions>,
     _window: tauri::Window
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     println!("[export_composite_video] Starting composite export");
     println!("[export_composite_video] Output: {}", output_path);
     println!("[export_composite_video] Canvas size: {}x{}", canvas_width, canvas_height);
     println!("[export_composite_video] Tracks: {}", tracks.len());
 
     if tracks.is_empty() {
-        return Err("No tracks to export".to_string());
+        return Err(CommandError::invalid_input("No tracks to export"));
     }
 
+    let job = ExportJobGuard::register("composite");
+
     // Parse export options
     let opts = export_options.unwrap_or_else(|| CompositeExportOptions {
         resolution: Some("source".to_string()),
         source_width: Some(canvas_width),
         source_height: Some(canvas_height),
+        ..Default::default()
     });
 
     // Determine output resolution and bitrate
@@ -1259,23 +8565,135 @@ async fn export_composite_video(
             (canvas_width, canvas_height, "8000k")
         }
         Some(res) => {
-            return Err(format!("Invalid resolution: {}", res));
+            return Err(CommandError::invalid_input(format!("Invalid resolution: {}", res)));
+        }
+    };
+
+    // Optional preview window: every track starts at t=0 on the composite timeline,
+    // so seeking each input by the same amount renders just that region
+    let render_start = opts.render_start.unwrap_or(0.0);
+    if render_start < 0.0 {
+        return Err(CommandError::invalid_input(format!("Invalid render_start: {}", render_start)));
+    }
+    let render_duration = match opts.render_end {
+        Some(end) if end <= render_start => {
+            return Err(CommandError::invalid_input(format!("render_end ({}) must be after render_start ({})", end, render_start)));
         }
+        Some(end) => Some(end - render_start),
+        None => None,
     };
+    if opts.render_start.is_some() || opts.render_end.is_some() {
+        println!(
+            "[export_composite_video] Rendering region: {}s for {:?}s",
+            render_start, render_duration
+        );
+    }
+
+    let audio_sample_rate = opts.audio_sample_rate.unwrap_or(48000);
+    if !(8000..=192000).contains(&audio_sample_rate) {
+        return Err(CommandError::invalid_input(format!("Invalid audio sample rate: {}", audio_sample_rate)));
+    }
+    let profile = resolve_output_profile(opts.optimize_for.as_deref(), opts.x264_params.as_deref())?
+        .with_scenecut_keyframes(opts.scenecut_keyframes.unwrap_or(false));
+    if let Some(fps) = opts.output_fps {
+        if !(1..=240).contains(&fps) {
+            return Err(CommandError::invalid_input(format!("Invalid output_fps: {}", fps)));
+        }
+    }
+    // Generated color backgrounds run at the output rate instead of lavfi's default 25 fps
+    let background_rate = opts.output_fps.map(|fps| format!(":r={}", fps)).unwrap_or_default();
+
+    // Transparent canvases need an alpha-capable container/codec and no background fill
+    let transparent = opts.transparent_background.unwrap_or(false);
+    if transparent {
+        if opts.background.is_some() {
+            return Err(CommandError::invalid_input("transparent_background cannot be combined with a background"));
+        }
+        if profile.x264_params.is_some() {
+            return Err(CommandError::invalid_input("x264_params only applies to H.264 output, not transparent exports"));
+        }
+        alpha_output_codec(&output_path, bitrate, profile.audio_bitrate)?;
+        println!("[export_composite_video] Exporting with a transparent background");
+    }
 
     // Sort tracks by z-index (lower first, so they appear at bottom)
     let mut sorted_tracks = tracks.clone();
     sorted_tracks.sort_by_key(|t| t.z_index);
 
+    // Resolve the ducked track to its position after sorting (the sort is stable)
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+    order.sort_by_key(|&i| tracks[i].z_index);
+    let ducking = resolve_composite_ducking(opts.ducking.as_ref(), &tracks, &order)?;
+    if let Some((position, ref chain)) = ducking {
+        println!(
+            "[export_composite_video] Ducking track {} ({})",
+            order[position],
+            if chain.is_some() { "sidechain" } else { "static weight" }
+        );
+    }
+
     // Build FFmpeg filter graph
     let mut filter_parts = Vec::new();
     let mut overlay_chain = String::new();
 
-    // Create a black background canvas
-    filter_parts.push(format!(
-        "color=c=black:s={}x{}:d=30[bg]",
-        output_width, output_height
-    ));
+    // The background must last as long as the longest track, counted from render_start
+    // (every input is seeked by it) and cut off at render_end
+    let timeline_end = sorted_tracks
+        .iter()
+        .filter_map(|t| probe_duration(&t.path).ok())
+        .fold(0.0, f64::max);
+    let timeline_end = if timeline_end > 0.0 { timeline_end } else { 30.0 };
+    let background_duration = opts.render_end.unwrap_or(timeline_end).min(timeline_end) - render_start;
+    if background_duration <= 0.0 {
+        return Err(CommandError::invalid_input(format!(
+            "render_start ({}) is past the end of the timeline ({}s)",
+            render_start, timeline_end
+        )));
+    }
+
+    // Create the background canvas (black unless a color or image is requested)
+    // A background image is added as the last input so track input indices stay unchanged
+    let background_image = match opts.background {
+        Some(Background::Image(ref image_path)) => {
+            if !std::path::Path::new(image_path).exists() {
+                return Err(CommandError::invalid_input(format!("Background image not found: {}", image_path)));
+            }
+            filter_parts.push(format!(
+                "{}[bg]",
+                composite_background_image_filter(sorted_tracks.len(), output_width, output_height)
+            ));
+            Some(image_path.clone())
+        }
+        Some(Background::Color(ref color)) => {
+            if !is_valid_ffmpeg_color(color) {
+                return Err(CommandError::invalid_input(format!("Invalid background color: {}", color)));
+            }
+            filter_parts.push(format!(
+                "color=c={}:s={}x{}:d={}{}[bg]",
+                color, output_width, output_height, background_duration, background_rate
+            ));
+            None
+        }
+        None if transparent => {
+            // Fully transparent canvas; overlays keep yuva420p so the alpha survives
+            filter_parts.push(format!(
+                "color=c=black@0.0:s={}x{}:d={}{},format=yuva420p[bg]",
+                output_width, output_height, background_duration, background_rate
+            ));
+            None
+        }
+        None => {
+            filter_parts.push(format!(
+                "color=c=black:s={}x{}:d={}{}[bg]",
+                output_width, output_height, background_duration, background_rate
+            ));
+            None
+        }
+    };
+
+    // Color background filter without its output pad, for chunked rendering
+    let background_filter = filter_parts[0].trim_end_matches("[bg]").to_string();
+    let mut layers = Vec::new();
 
     // Process each video track
     for (i, track) in sorted_tracks.iter().enumerate() {
@@ -1289,64 +8707,102 @@ async fn export_composite_video(
         let scaled_y = (track.position_y as f64 * scale_y + output_height as f64 / 2.0 - scaled_height as f64 / 2.0) as i32;
 
         // Video filter: scale, apply opacity
-        filter_parts.push(format!(
-            "[{}:v]scale={}:{},format=yuva420p,colorchannelmixer=aa={}[v{}]",
-            i, scaled_width, scaled_height, track.opacity, i
-        ));
-
-        // Audio filter: apply volume
-        filter_parts.push(format!(
-            "[{}:a]volume={}[a{}]",
-            i, track.volume, i
-        ));
-
-        // Build overlay chain
-        if i == 0 {
-            overlay_chain = format!(
-                "[bg][v{}]overlay=x={}:y={}[tmp{}]",
-                i, scaled_x, scaled_y, i
-            );
-        } else if i == sorted_tracks.len() - 1 {
-            // Last overlay outputs to [vout]
-            overlay_chain.push_str(&format!(
-                ";[tmp{}][v{}]overlay=x={}:y={}[vout]",
-                i - 1, i, scaled_x, scaled_y
-            ));
-        } else {
-            overlay_chain.push_str(&format!(
-                ";[tmp{}][v{}]overlay=x={}:y={}[tmp{}]",
-                i - 1, i, scaled_x, scaled_y, i
-            ));
+        let tonemap = hdr_tonemap_filter(&track.path, opts.tonemap)?
+            .map(|chain| format!("{},", chain))
+            .unwrap_or_default();
+        // Fades animate alpha on top of the track's static opacity
+        let (video_fade, _) = track_fade_filters(track, render_start)?;
+        let video_filter = format!(
+            "{}scale={}:{},format=yuva420p,colorchannelmixer=aa={}{}",
+            tonemap, scaled_width, scaled_height, track.opacity, video_fade
+        );
+        filter_parts.push(format!("[{}:v]{}[v{}]", i, video_filter, i));
+
+        // Blending works on the covered region of the canvas, so the track must lie inside it
+        let blend_mode = resolve_blend_mode(track.blend_mode.as_deref())?;
+        if blend_mode.is_some()
+            && (scaled_x < 0 || scaled_y < 0 || scaled_x + scaled_width > output_width || scaled_y + scaled_height > output_height)
+        {
+            return Err(CommandError::invalid_input(format!("Blend mode requires the track to be fully inside the canvas: {}", track.path)));
         }
-    }
 
-    // Handle single track case
-    if sorted_tracks.len() == 1 {
-        overlay_chain = format!(
-            "[bg][v0]overlay=x={}:y={}[vout]",
-            (sorted_tracks[0].position_x as f64 * output_width as f64 / canvas_width as f64 + output_width as f64 / 2.0 - (sorted_tracks[0].width as f64 * output_width as f64 / canvas_width as f64) / 2.0) as i32,
-            (sorted_tracks[0].position_y as f64 * output_height as f64 / canvas_height as f64 + output_height as f64 / 2.0 - (sorted_tracks[0].height as f64 * output_height as f64 / canvas_height as f64) / 2.0) as i32
-        );
+        let layer = CompositeLayer {
+            path: track.path.clone(),
+            video_filter,
+            x: scaled_x,
+            y: scaled_y,
+            width: scaled_width,
+            height: scaled_height,
+            opacity: track.opacity,
+            blend_mode,
+        };
+
+        // Build overlay chain (the last overlay outputs to [vout])
+        let base = if i == 0 { "[bg]".to_string() } else { format!("[tmp{}]", i - 1) };
+        let output = if i == sorted_tracks.len() - 1 { "[vout]".to_string() } else { format!("[tmp{}]", i) };
+        if i > 0 {
+            overlay_chain.push(';');
+        }
+        overlay_chain.push_str(&composite_layer_stage(&base, &format!("[v{}]", i), &layer, i, &output));
+        layers.push(layer);
     }
 
     // Build audio mix
-    let audio_inputs: Vec<String> = (0..sorted_tracks.len()).map(|i| format!("[a{}]", i)).collect();
-    let audio_mix = if sorted_tracks.len() > 1 {
-        format!(
-            ";{}amix=inputs={}:duration=longest[aout]",
-            audio_inputs.join(""),
-            sorted_tracks.len()
+    let separate_audio = opts.separate_audio_tracks.unwrap_or(false);
+    let audio_tracks: Vec<&TrackExportData> = sorted_tracks.iter().collect();
+    let CompositeAudioGraph { graph: audio_graph, outputs: audio_outputs, metadata: audio_metadata } =
+        build_composite_audio_graph(&audio_tracks, ducking.as_ref(), separate_audio, audio_sample_rate, render_start)?;
+
+    // Large projects are rendered in chunks to keep each filter graph small
+    let chunk_threshold = opts.chunk_threshold.unwrap_or(CHUNKED_COMPOSITE_THRESHOLD);
+    if sorted_tracks.len() > chunk_threshold {
+        // Chunk intermediates are H.264 and would drop the alpha channel
+        if transparent {
+            return Err(CommandError::invalid_input(format!(
+                "Transparent background supports at most {} tracks (raise chunk_threshold to render in one pass)",
+                chunk_threshold
+            )));
+        }
+        println!(
+            "[export_composite_video] {} tracks exceeds threshold {}, rendering in chunks of {}",
+            sorted_tracks.len(), chunk_threshold, COMPOSITE_CHUNK_SIZE
+        );
+        export_composite_chunked(
+            &layers,
+            &background_filter,
+            background_image.as_deref(),
+            background_duration,
+            (output_width, output_height),
+            &audio_graph,
+            &audio_outputs,
+            &audio_metadata,
+            render_start,
+            render_duration,
+            COMPOSITE_CHUNK_SIZE,
+            &profile,
+            bitrate,
+            audio_sample_rate,
+            opts.output_fps,
+            &output_path,
         )
-    } else {
-        ";[a0]anull[aout]".to_string()
-    };
+        .await?;
+        verify_output_file(&output_path)?;
+        if let Some(fps) = opts.output_fps {
+            verify_output_frame_rate(&output_path, fps)?;
+        }
+        if separate_audio {
+            verify_audio_stream_count(&output_path, audio_outputs.len())?;
+        }
+        println!("[export_composite_video] Output file: {}", output_path);
+        return Ok(output_path);
+    }
 
     // Combine all filter parts
     let complete_filter = format!(
-        "{};{}{}",
+        "{};{};{}",
         filter_parts.join(";"),
         overlay_chain,
-        audio_mix
+        audio_graph
     );
 
     println!("[export_composite_video] Filter graph: {}", complete_filter);
@@ -1354,10 +8810,32 @@ async fn export_composite_video(
     // Build FFmpeg command
     let mut args = vec!["-y".to_string()];
 
-    // Add input files
-    for track in &sorted_tracks {
+    // Add input files (hardware decode options apply to each track input)
+    let track_inputs = |hwaccel: &[String]| -> Vec<String> {
+        let mut inputs = Vec::new();
+        for track in &sorted_tracks {
+            inputs.extend(hwaccel.iter().cloned());
+            if render_start > 0.0 {
+                inputs.push("-ss".to_string());
+                inputs.push(render_start.to_string());
+            }
+            inputs.push("-i".to_string());
+            inputs.push(track.path.clone());
+        }
+        inputs
+    };
+    let hwaccel_args = resolve_hwaccel_args(opts.hwaccel.as_deref());
+    args.extend(track_inputs(&hwaccel_args));
+    let inputs_end = args.len();
+
+    // Looped still image for the background (input index == track count)
+    if let Some(ref image_path) = background_image {
+        args.push("-loop".to_string());
+        args.push("1".to_string());
+        args.push("-t".to_string());
+        args.push(background_duration.to_string());
         args.push("-i".to_string());
-        args.push(track.path.clone());
+        args.push(image_path.clone());
     }
 
     // Add filter complex
@@ -1367,40 +8845,46 @@ async fn export_composite_video(
     // Map output streams
     args.push("-map".to_string());
     args.push("[vout]".to_string());
-    args.push("-map".to_string());
-    args.push("[aout]".to_string());
+    for label in &audio_outputs {
+        args.push("-map".to_string());
+        args.push(label.clone());
+    }
+    args.extend(audio_metadata.iter().cloned());
 
-    // Encoding options
-    args.push("-c:v".to_string());
-    args.push("libx264".to_string());
-    args.push("-preset".to_string());
-    args.push("fast".to_string());
-    args.push("-b:v".to_string());
-    args.push(bitrate.to_string());
-    args.push("-c:a".to_string());
-    args.push("aac".to_string());
-    args.push("-b:a".to_string());
-    args.push("192k".to_string());
-    args.push("-pix_fmt".to_string());
-    args.push("yuv420p".to_string());
+    // Encoding options and output
+    args.extend(composite_output_args(&profile, bitrate, audio_sample_rate, render_duration, opts.output_fps, transparent, &output_path));
 
-    args.push(output_path.clone());
+    // Same command without hardware decode, used if the hardware path fails
+    let software_args = if hwaccel_args.is_empty() {
+        None
+    } else {
+        Some([vec!["-y".to_string()], track_inputs(&[]), args[inputs_end..].to_vec()].concat())
+    };
 
     println!("[export_composite_video] FFmpeg args: {:?}", args);
 
-    // Execute FFmpeg
+    // Execute FFmpeg; stderr is piped for progress and drained by the reader thread
     let ffmpeg_path = get_ffmpeg_path();
     let mut child = Command::new(&ffmpeg_path)
         .args(&args)
+        .stderr(Stdio::piped())
         .spawn()
         .map_err(|e| {
-            let err_msg = format!("Failed to start FFmpeg: {}", e);
-            println!("[export_composite_video] ERROR: {}", err_msg);
-            err_msg
+            let err = CommandError::spawn_failed(e, "Failed to start FFmpeg");
+            println!("[export_composite_video] ERROR: {}", err);
+            err
         })?;
+    let progress_reader = child.stderr.take().map(|stderr| {
+        let set_percent = job.percent_setter();
+        spawn_ffmpeg_progress_reader(stderr, background_duration, move |percent, _| set_percent(percent))
+    });
 
     println!("[export_composite_video] Waiting for FFmpeg to complete...");
-    let status = tokio::task::spawn_blocking(move || child.wait())
+    let (status, stderr_tail) = tokio::task::spawn_blocking(move || {
+        let status = child.wait();
+        let tail = progress_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        status.map(|status| (status, tail))
+    })
         .await
         .map_err(|e| {
             let err_msg = format!("Task join error: {}", e);
@@ -1413,14 +8897,31 @@ async fn export_composite_video(
             err_msg
         })?;
 
-    if status.success() {
+    let succeeded = match software_args {
+        _ if status.success() => true,
+        Some(software_args) => {
+            println!("[export_composite_video] Warning: hardware decode failed ({}), retrying with software decode: {}", status, stderr_tail);
+            run_ffmpeg_with_progress(software_args, "export_composite_video", background_duration, job.percent_setter()).await?;
+            true
+        }
+        None => false,
+    };
+
+    if succeeded {
         println!("[export_composite_video] FFmpeg completed successfully!");
+        verify_output_file(&output_path)?;
+        if let Some(fps) = opts.output_fps {
+            verify_output_frame_rate(&output_path, fps)?;
+        }
+        if separate_audio {
+            verify_audio_stream_count(&output_path, audio_outputs.len())?;
+        }
         println!("[export_composite_video] Output file: {}", output_path);
         Ok(output_path)
     } else {
         let err_msg = format!("FFmpeg exited with status: {}", status);
-        println!("[export_composite_video] ERROR: {}", err_msg);
-        Err(err_msg)
+        println!("[export_composite_video] ERROR: {}\n{}", err_msg, stderr_tail);
+        Err(CommandError::new(ErrorCode::NonZeroExit, err_msg).with_detail(stderr_tail))
     }
 }
 
@@ -1465,26 +8966,722 @@ pub fn run() {
             greet,
             open_file_dialog,
             get_video_metadata,
+            get_duration,
+            get_frame_at,
             get_video_file,
             get_video_file_path,
+            reveal_in_file_manager,
             trim_video,
+            trim_video_bytes,
+            clip_around,
+            trim_by_frames,
+            splice_video,
             concatenate_clips,
             save_file_dialog,
             start_screen_recording,
             stop_screen_recording,
+            snapshot_recording,
             is_recording,
             start_screen_preview,
             stop_screen_preview,
+            save_preview_recording,
+            start_mic_monitor,
+            stop_mic_monitor,
             start_camera_recording,
             stop_camera_recording,
             is_camera_recording,
             get_screen_resolution,
             get_camera_capabilities,
             list_audio_video_devices,
+            list_windows,
+            list_hwaccels,
+            set_command_timeout,
+            set_default_output_dir,
+            list_clipforge_ffmpeg_processes,
+            kill_orphaned_ffmpeg,
+            is_export_running,
+            list_active_exports,
+            start_device_monitoring,
+            stop_device_monitoring,
             move_file,
             delete_file,
-            export_composite_video
+            remux,
+            trim_silence_edges,
+            detect_breaks,
+            detect_vfr,
+            mux_av,
+            denoise,
+            compare_videos,
+            benchmark_encoder,
+            estimate_export_size,
+            add_waveform_visualization,
+            preview_safe_areas,
+            convert_to_cfr,
+            save_project,
+            load_project,
+            relocate_media,
+            merge_audio_tracks,
+            convert_framerate,
+            apply_lut,
+            set_rotation_metadata,
+            blur_region,
+            frames_to_video,
+            check_av_sync,
+            fix_av_sync,
+            generate_storyboard,
+            generate_contact_sheet,
+            add_countdown_intro,
+            get_chapters,
+            set_chapters,
+            export_composite_video,
+            export_composite_audio
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Renders `filter` over a black clip and returns the raw grayscale frames,
+    // or None when no FFmpeg is installed on the test machine
+    fn render_gray_frames(filter: &str, width: usize, height: usize, seconds: u32) -> Option<Vec<Vec<u8>>> {
+        let output = Command::new(get_ffmpeg_path())
+            .args([
+                "-v", "error",
+                "-f", "lavfi",
+                "-i", &format!("color=c=black:s={}x{}:r=10:d={}", width, height, seconds),
+                "-vf", filter,
+                "-f", "rawvideo",
+                "-pix_fmt", "gray",
+                "-",
+            ])
+            .output()
+            .ok()?;
+        assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+        Some(output.stdout.chunks(width * height).map(|frame| frame.to_vec()).collect())
+    }
+
+    #[test]
+    fn progress_bar_grows_over_time() {
+        let bar = ProgressBarOverlay {
+            position: Some("bottom".to_string()),
+            color: Some("white".to_string()),
+            height: Some(4),
+        };
+        let filter = build_progress_bar_filter(&bar, 0.0, 2.0).unwrap();
+        let Some(frames) = render_gray_frames(&filter, 64, 32, 2) else {
+            eprintln!("ffmpeg not available, skipping");
+            return;
+        };
+
+        let bar_width = |frame: &Vec<u8>| frame[31 * 64..].iter().filter(|&&p| p > 128).count();
+        let first = bar_width(frames.first().unwrap());
+        let last = bar_width(frames.last().unwrap());
+        assert!(first < last, "bar should widen: {} at start, {} at end", first, last);
+    }
+
+    // `ffmpeg -f avfoundation -list_devices true -i ""` output, trimmed of the build banner
+    const AVFOUNDATION_VIDEO_ONLY: &str = "\
+[AVFoundation indev @ 0x13a704a20] AVFoundation video devices:
+[AVFoundation indev @ 0x13a704a20] [0] FaceTime HD Camera
+[AVFoundation indev @ 0x13a704a20] [1] Capture screen 0
+[AVFoundation indev @ 0x13a704a20] AVFoundation audio devices:
+[in#0 @ 0x13a7048f0] Error opening input: Input/output error
+Error opening input file .
+";
+
+    const AVFOUNDATION_AUDIO_ONLY: &str = "\
+[AVFoundation indev @ 0x7fb1c5f04a40] AVFoundation video devices:
+[AVFoundation indev @ 0x7fb1c5f04a40] AVFoundation audio devices:
+[AVFoundation indev @ 0x7fb1c5f04a40] [0] MacBook Pro Microphone
+[AVFoundation indev @ 0x7fb1c5f04a40] [1] BlackHole 2ch
+: Input/output error
+";
+
+    const AVFOUNDATION_BRACKETED_NAMES: &str = "\
+[AVFoundation indev @ 0x600001c6c000] AVFoundation video devices:
+[AVFoundation indev @ 0x600001c6c000] [0] Logitech BRIO [4K]
+[AVFoundation indev @ 0x600001c6c000] [1] Capture screen 0
+[AVFoundation indev @ 0x600001c6c000] AVFoundation audio devices:
+[AVFoundation indev @ 0x600001c6c000] [0] Jabra Evolve2 [USB] (Mono)
+[AVFoundation indev @ 0x600001c6c000] [1] ZoomAudioDevice
+[in#0 @ 0x600001d68000] Error opening input: Input/output error
+";
+
+    const AVFOUNDATION_EMPTY: &str = "\
+[AVFoundation indev @ 0x14be05e80] AVFoundation video devices:
+[AVFoundation indev @ 0x14be05e80] AVFoundation audio devices:
+[in#0 @ 0x14be05b90] Error opening input: Input/output error
+Error opening input file .
+";
+
+    fn device(index: i32, name: &str) -> DeviceInfo {
+        DeviceInfo { index, name: name.to_string() }
+    }
+
+    #[test]
+    fn avfoundation_video_only() {
+        let devices = parse_avfoundation_devices(AVFOUNDATION_VIDEO_ONLY);
+        assert_eq!(devices.video_devices, vec![device(0, "FaceTime HD Camera"), device(1, "Capture screen 0")]);
+        assert!(devices.audio_devices.is_empty());
+    }
+
+    #[test]
+    fn avfoundation_audio_only() {
+        let devices = parse_avfoundation_devices(AVFOUNDATION_AUDIO_ONLY);
+        assert!(devices.video_devices.is_empty());
+        assert_eq!(devices.audio_devices, vec![device(0, "MacBook Pro Microphone"), device(1, "BlackHole 2ch")]);
+    }
+
+    #[test]
+    fn avfoundation_names_with_brackets() {
+        let devices = parse_avfoundation_devices(AVFOUNDATION_BRACKETED_NAMES);
+        assert_eq!(devices.video_devices, vec![device(0, "Logitech BRIO [4K]"), device(1, "Capture screen 0")]);
+        assert_eq!(
+            devices.audio_devices,
+            vec![device(0, "Jabra Evolve2 [USB] (Mono)"), device(1, "ZoomAudioDevice")]
+        );
+    }
+
+    #[test]
+    fn avfoundation_empty_list() {
+        let devices = parse_avfoundation_devices(AVFOUNDATION_EMPTY);
+        assert!(devices.video_devices.is_empty());
+        assert!(devices.audio_devices.is_empty());
+    }
+
+    // `pactl list sources`, trimmed to the first few properties of each source
+    const PACTL_SOURCES: &str = "\
+Source #0
+\tState: SUSPENDED
+\tName: alsa_output.pci-0000_00_1f.3.analog-stereo.monitor
+\tDescription: Monitor of Built-in Audio Analog Stereo
+\tDriver: module-alsa-card.c
+\tSample Specification: s16le 2ch 44100Hz
+
+Source #1
+\tState: RUNNING
+\tName: alsa_input.pci-0000_00_1f.3.analog-stereo
+\tDescription: Built-in Audio Analog Stereo
+\tDriver: module-alsa-card.c
+\tProperties:
+\t\tdevice.description = \"Built-in Audio\"
+
+Source #5
+\tState: IDLE
+\tName: alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone-00.analog-stereo
+\tDescription: Yeti Stereo Microphone Analog Stereo
+";
+
+    #[test]
+    fn pactl_sources_report_names_with_their_index() {
+        assert_eq!(
+            parse_pactl_sources(PACTL_SOURCES),
+            vec![
+                device(0, "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"),
+                device(1, "alsa_input.pci-0000_00_1f.3.analog-stereo"),
+                device(5, "alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone-00.analog-stereo"),
+            ]
+        );
+        assert!(parse_pactl_sources("").is_empty());
+    }
+
+    #[test]
+    fn concat_segment_pads_short_audio_to_the_video() {
+        if !ffprobe_available() {
+            eprintln!("ffprobe not available, skipping");
+            return;
+        }
+        let dir = frame_dir("concat_short_audio", &[]);
+        let source = dir.join("short_audio.mp4");
+        let segment = dir.join("segment_0.mp4");
+
+        // 3s of video with only 1s of audio, like a webcam capture whose mic started late
+        let generated = Command::new(get_ffmpeg_path())
+            .args([
+                "-v", "error", "-y",
+                "-f", "lavfi", "-i", "testsrc=s=64x36:r=10:d=3",
+                "-f", "lavfi", "-i", "sine=f=440:d=1",
+                "-c:a", "aac",
+                &source.to_string_lossy(),
+            ])
+            .status();
+        assert!(generated.is_ok_and(|status| status.success()), "failed to generate test clip");
+
+        let clip = ClipSegment {
+            path: source.to_string_lossy().to_string(),
+            clip_start: 0.0,
+            clip_end: 3.0,
+        };
+        let profile = resolve_output_profile(None, None).unwrap();
+        let output = Command::new(get_ffmpeg_path())
+            .args(concat_segment_args(&clip, &segment.to_string_lossy(), true, &profile))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+
+        let stream_duration = |selector: &str| -> f64 {
+            probe_entry(&segment.to_string_lossy(), "stream=duration", Some(selector))
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap()
+        };
+        let (video, audio) = (stream_duration("v:0"), stream_duration("a:0"));
+        let _ = std::fs::remove_dir_all(&dir);
+        // Within one AAC frame (1024 samples) of the video
+        assert!((audio - video).abs() < 0.05, "audio {}s vs video {}s", audio, video);
+        assert!(video > 2.5, "video was cut short: {}s", video);
+    }
+
+    #[test]
+    fn ducking_attenuates_main_audio_only_while_commentary_plays() {
+        let ducking = DuckingOptions { target_track: None, threshold: None, ratio: None };
+        let Some(duck) = build_ducking_filter(&ducking, "[0:a]", "[key]", "[a]").unwrap() else {
+            eprintln!("ffmpeg or sidechaincompress not available, skipping");
+            return;
+        };
+
+        // 6s of steady tone; commentary from 2s to 4s
+        let graph = format!("[1:a]volume=4,adelay=2000:all=1[key];{}", duck);
+        let output = Command::new(get_ffmpeg_path())
+            .args([
+                "-v", "error",
+                "-f", "lavfi", "-i", "sine=f=220:d=6",
+                "-f", "lavfi", "-i", "sine=f=880:d=2",
+                "-filter_complex", &graph,
+                "-map", "[a]",
+                "-t", "6",
+                "-ac", "1",
+                "-ar", "48000",
+                "-f", "f32le",
+                "-",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+
+        let samples: Vec<f32> = output
+            .stdout
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        let rms = |from: f64, to: f64| {
+            let window = &samples[(from * 48000.0) as usize..(to * 48000.0) as usize];
+            (window.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / window.len() as f64).sqrt()
+        };
+        let (before, during, after) = (rms(0.5, 1.5), rms(2.5, 3.5), rms(5.0, 5.8));
+        assert!(during < before * 0.5, "main should duck under commentary: {} before, {} during", before, during);
+        assert!((after - before).abs() < before * 0.1, "main should recover: {} before, {} after", before, after);
+    }
+
+    fn audio_track(path: &str, language: Option<&str>) -> TrackExportData {
+        TrackExportData {
+            path: path.to_string(),
+            position_x: 0,
+            position_y: 0,
+            volume: 1.0,
+            opacity: 1.0,
+            width: 1280,
+            height: 720,
+            z_index: 0,
+            blend_mode: None,
+            fade_in: None,
+            fade_out: None,
+            language: language.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn separate_composite_audio_maps_one_labelled_stream_per_track() {
+        let tracks = [
+            audio_track("/media/screen.mp4", None),
+            audio_track("/media/camera.mov", Some("eng")),
+            audio_track("/media/music.m4a", Some("fra")),
+        ];
+        let tracks: Vec<&TrackExportData> = tracks.iter().collect();
+
+        let audio = build_composite_audio_graph(&tracks, None, true, 48000, 0.0).unwrap();
+        assert_eq!(audio.outputs, vec!["[a0]", "[a1]", "[a2]"]);
+        for (i, label) in audio.outputs.iter().enumerate() {
+            assert!(audio.graph.contains(&format!("[{}:a]aresample=48000,volume=1{}", i, label)));
+        }
+        assert!(!audio.graph.contains("amix"), "separate tracks must not be mixed: {}", audio.graph);
+
+        let expected: Vec<String> = [
+            ("0", "screen", "und", "default"),
+            ("1", "camera", "eng", "0"),
+            ("2", "music", "fra", "0"),
+        ]
+        .iter()
+        .flat_map(|(i, title, language, disposition)| {
+            [
+                format!("-metadata:s:a:{}", i), format!("title={}", title),
+                format!("-metadata:s:a:{}", i), format!("language={}", language),
+                format!("-disposition:a:{}", i), disposition.to_string(),
+            ]
+        })
+        .collect();
+        assert_eq!(audio.metadata, expected);
+    }
+
+    #[test]
+    fn separate_composite_audio_keeps_a_stream_per_track_when_ducking() {
+        let tracks = [
+            audio_track("/media/screen.mp4", None),
+            audio_track("/media/camera.mov", None),
+            audio_track("/media/music.m4a", None),
+        ];
+        let tracks: Vec<&TrackExportData> = tracks.iter().collect();
+        let chain = "[a0][duckkeysrc]sidechaincompress=threshold=0.05:ratio=8:attack=20:release=400[ducked]".to_string();
+
+        let audio = build_composite_audio_graph(&tracks, Some(&(0, Some(chain))), true, 48000, 0.0).unwrap();
+        assert_eq!(audio.outputs, vec!["[ducked]", "[a1out]", "[a2out]"]);
+        // Every mapped label is produced exactly once and consumed nowhere else in the graph
+        for label in &audio.outputs {
+            assert_eq!(audio.graph.matches(label.as_str()).count(), 1, "{} in {}", label, audio.graph);
+        }
+        assert_eq!(audio.metadata.len(), 3 * 6);
+    }
+
+    #[test]
+    fn pip_audio_with_both_sources_mixes_them() {
+        let graph = build_pip_audio_filter(true, true, false, 0.8, 0.0, None);
+        assert_eq!(graph, "[0:a][1:a]amix=inputs=2:duration=first:weights=1 0.8[a]");
+    }
+
+    #[test]
+    fn pip_audio_silent_pip_over_main_with_audio() {
+        let graph = build_pip_audio_filter(true, false, false, 0.8, 0.0, None);
+        assert_eq!(graph, "[0:a]anull[a]");
+        assert!(!graph.contains("[1:a]"));
+    }
+
+    #[test]
+    fn pip_audio_pip_with_audio_over_silent_main() {
+        let graph = build_pip_audio_filter(false, true, false, 0.8, 12.5, None);
+        assert!(!graph.contains("[0:a]"));
+        assert!(graph.contains("anullsrc=channel_layout=stereo:sample_rate=48000,atrim=duration=12.5[silence]"));
+        assert!(graph.ends_with("[silence][1:a]amix=inputs=2:duration=first:weights=1 0.8[a]"));
+    }
+
+    #[test]
+    fn pip_audio_both_silent_generates_silence() {
+        let graph = build_pip_audio_filter(false, false, false, 0.8, 12.5, None);
+        assert_eq!(graph, "anullsrc=channel_layout=stereo:sample_rate=48000,atrim=duration=12.5[a]");
+    }
+
+    #[test]
+    fn pip_scale_keeps_a_4_3_camera_undistorted_over_16_9() {
+        // 64x36 main, 40x30 camera at half width: 32 wide and 32 / (4/3) = 24 high
+        let filter = build_pip_scale_filter(50.0);
+        let Ok(output) = Command::new(get_ffmpeg_path())
+            .args([
+                "-v", "error",
+                "-f", "lavfi", "-i", "color=c=black:s=64x36:r=1:d=1",
+                "-f", "lavfi", "-i", "color=c=white:s=40x30:r=1:d=1",
+                "-filter_complex", &format!("{};[main]nullsink", filter),
+                "-map", "[pipscaled]",
+                "-frames:v", "1",
+                "-f", "rawvideo",
+                "-pix_fmt", "gray",
+                "-",
+            ])
+            .output()
+        else {
+            eprintln!("ffmpeg not available, skipping");
+            return;
+        };
+        assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(output.stdout.len(), 32 * 24);
+    }
+
+    // Composites a 16x16 mid-gray layer at (8, 8) over a 32x32 mid-gray base and returns the
+    // gray frame, or None when no FFmpeg is installed on the test machine
+    fn render_composite_frame(blend_mode: Option<&str>) -> Option<Vec<u8>> {
+        let layer = CompositeLayer {
+            path: String::new(),
+            video_filter: String::new(),
+            x: 8,
+            y: 8,
+            width: 16,
+            height: 16,
+            opacity: 1.0,
+            blend_mode: blend_mode.map(str::to_string),
+        };
+        let stage = composite_layer_stage("[0:v]", "[1:v]", &layer, 0, "[v]");
+        let output = Command::new(get_ffmpeg_path())
+            .args([
+                "-v", "error",
+                "-f", "lavfi", "-i", "color=c=0x808080:s=32x32:r=1:d=1",
+                "-f", "lavfi", "-i", "color=c=0x808080:s=16x16:r=1:d=1",
+                "-filter_complex", &stage,
+                "-map", "[v]",
+                "-frames:v", "1",
+                "-f", "rawvideo",
+                "-pix_fmt", "gray",
+                "-",
+            ])
+            .output()
+            .ok()?;
+        assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+        Some(output.stdout)
+    }
+
+    #[test]
+    fn screen_blend_differs_from_normal_overlay() {
+        let (Some(normal), Some(screen)) = (render_composite_frame(None), render_composite_frame(Some("screen"))) else {
+            eprintln!("ffmpeg not available, skipping");
+            return;
+        };
+
+        // Screen lightens mid-gray over mid-gray (1 - 0.5 * 0.5 = 75%); a normal overlay leaves it alone
+        let center = 16 * 32 + 16;
+        assert!(
+            screen[center] as i32 > normal[center] as i32 + 30,
+            "screen should brighten the covered region: normal {}, screen {}",
+            normal[center],
+            screen[center]
+        );
+        // Outside the layer both leave the base untouched
+        assert!((screen[0] as i32 - normal[0] as i32).abs() <= 2);
+    }
+
+    // silencedetect output from `ffmpeg -i talk.m4a -af silencedetect=noise=-40dB:d=0.1 -f null -`;
+    // the clip starts silent (encoder priming shifts it slightly negative) and ends silent
+    const SILENCEDETECT_STDERR: &str = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'talk.m4a':
+  Duration: 00:00:12.50, start: 0.000000, bitrate: 129 kb/s
+Stream mapping:
+  Stream #0:0 -> #0:0 (aac (native) -> pcm_s16le (native))
+[silencedetect @ 0x600003a3c000] silence_start: -0.0213333
+[silencedetect @ 0x600003a3c000] silence_end: 1.50431 | silence_duration: 1.52565
+[silencedetect @ 0x600003a3c000] silence_start: 5.2
+[silencedetect @ 0x600003a3c000] silence_end: 6.03 | silence_duration: 0.83
+[silencedetect @ 0x600003a3c000] silence_start: 11.9
+size=N/A time=00:00:12.50 bitrate=N/A speed= 412x
+";
+
+    #[test]
+    fn silencedetect_clamps_negative_start_and_closes_trailing_silence() {
+        assert_eq!(
+            parse_silencedetect(SILENCEDETECT_STDERR, 12.5),
+            vec![(0.0, 1.50431), (5.2, 6.03), (11.9, 12.5)]
+        );
+    }
+
+    #[test]
+    fn silencedetect_ignores_unmatched_silence_end() {
+        let stderr = "\
+[silencedetect @ 0x55d0c8f0a5c0] silence_end: 0.84 | silence_duration: 0.84
+[silencedetect @ 0x55d0c8f0a5c0] silence_start: 3.1
+[silencedetect @ 0x55d0c8f0a5c0] silence_end: 3.9 | silence_duration: 0.8
+";
+        assert_eq!(parse_silencedetect(stderr, 10.0), vec![(3.1, 3.9)]);
+        assert!(parse_silencedetect("size=N/A time=00:00:10.00 bitrate=N/A", 10.0).is_empty());
+    }
+
+    // One detect_breaks pass over a 60s recording: blackdetect and silencedetect lines interleave
+    const BREAK_DETECT_STDERR: &str = "\
+[blackdetect @ 0x7f9e4c006a80] black_start:0 black_end:0.8 black_duration:0.8
+[silencedetect @ 0x7f9e4c00b2c0] silence_start: 0
+[silencedetect @ 0x7f9e4c00b2c0] silence_end: 0.6 | silence_duration: 0.6
+[blackdetect @ 0x7f9e4c006a80] black_start:20.4 black_end:22 black_duration:1.6
+[silencedetect @ 0x7f9e4c00b2c0] silence_start: 20.9
+[silencedetect @ 0x7f9e4c00b2c0] silence_end: 22.3 | silence_duration: 1.4
+[blackdetect @ 0x7f9e4c006a80] black_start:35 black_end:36 black_duration:1
+[silencedetect @ 0x7f9e4c00b2c0] silence_start: 40.2
+[silencedetect @ 0x7f9e4c00b2c0] silence_end: 41 | silence_duration: 0.8
+frame= 1800 fps=950 q=-0.0 Lsize=N/A time=00:01:00.00 bitrate=N/A speed=31.6x
+";
+
+    #[test]
+    fn breaks_are_midpoints_of_black_and_silence_overlaps() {
+        let blacks = parse_blackdetect(BREAK_DETECT_STDERR);
+        assert_eq!(blacks, vec![(0.0, 0.8), (20.4, 22.0), (35.0, 36.0)]);
+        let silences = parse_silencedetect(BREAK_DETECT_STDERR, 60.0);
+
+        // The opening overlap starts at 0 and is kept (its midpoint is 0.3); 20.9-22.0 gives 21.45;
+        // the black at 35s and the silence at 40s never overlap
+        assert_eq!(break_points(&blacks, &silences, 60.0, 0.5), vec![0.3, 21.45]);
+    }
+
+    #[test]
+    fn breaks_without_overlap_are_empty() {
+        let blacks = [(10.0, 11.0), (30.0, 31.0)];
+        let silences = [(11.0, 12.0), (28.0, 29.5)];
+        assert!(break_points(&blacks, &silences, 60.0, 0.5).is_empty());
+    }
+
+    #[test]
+    fn breaks_closer_than_min_duration_are_merged() {
+        // Two silences inside one black stretch give points 10.25 and 10.75
+        let blacks = [(10.0, 11.0), (30.0, 31.0)];
+        let silences = [(10.0, 10.5), (10.5, 11.0), (30.0, 31.0)];
+        assert_eq!(break_points(&blacks, &silences, 60.0, 0.5), vec![10.25, 10.75, 30.5]);
+        assert_eq!(break_points(&blacks, &silences, 60.0, 1.0), vec![10.25, 30.5]);
+    }
+
+    #[test]
+    fn refresh_rate_from_system_profiler() {
+        let output = "\
+Graphics/Displays:
+
+    Apple M2 Pro:
+
+      Chipset Model: Apple M2 Pro
+      Displays:
+        Color LCD:
+          Display Type: Built-in Liquid Retina XDR Display
+          Resolution: 3456 x 2234 Retina
+          Main Display: Yes
+          UI Looks like: 1728 x 1117 @ 120.00Hz
+";
+        assert_eq!(parse_refresh_rate(output), Some(120.0));
+    }
+
+    #[test]
+    fn refresh_rate_from_cim_number() {
+        assert_eq!(parse_refresh_rate("144\r\n"), Some(144.0));
+    }
+
+    #[test]
+    fn refresh_rate_from_xrandr_active_mode() {
+        let output = "\
+Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384
+DP-1 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
+   2560x1440    165.00*+ 143.97   60.00
+   1920x1080     60.00    50.00
+";
+        assert_eq!(parse_refresh_rate(output), Some(165.0));
+    }
+
+    #[test]
+    fn refresh_rate_out_of_range_is_rejected() {
+        assert_eq!(parse_refresh_rate("0"), None);
+        assert_eq!(parse_refresh_rate("UI Looks like: 1728 x 1117 @ 5000.00Hz"), None);
+        assert_eq!(parse_refresh_rate(""), None);
+    }
+
+    // Creates a fresh temp directory holding empty files with the given names
+    fn frame_dir(name: &str, files: &[&str]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("clipforge_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn image_sequence_padded_pattern_needs_at_least_its_width() {
+        let dir = frame_dir(
+            "padded_frames",
+            &["frame_000002.png", "frame_000001.png", "frame_1000000.png", "frame_12.png", "frame_abc.png", "frame_000003.jpg", "other.png"],
+        );
+        let numbers = image_sequence_numbers(&dir, "frame_%06d.png");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(numbers, Ok(vec![1, 2, 1000000]));
+    }
+
+    #[test]
+    fn image_sequence_unpadded_pattern_rejects_leading_zeros() {
+        let dir = frame_dir("unpadded_frames", &["frame_0.png", "frame_1.png", "frame_10.png", "frame_007.png", "frame_.png"]);
+        let numbers = image_sequence_numbers(&dir, "frame_%d.png");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(numbers, Ok(vec![0, 1, 10]));
+    }
+
+    #[test]
+    fn image_sequence_rejects_bad_patterns() {
+        let dir = std::env::temp_dir();
+        for pattern in ["frames/frame_%d.png", "frame_%d\\x.png", "frame_%d/x.png", "frame.png", "frame_%5d.png", "frame_%d_%d.png"] {
+            assert!(image_sequence_numbers(&dir, pattern).is_err(), "{} should be rejected", pattern);
+        }
+    }
+
+    #[test]
+    fn pip_stays_on_screen_in_every_corner() {
+        // A 16x12 white PiP over 64x36 black: every one of its pixels must land in the frame,
+        // at the requested margin and with a margin far larger than the frame
+        for (margin, expected_origin) in [(4, [(4, 4), (44, 4), (4, 20), (44, 20)]), (1000, [(0, 0), (48, 0), (0, 24), (48, 24)])] {
+            for (position, (x, y)) in ["top-left", "top-right", "bottom-left", "bottom-right"].into_iter().zip(expected_origin) {
+                let filter = format!("[0:v][1:v]overlay={}[v]", pip_overlay_position(position, margin));
+                let Ok(output) = Command::new(get_ffmpeg_path())
+                    .args([
+                        "-v", "error",
+                        "-f", "lavfi", "-i", "color=c=black:s=64x36:r=1:d=1",
+                        "-f", "lavfi", "-i", "color=c=white:s=16x12:r=1:d=1",
+                        "-filter_complex", &filter,
+                        "-map", "[v]",
+                        "-frames:v", "1",
+                        "-f", "rawvideo",
+                        "-pix_fmt", "gray",
+                        "-",
+                    ])
+                    .output()
+                else {
+                    eprintln!("ffmpeg not available, skipping");
+                    return;
+                };
+                assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+
+                let frame = output.stdout;
+                let white = frame.iter().filter(|&&p| p > 128).count();
+                assert_eq!(white, 16 * 12, "{} at margin {} is partly off-screen", position, margin);
+                assert!(frame[y * 64 + x] > 128, "{} at margin {} should start at ({}, {})", position, margin, x, y);
+            }
+        }
+    }
+
+    fn styled_pip(border_width: Option<i32>, border_color: Option<&str>, rounded_corners: Option<i32>) -> PipTrackData {
+        PipTrackData {
+            path: "camera.mp4".to_string(),
+            offset: 0.0,
+            duration: 10.0,
+            volume: 1.0,
+            position: "bottom-right".to_string(),
+            size_percent: 25.0,
+            margin: None,
+            border_width,
+            border_color: border_color.map(str::to_string),
+            rounded_corners,
+        }
+    }
+
+    #[test]
+    fn pip_style_is_empty_without_styling() {
+        assert_eq!(pip_style_filter(&styled_pip(None, None, None)), Ok(String::new()));
+        assert_eq!(pip_style_filter(&styled_pip(Some(0), Some("red"), Some(0))), Ok(String::new()));
+    }
+
+    #[test]
+    fn pip_style_rejects_invalid_values() {
+        assert!(pip_style_filter(&styled_pip(Some(-2), None, None)).is_err());
+        assert!(pip_style_filter(&styled_pip(Some(3), None, None)).is_err());
+        assert!(pip_style_filter(&styled_pip(None, None, Some(-1))).is_err());
+        assert!(pip_style_filter(&styled_pip(Some(4), Some("red;drawtext"), None)).is_err());
+    }
+
+    #[test]
+    fn pip_style_pads_the_border_before_rounding_corners() {
+        let filter = pip_style_filter(&styled_pip(Some(4), Some("#ff0000"), Some(12))).unwrap();
+        let pad = filter.find("pad=iw+8:ih+8:4:4:color=#ff0000").expect("border pad");
+        let alpha = filter.find("format=yuva420p").expect("alpha format");
+        let mask = filter.find("geq=").expect("corner mask");
+        assert!(pad < alpha && alpha < mask, "unexpected order: {}", filter);
+        assert!(filter.contains("W/2-12"));
+    }
+
+    #[test]
+    fn hdr_requires_pq_or_hlg_transfer() {
+        assert!(is_hdr_transfer("smpte2084"));
+        assert!(is_hdr_transfer("arib-std-b67\n"));
+        assert!(!is_hdr_transfer("bt709"));
+        assert!(!is_hdr_transfer("bt2020-10"));
+        assert!(!is_hdr_transfer(""));
+    }
+}
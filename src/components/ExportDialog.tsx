@@ -18,6 +18,7 @@ import { VideoResolution, RESOLUTION_OPTIONS } from '../types/recording';
 import { Track } from '../types/clip';
 import { Clip } from '../store/videoStore';
 import ResolutionSelector from './ResolutionSelector';
+import { formatCommandError } from '../utils/commandError';
 
 declare const window: any;
 
@@ -286,7 +287,7 @@ function ExportDialog({
 
     } catch (err: any) {
       console.error('[ExportDialog] Export failed:', err);
-      setError(err.toString());
+      setError(formatCommandError(err));
       setStatus('error');
     }
   };
@@ -34,6 +34,8 @@ import PermissionHelper from './PermissionHelper';
 import CameraPreview from './CameraPreview';
 import ScreenPreview from './ScreenPreview';
 import ResolutionSelector from './ResolutionSelector';
+import { AudioVideoDevices, DeviceInfo } from '../types/recording';
+import { formatCommandError } from '../utils/commandError';
 
 interface RecordingDialogProps {
   open: boolean;
@@ -49,7 +51,7 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
   const [outputPath, setOutputPath] = useState<string | null>(null);
   const [error, setError] = useState<string | null>(null);
   const [showPermissionHelper, setShowPermissionHelper] = useState(false);
-  const [audioDevices, setAudioDevices] = useState<string[]>([]);
+  const [audioDevices, setAudioDevices] = useState<DeviceInfo[]>([]);
   const [selectedAudioDevice, setSelectedAudioDevice] = useState<string>('');
   const [isWindows, setIsWindows] = useState(false);
   const [enableMicrophone, setEnableMicrophone] = useState(false);
@@ -90,13 +92,11 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
 
     const fetchDevices = async () => {
       try {
-        const devices = await invoke<{ video_devices: string[], audio_devices: string[] }>(
-          'list_audio_video_devices'
-        );
+        const devices = await invoke<AudioVideoDevices>('list_audio_video_devices');
         setAudioDevices(devices.audio_devices);
         // Auto-select first device if available
         if (devices.audio_devices.length > 0 && !selectedAudioDevice) {
-          setSelectedAudioDevice(devices.audio_devices[0]);
+          setSelectedAudioDevice(String(devices.audio_devices[0].index));
         }
       } catch (err) {
         console.error('Failed to fetch devices:', err);
@@ -156,13 +156,17 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
 
       // Add audio device for camera recording on Windows
       if (source === 'camera' && isWindows && selectedAudioDevice) {
-        recordingOptions.audio_device = selectedAudioDevice;
+        recordingOptions.audio_device_index = Number(selectedAudioDevice);
       }
 
       // Add audio device for screen recording with microphone enabled
       if (source === 'screen' && enableMicrophone) {
         // For Windows, use the selected device; for macOS, just indicate we want audio (will use default)
-        recordingOptions.audio_device = isWindows ? selectedAudioDevice : 'default';
+        if (isWindows && selectedAudioDevice) {
+          recordingOptions.audio_device_index = Number(selectedAudioDevice);
+        } else {
+          recordingOptions.audio_device = 'default';
+        }
       }
 
       // Start recording based on source
@@ -183,7 +187,7 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
 
     } catch (err: any) {
       console.error('Failed to start recording:', err);
-      setError(err.toString());
+      setError(formatCommandError(err));
     }
   };
 
@@ -236,7 +240,7 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
 
     } catch (err: any) {
       console.error('Failed to stop recording:', err);
-      setError(err.toString());
+      setError(formatCommandError(err));
       setIsRecording(false);
     }
   };
@@ -342,8 +346,8 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
                       onChange={(e) => setSelectedAudioDevice(e.target.value)}
                     >
                       {audioDevices.map((device) => (
-                        <MenuItem key={device} value={device}>
-                          {device}
+                        <MenuItem key={device.index} value={String(device.index)}>
+                          {device.name}
                         </MenuItem>
                       ))}
                     </Select>
@@ -364,8 +368,8 @@ function RecordingDialog({ open, onClose }: RecordingDialogProps) {
                       onChange={(e) => setSelectedAudioDevice(e.target.value)}
                     >
                       {audioDevices.map((device) => (
-                        <MenuItem key={device} value={device}>
-                          {device}
+                        <MenuItem key={device.index} value={String(device.index)}>
+                          {device.name}
                         </MenuItem>
                       ))}
                     </Select>
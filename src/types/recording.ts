@@ -51,9 +51,20 @@ export interface RecordingOptions {
   sourceHeight?: number;
 }
 
+export interface DeviceInfo {
+  index: number;
+  name: string;
+}
+
+export interface AudioVideoDevices {
+  video_devices: DeviceInfo[];
+  audio_devices: DeviceInfo[];
+}
+
 export interface ScreenResolution {
   width: number;
   height: number;
+  refresh_rate: number; // Hz; 30 when detection fails
 }
 
 export interface CameraCapabilities {
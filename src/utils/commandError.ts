@@ -0,0 +1,48 @@
+/**
+ * Command Error Utilities
+ *
+ * Helpers for the structured errors returned by export and recording commands
+ */
+
+export type CommandErrorCode =
+  | 'ffmpeg_missing'
+  | 'spawn_failed'
+  | 'non_zero_exit'
+  | 'invalid_input'
+  | 'cancelled'
+  | 'output_empty'
+  | 'device_busy'
+  | 'permission_denied'
+  | 'failed';
+
+export interface CommandError {
+  code: CommandErrorCode;
+  message: string;
+  detail: string | null;
+  device: string | null; // Set for device_busy / permission_denied
+}
+
+/**
+ * Check whether a rejected invoke() value is a structured command error
+ * @param err - Value caught from invoke()
+ */
+export function isCommandError(err: unknown): err is CommandError {
+  return typeof err === 'object' && err !== null && 'code' in err && 'message' in err;
+}
+
+/**
+ * Turn a rejected invoke() value into a user-facing message
+ * Commands that still return plain strings are passed through unchanged
+ * @param err - Value caught from invoke()
+ * @returns Message suitable for display
+ */
+export function formatCommandError(err: unknown): string {
+  if (isCommandError(err)) {
+    // The message already names the device and what to do; the FFmpeg log would only add noise
+    if (err.code === 'device_busy' || err.code === 'permission_denied') {
+      return err.message;
+    }
+    return err.detail ? `${err.message}: ${err.detail}` : err.message;
+  }
+  return String(err);
+}